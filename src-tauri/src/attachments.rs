@@ -0,0 +1,339 @@
+// Content-addressed blob store for files embedded in notes. Blobs live under
+// `<app data>/attachments/<first two hex chars>/<sha256>` and notes reference
+// them as `attachment:<sha256>`. The webview resolves references to URLs of the
+// protocol handler registered in lib.rs, whose form differs per platform, and
+// saved content is turned back into references.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use sha2::{Digest, Sha256};
+use sqlx::{SqliteConnection, SqlitePool};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::http::{header, Response, StatusCode};
+use tauri::{AppHandle, Manager};
+
+use crate::db::Database;
+
+pub const URL_SCHEME: &str = "silto-attachment";
+pub const REFERENCE_PREFIX: &str = "attachment:";
+// Windows and Android webviews serve custom schemes over http(s).
+#[cfg(any(windows, target_os = "android"))]
+const URL_PREFIX: &str = "http://silto-attachment.localhost/";
+#[cfg(not(any(windows, target_os = "android")))]
+const URL_PREFIX: &str = "silto-attachment://localhost/";
+/// Every URL form the webview may hand back in saved content.
+const URL_PREFIXES: [&str; 3] = [
+    "silto-attachment://localhost/",
+    "http://silto-attachment.localhost/",
+    "https://silto-attachment.localhost/",
+];
+/// Only these are served inline; anything else is sent as a download so the
+/// webview never renders it as a page.
+const INLINE_MIME_TYPES: [&str; 6] = [
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/bmp",
+    "image/avif",
+];
+
+/// Unreferenced blobs younger than this survive garbage collection, so an
+/// attachment added to a note that has not been saved yet is not lost.
+const GC_GRACE_SECS: i64 = 60 * 60;
+
+/// What stored content uses to point at a blob.
+pub fn reference_for(hash: &str) -> String {
+    format!("{}{}", REFERENCE_PREFIX, hash)
+}
+
+/// URL the webview on this platform loads the blob from.
+pub fn url_for(hash: &str) -> String {
+    format!("{}{}", URL_PREFIX, hash)
+}
+
+/// Replaces the attachment URLs in `content` with references.
+pub fn to_references(content: &str) -> String {
+    URL_PREFIXES
+        .iter()
+        .fold(content.to_string(), |content, prefix| {
+            content.replace(prefix, REFERENCE_PREFIX)
+        })
+}
+
+pub fn attachments_dir(app_dir: &Path) -> PathBuf {
+    app_dir.join("attachments")
+}
+
+pub fn app_attachments_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let app_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data dir: {}", e))?;
+    Ok(attachments_dir(&app_dir))
+}
+
+pub fn blob_path(dir: &Path, hash: &str) -> Option<PathBuf> {
+    // Hashes come from URLs too, so only accept what we generate.
+    if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(dir.join(&hash[..2]).join(hash))
+}
+
+/// Writes `bytes` into the store and returns their SHA-256 hex digest.
+pub fn store_blob(dir: &Path, bytes: &[u8]) -> Result<String, String> {
+    let hash = format!("{:x}", Sha256::digest(bytes));
+    let path = blob_path(dir, &hash).ok_or("Invalid attachment hash")?;
+    if path.exists() {
+        return Ok(hash);
+    }
+
+    let parent = path.parent().ok_or("Invalid attachment path")?;
+    fs::create_dir_all(parent).map_err(|e| format!("Failed to create attachment dir: {}", e))?;
+    // Write then rename so a crash never leaves a truncated blob under its hash.
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, bytes).map_err(|e| format!("Failed to write attachment: {}", e))?;
+    fs::rename(&tmp, &path).map_err(|e| format!("Failed to store attachment: {}", e))?;
+    Ok(hash)
+}
+
+pub async fn insert_attachment(
+    conn: &mut SqliteConnection,
+    hash: &str,
+    mime: &str,
+    size: i64,
+) -> Result<(), String> {
+    sqlx::query(
+        "INSERT OR IGNORE INTO attachments (hash, mime, size, created_at) VALUES (?, ?, ?, ?)",
+    )
+    .bind(hash)
+    .bind(mime)
+    .bind(size)
+    .bind(chrono::Utc::now())
+    .execute(conn)
+    .await
+    .map_err(|e| format!("Failed to record attachment: {}", e))?;
+    Ok(())
+}
+
+/// Moves every base64 `data:` URI in `content` into the store and returns the
+/// content with those URIs replaced by attachment references.
+pub async fn extract_inline_data(
+    conn: &mut SqliteConnection,
+    dir: &Path,
+    content: &str,
+) -> Result<String, String> {
+    let mut out = String::with_capacity(content.len());
+    let mut rest = content;
+
+    while let Some(start) = rest.find("data:") {
+        let quote = rest[..start].chars().last();
+        let (before, candidate) = rest.split_at(start);
+        out.push_str(before);
+
+        // Only URIs that are a whole quoted attribute value are rewritten.
+        let end = match quote {
+            Some(q @ ('"' | '\'')) => candidate.find(q),
+            _ => None,
+        };
+        let parsed = end.and_then(|end| {
+            let uri = &candidate[..end];
+            let (header, data) = uri["data:".len()..].split_once(',')?;
+            let mime = header.strip_suffix(";base64")?;
+            let bytes = BASE64.decode(data.trim()).ok()?;
+            Some((end, mime.to_string(), bytes))
+        });
+
+        match parsed {
+            Some((end, mime, bytes)) => {
+                let hash = store_blob(dir, &bytes)?;
+                insert_attachment(conn, &hash, &mime, bytes.len() as i64).await?;
+                out.push_str(&reference_for(&hash));
+                rest = &candidate[end..];
+            }
+            None => {
+                out.push_str("data:");
+                rest = &candidate["data:".len()..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Hashes of the attachment references that appear in `content`.
+pub fn referenced_hashes(content: &str) -> Vec<String> {
+    let mut hashes: Vec<String> = content
+        .match_indices(REFERENCE_PREFIX)
+        .filter_map(|(i, _)| {
+            content.get(i + REFERENCE_PREFIX.len()..i + REFERENCE_PREFIX.len() + 64)
+        })
+        .filter(|hash| hash.bytes().all(|b| b.is_ascii_hexdigit()))
+        .map(str::to_string)
+        .collect();
+    hashes.sort();
+    hashes.dedup();
+    hashes
+}
+
+/// Makes `note_attachments` match the attachment references in the note's content.
+pub async fn sync_note_references(
+    conn: &mut SqliteConnection,
+    note_id: &str,
+    content: &str,
+) -> Result<(), String> {
+    sqlx::query("DELETE FROM note_attachments WHERE note_id = ?")
+        .bind(note_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to clear attachment references: {}", e))?;
+
+    for hash in referenced_hashes(content) {
+        // Only link blobs we actually hold; stray URLs are ignored.
+        sqlx::query(
+            "INSERT OR IGNORE INTO note_attachments (note_id, hash)
+             SELECT ?, hash FROM attachments WHERE hash = ?",
+        )
+        .bind(note_id)
+        .bind(&hash)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to record attachment reference: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Deletes attachments no note (live or trashed) refers to. Returns how many
+/// were removed.
+pub async fn collect_garbage(pool: &SqlitePool, dir: &Path) -> Result<u64, String> {
+    let cutoff = chrono::Utc::now() - chrono::Duration::seconds(GC_GRACE_SECS);
+    // Old revisions may still point at a blob after the note itself moved on.
+    let orphans: Vec<String> = sqlx::query_scalar(
+        "SELECT hash FROM attachments
+         WHERE hash NOT IN (SELECT hash FROM note_attachments) AND created_at <= ?
+         AND NOT EXISTS (SELECT 1 FROM note_revisions WHERE instr(content, attachments.hash) > 0)",
+    )
+    .bind(cutoff)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to find unused attachments: {}", e))?;
+
+    let mut removed = 0;
+    for hash in orphans {
+        sqlx::query("DELETE FROM attachments WHERE hash = ?")
+            .bind(&hash)
+            .execute(pool)
+            .await
+            .map_err(|e| format!("Failed to delete attachment: {}", e))?;
+        if let Some(path) = blob_path(dir, &hash) {
+            if path.exists() {
+                fs::remove_file(&path)
+                    .map_err(|e| format!("Failed to delete attachment file: {}", e))?;
+            }
+        }
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+/// One-off rewrite of notes saved before the store existed.
+pub async fn migrate_inline_images(
+    conn: &mut SqliteConnection,
+    app_dir: &Path,
+) -> Result<(), String> {
+    let dir = attachments_dir(app_dir);
+    let notes: Vec<(String, String)> =
+        sqlx::query_as("SELECT id, content FROM notes WHERE content LIKE '%data:%;base64,%'")
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to load notes with inline images: {}", e))?;
+
+    for (id, content) in notes {
+        let rewritten = extract_inline_data(conn, &dir, &content).await?;
+        sqlx::query("UPDATE notes SET content = ? WHERE id = ?")
+            .bind(&rewritten)
+            .bind(&id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to rewrite note content: {}", e))?;
+        sync_note_references(conn, &id, &rewritten).await?;
+    }
+
+    let revisions: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT id, content FROM note_revisions WHERE content LIKE '%data:%;base64,%'",
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to load revisions with inline images: {}", e))?;
+
+    for (id, content) in revisions {
+        let rewritten = extract_inline_data(conn, &dir, &content).await?;
+        sqlx::query("UPDATE note_revisions SET content = ? WHERE id = ?")
+            .bind(&rewritten)
+            .bind(id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to rewrite revision content: {}", e))?;
+    }
+    Ok(())
+}
+
+fn empty_response(status: StatusCode) -> Response<Vec<u8>> {
+    let mut response = Response::new(Vec::new());
+    *response.status_mut() = status;
+    response
+}
+
+/// Answers a request for `<attachment URL prefix><hash>` with the blob.
+pub async fn serve(app: &AppHandle, path: &str) -> Response<Vec<u8>> {
+    let hash = path.trim_start_matches('/');
+    let (Some(db), Ok(dir)) = (app.try_state::<Database>(), app_attachments_dir(app)) else {
+        return empty_response(StatusCode::SERVICE_UNAVAILABLE);
+    };
+    let Ok(pool) = db.pool() else {
+        return empty_response(StatusCode::SERVICE_UNAVAILABLE);
+    };
+    let Some(path) = blob_path(&dir, hash) else {
+        return empty_response(StatusCode::BAD_REQUEST);
+    };
+
+    let mime: Option<String> = sqlx::query_scalar("SELECT mime FROM attachments WHERE hash = ?")
+        .bind(hash)
+        .fetch_optional(&pool)
+        .await
+        .unwrap_or(None);
+    let (Some(mime), Ok(bytes)) = (mime, fs::read(&path)) else {
+        return empty_response(StatusCode::NOT_FOUND);
+    };
+
+    let mut response = Response::new(bytes);
+    let headers = response.headers_mut();
+    // The stored type is whatever the caller claimed when the blob was added.
+    match mime.parse() {
+        Ok(value) if INLINE_MIME_TYPES.contains(&mime.as_str()) => {
+            headers.insert(header::CONTENT_TYPE, value);
+        }
+        _ => {
+            headers.insert(
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static("application/octet-stream"),
+            );
+            headers.insert(
+                header::CONTENT_DISPOSITION,
+                header::HeaderValue::from_static("attachment"),
+            );
+        }
+    }
+    headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        header::HeaderValue::from_static("nosniff"),
+    );
+    // Blobs are addressed by content, so they never change.
+    response.headers_mut().insert(
+        header::CACHE_CONTROL,
+        header::HeaderValue::from_static("public, max-age=31536000, immutable"),
+    );
+    response
+}
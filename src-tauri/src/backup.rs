@@ -0,0 +1,239 @@
+// Rolling snapshots of silto.db in `<app data>/backups`. Snapshots are taken
+// with `VACUUM INTO`, which gives a consistent copy while the app keeps
+// running; an encrypted database yields copies encrypted with the same key.
+// Files are named `silto-<kind>-<UTC time>.db`, and the name is all the
+// bookkeeping there is.
+
+use crate::db::Database;
+use crate::encryption::DbKey;
+use crate::models::{BackupInfo, BackupKind, RecoveryReport};
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, Utc};
+use sqlx::{ConnectOptions, Connection, SqlitePool};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const BACKUP_DIR: &str = "backups";
+const TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+/// Copies kept of those taken before a migration or a restore.
+const KEEP_SAFETY_COPIES: usize = 3;
+
+impl BackupKind {
+    fn slug(self) -> &'static str {
+        match self {
+            BackupKind::Daily => "daily",
+            BackupKind::Weekly => "weekly",
+            BackupKind::PreMigration => "pre-migration",
+            BackupKind::PreRestore => "pre-restore",
+        }
+    }
+
+    fn from_slug(slug: &str) -> Option<Self> {
+        [
+            BackupKind::Daily,
+            BackupKind::Weekly,
+            BackupKind::PreMigration,
+            BackupKind::PreRestore,
+        ]
+        .into_iter()
+        .find(|kind| kind.slug() == slug)
+    }
+}
+
+pub fn backup_dir(app_dir: &Path) -> PathBuf {
+    app_dir.join(BACKUP_DIR)
+}
+
+/// Reads kind and time back out of a backup file name.
+fn parse_name(name: &str) -> Option<(BackupKind, DateTime<Utc>)> {
+    let stem = name.strip_prefix("silto-")?.strip_suffix(".db")?;
+    // The time is the last two dash-separated parts; kinds may contain dashes.
+    let mut parts = stem.rsplitn(3, '-');
+    let time = parts.next()?;
+    let date = parts.next()?;
+    let kind = BackupKind::from_slug(parts.next()?)?;
+    let created_at =
+        NaiveDateTime::parse_from_str(&format!("{}-{}", date, time), TIME_FORMAT).ok()?;
+    Some((kind, created_at.and_utc()))
+}
+
+/// Every backup on disk, newest first.
+pub fn list(app_dir: &Path) -> Result<Vec<BackupInfo>, String> {
+    let dir = backup_dir(app_dir);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(&dir).map_err(|e| format!("Failed to read backups: {}", e))? {
+        let entry = entry.map_err(|e| format!("Failed to read backups: {}", e))?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some((kind, created_at)) = parse_name(&name) else {
+            continue;
+        };
+        let size = entry
+            .metadata()
+            .map_err(|e| format!("Failed to read backups: {}", e))?
+            .len();
+        backups.push(BackupInfo {
+            name,
+            kind,
+            created_at,
+            size,
+        });
+    }
+    backups.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+    Ok(backups)
+}
+
+/// Writes a snapshot of the database behind `pool`.
+pub async fn snapshot(
+    pool: &SqlitePool,
+    app_dir: &Path,
+    kind: BackupKind,
+) -> Result<BackupInfo, String> {
+    let dir = backup_dir(app_dir);
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create backup dir: {}", e))?;
+
+    let created_at = Utc::now();
+    let name = format!(
+        "silto-{}-{}.db",
+        kind.slug(),
+        created_at.format(TIME_FORMAT)
+    );
+    let path = dir.join(&name);
+    // Written under another name first, so a half-written copy never lists.
+    let partial = dir.join(format!("{}.partial", name));
+    for stale in [&path, &partial] {
+        if stale.exists() {
+            fs::remove_file(stale).map_err(|e| format!("Failed to remove stale backup: {}", e))?;
+        }
+    }
+
+    sqlx::query("VACUUM INTO ?")
+        .bind(partial.to_string_lossy().to_string())
+        .execute(pool)
+        .await
+        .map_err(|e| format!("Failed to back up database: {}", e))?;
+    fs::rename(&partial, &path).map_err(|e| format!("Failed to save backup: {}", e))?;
+
+    let size = fs::metadata(&path)
+        .map_err(|e| format!("Failed to save backup: {}", e))?
+        .len();
+    Ok(BackupInfo {
+        name,
+        kind,
+        created_at,
+        size,
+    })
+}
+
+/// Deletes all but the newest `keep` backups of `kind`.
+fn prune(app_dir: &Path, kind: BackupKind, keep: usize) -> Result<(), String> {
+    let dir = backup_dir(app_dir);
+    for backup in list(app_dir)?
+        .into_iter()
+        .filter(|b| b.kind == kind)
+        .skip(keep)
+    {
+        fs::remove_file(dir.join(&backup.name))
+            .map_err(|e| format!("Failed to remove old backup: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Takes the daily and weekly copies that are due and drops the ones past
+/// `daily_keep` and `weekly_keep`; a limit of 0 turns that kind off. Days and
+/// weeks follow the local calendar.
+pub async fn run_scheduled(
+    pool: &SqlitePool,
+    app_dir: &Path,
+    daily_keep: u32,
+    weekly_keep: u32,
+) -> Result<(), String> {
+    let now = Local::now();
+    let backups = list(app_dir)?;
+    let taken = |kind: BackupKind, same_period: &dyn Fn(DateTime<Local>) -> bool| {
+        backups
+            .iter()
+            .any(|b| b.kind == kind && same_period(b.created_at.with_timezone(&Local)))
+    };
+
+    if daily_keep > 0 && !taken(BackupKind::Daily, &|t| t.date_naive() == now.date_naive()) {
+        snapshot(pool, app_dir, BackupKind::Daily).await?;
+    }
+    if weekly_keep > 0 && !taken(BackupKind::Weekly, &|t| t.iso_week() == now.iso_week()) {
+        snapshot(pool, app_dir, BackupKind::Weekly).await?;
+    }
+
+    prune(app_dir, BackupKind::Daily, daily_keep as usize)?;
+    prune(app_dir, BackupKind::Weekly, weekly_keep as usize)?;
+    prune(app_dir, BackupKind::PreMigration, KEEP_SAFETY_COPIES)?;
+    prune(app_dir, BackupKind::PreRestore, KEEP_SAFETY_COPIES)
+}
+
+/// Whether `path` opens with `key`, passes the integrity check and holds a
+/// migrated schema. SQLite reads a short junk file as an empty database.
+async fn is_restorable(path: &Path, key: Option<&DbKey>) -> bool {
+    let problems = crate::recovery::integrity_problems(path, key).await;
+    if !problems.is_ok_and(|problems| problems.is_empty()) {
+        return false;
+    }
+    let Ok(mut conn) = crate::db::connect_options(path, key)
+        .create_if_missing(false)
+        .read_only(true)
+        .connect()
+        .await
+    else {
+        return false;
+    };
+    let version: Result<Option<i64>, _> =
+        sqlx::query_scalar("SELECT MAX(version) FROM schema_version")
+            .fetch_one(&mut conn)
+            .await;
+    let _ = conn.close().await;
+    matches!(version, Ok(Some(version)) if version > 0)
+}
+
+/// Replaces the database with the backup `name` and reopens the pool on it.
+/// `key` must be the current key of an encrypted database; changing the
+/// passphrase re-keys the backups along with it. The current state is saved as a pre-restore backup first.
+pub async fn restore(
+    db: &Database,
+    name: &str,
+    key: Option<&DbKey>,
+) -> Result<Option<RecoveryReport>, String> {
+    if !list(&db.app_dir)?.iter().any(|b| b.name == name) {
+        return Err(format!("Backup {} not found", name));
+    }
+    let source = backup_dir(&db.app_dir).join(name);
+    if !is_restorable(&source, key).await {
+        return Err("The backup is damaged or was made with a different passphrase".to_string());
+    }
+
+    snapshot(&db.pool()?, &db.app_dir, BackupKind::PreRestore).await?;
+    prune(&db.app_dir, BackupKind::PreRestore, KEEP_SAFETY_COPIES)?;
+
+    // Commands fail with "Database is locked" until the new pool is in place.
+    if let Some(old) = db.replace_pool(None) {
+        old.close().await;
+    }
+    // Reopen whichever file ended up in place, so a failed swap leaves the
+    // current database open.
+    let swapped = swap_in(&source, &db.path);
+    let (pool, recovery) = crate::db::open_checked(&db.path, &db.app_dir, key).await?;
+    db.replace_pool(Some(pool));
+    swapped?;
+    Ok(recovery)
+}
+
+fn swap_in(source: &Path, path: &Path) -> Result<(), String> {
+    let staged = path.with_extension("db.restore");
+    fs::copy(source, &staged).map_err(|e| format!("Failed to copy backup: {}", e))?;
+    for suffix in ["-wal", "-shm"] {
+        let side = PathBuf::from(format!("{}{}", path.display(), suffix));
+        if side.exists() {
+            fs::remove_file(&side)
+                .map_err(|e| format!("Failed to remove {}: {}", side.display(), e))?;
+        }
+    }
+    fs::rename(&staged, path).map_err(|e| format!("Failed to restore backup: {}", e))
+}
@@ -0,0 +1,172 @@
+// Bulk todo operations. A batch runs in a single transaction and is applied
+// all or nothing: every operation is attempted so the caller learns about each
+// failure, and the transaction is only committed if none failed.
+
+use crate::models::{BatchItemResult, BatchOp, BatchResult, BatchStatus, Todo};
+use chrono::{DateTime, Utc};
+use sqlx::{SqliteConnection, SqlitePool};
+
+impl BatchOp {
+    /// The todo the operation applies to.
+    pub fn id(&self) -> &str {
+        match self {
+            BatchOp::Complete { id }
+            | BatchOp::Uncomplete { id }
+            | BatchOp::Delete { id }
+            | BatchOp::Move { id, .. }
+            | BatchOp::Tag { id, .. }
+            | BatchOp::Untag { id, .. } => id,
+        }
+    }
+}
+
+async fn fetch_live_todo(conn: &mut SqliteConnection, id: &str) -> Result<Todo, String> {
+    sqlx::query_as::<_, Todo>("SELECT * FROM todos WHERE id = ? AND deleted_at IS NULL")
+        .bind(id)
+        .fetch_optional(conn)
+        .await
+        .map_err(|e| format!("Failed to fetch todo: {}", e))?
+        .ok_or_else(|| "Todo not found".to_string())
+}
+
+/// Same bookkeeping as `save_todo` for a completion toggle: completing stamps
+/// `completed_at`, reopening clears it and brings the todo out of the archive.
+async fn set_completed(
+    conn: &mut SqliteConnection,
+    mut todo: Todo,
+    completed: bool,
+    now: DateTime<Utc>,
+) -> Result<Option<Todo>, String> {
+    if todo.completed == completed {
+        return Ok(None);
+    }
+    todo.completed = completed;
+    todo.completed_at = completed.then_some(now);
+    todo.archived = todo.archived && completed;
+    todo.updated_at = now;
+
+    sqlx::query(
+        "UPDATE todos SET completed = ?, completed_at = ?, archived = ?, updated_at = ? WHERE id = ?",
+    )
+    .bind(todo.completed)
+    .bind(todo.completed_at)
+    .bind(todo.archived)
+    .bind(todo.updated_at)
+    .bind(&todo.id)
+    .execute(conn)
+    .await
+    .map_err(|e| format!("Failed to update todo: {}", e))?;
+    Ok(Some(todo))
+}
+
+/// Applies one operation. Returns the todo when it was just completed, so a
+/// recurring one can schedule its next occurrence after the commit.
+async fn apply_op(
+    conn: &mut SqliteConnection,
+    op: &BatchOp,
+    now: DateTime<Utc>,
+) -> Result<Option<Todo>, String> {
+    let todo = fetch_live_todo(&mut *conn, op.id()).await?;
+
+    match op {
+        BatchOp::Complete { .. } => set_completed(conn, todo, true, now).await,
+        BatchOp::Uncomplete { .. } => {
+            set_completed(conn, todo, false, now).await?;
+            Ok(None)
+        }
+        BatchOp::Delete { id } => {
+            sqlx::query(&format!(
+                "{} UPDATE todos SET deleted_at = ? WHERE id IN (SELECT id FROM subtree) AND deleted_at IS NULL",
+                crate::subtasks::SUBTREE_CTE
+            ))
+            .bind(id)
+            .bind(now)
+            .execute(conn)
+            .await
+            .map_err(|e| format!("Failed to delete todo: {}", e))?;
+            Ok(None)
+        }
+        BatchOp::Move { id, list_id } => {
+            sqlx::query("UPDATE todos SET list_id = ?, updated_at = ? WHERE id = ?")
+                .bind(list_id)
+                .bind(now)
+                .bind(id)
+                .execute(conn)
+                .await
+                .map_err(|e| format!("Failed to move todo: {}", e))?;
+            Ok(None)
+        }
+        BatchOp::Tag { id, tag_id } => {
+            sqlx::query("INSERT OR IGNORE INTO todo_tags (todo_id, tag_id) VALUES (?, ?)")
+                .bind(id)
+                .bind(tag_id)
+                .execute(conn)
+                .await
+                .map_err(|e| format!("Failed to tag todo: {}", e))?;
+            Ok(None)
+        }
+        BatchOp::Untag { id, tag_id } => {
+            sqlx::query("DELETE FROM todo_tags WHERE todo_id = ? AND tag_id = ?")
+                .bind(id)
+                .bind(tag_id)
+                .execute(conn)
+                .await
+                .map_err(|e| format!("Failed to untag todo: {}", e))?;
+            Ok(None)
+        }
+    }
+}
+
+/// Runs `ops`; also returns the ids of next occurrences created for completed
+/// recurring todos.
+pub async fn apply(
+    pool: &SqlitePool,
+    ops: Vec<BatchOp>,
+) -> Result<(BatchResult, Vec<String>), String> {
+    let now = Utc::now();
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| format!("Failed to begin transaction: {}", e))?;
+
+    let mut items = Vec::with_capacity(ops.len());
+    let mut completed = Vec::new();
+    for op in &ops {
+        let (status, error) = match apply_op(&mut tx, op, now).await {
+            Ok(todo) => {
+                completed.extend(todo);
+                (BatchStatus::Applied, None)
+            }
+            Err(e) => (BatchStatus::Failed, Some(e)),
+        };
+        items.push(BatchItemResult {
+            id: op.id().to_string(),
+            status,
+            error,
+        });
+    }
+
+    let committed = items.iter().all(|item| item.status == BatchStatus::Applied);
+    if !committed {
+        tx.rollback()
+            .await
+            .map_err(|e| format!("Failed to roll back batch: {}", e))?;
+        for item in &mut items {
+            if item.status == BatchStatus::Applied {
+                item.status = BatchStatus::RolledBack;
+            }
+        }
+        return Ok((BatchResult { committed, items }, Vec::new()));
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("Failed to commit batch: {}", e))?;
+
+    // spawn_next runs its own transaction, so it has to wait for the commit.
+    let mut spawned = Vec::new();
+    for todo in completed.iter().filter(|todo| todo.recurrence.is_some()) {
+        spawned.extend(crate::recurrence::spawn_next(pool, todo).await?);
+    }
+    Ok((BatchResult { committed, items }, spawned))
+}
@@ -1,22 +1,179 @@
 use crate::db::Database;
-use crate::models::{Note, Settings, Todo};
+use crate::journal::{Journal, Pending, Target};
+use crate::models::{
+    Attachment, AttachmentData, BackupInfo, BatchOp, BatchResult, DiffLine, EncryptionStatus,
+    ExportFormat, ExportReport, ImportReport, ImportStrategy, JournalStatus, ListDeleteMode, Note,
+    NoteDateField, NoteFilter, NoteRevision, NoteSort, NoteSortField, Page, RecoveryReport,
+    SaveError, SearchHit, Settings, SortDirection, Statistics, StatsPeriod, Tag, Todo,
+    TodoDateField, TodoFilter, TodoList, TodoSort, TodoSortField, Trash, UnresolvedLink,
+    INBOX_LIST_ID,
+};
+use crate::paging::{Cursor, SortValue};
+use sqlx::{QueryBuilder, Sqlite};
 use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_dialog::DialogExt;
 use tauri_plugin_store::StoreExt;
 
 const SETTINGS_STORE_KEY: &str = "settings";
 
+/// Open todos past their due moment; an all-day todo is overdue once its day has ended.
+fn push_overdue_filter(builder: &mut QueryBuilder<'_, Sqlite>, now: chrono::DateTime<chrono::Utc>) {
+    builder.push(" AND completed = 0 AND due_at IS NOT NULL AND ((due_all_day = 0 AND due_at <= ");
+    builder.push_bind(now);
+    builder.push(") OR (due_all_day = 1 AND due_at <= ");
+    builder.push_bind(now - chrono::Duration::days(1));
+    builder.push("))");
+}
+
+fn todo_sort_column(sort: TodoSort) -> (&'static str, SortDirection) {
+    let column = match sort.field {
+        TodoSortField::CreatedAt => "created_at",
+        TodoSortField::DueAt => "due_at",
+        TodoSortField::Priority => "priority",
+        TodoSortField::RemindTime => "remind_time",
+        TodoSortField::Title => "title COLLATE NOCASE",
+    };
+    (column, sort.direction)
+}
+
+fn todo_sort_value(todo: &Todo, field: TodoSortField) -> Option<SortValue> {
+    match field {
+        TodoSortField::CreatedAt => Some(SortValue::Date(todo.created_at)),
+        TodoSortField::DueAt => todo.due_at.map(SortValue::Date),
+        TodoSortField::Priority => Some(SortValue::Int(todo.priority as i64)),
+        TodoSortField::RemindTime => todo.remind_time.map(SortValue::Date),
+        TodoSortField::Title => Some(SortValue::Text(todo.title.clone())),
+    }
+}
+
+/// Conditions shared by a todo page and its total count.
+fn push_todo_filters(builder: &mut QueryBuilder<'_, Sqlite>, filter: &TodoFilter) {
+    crate::tags::push_tag_filter(builder, crate::tags::TODO_TAGS, &filter.tag_ids, filter.tag_match);
+    if let Some(list_id) = &filter.list_id {
+        if list_id == INBOX_LIST_ID {
+            builder.push(" AND (list_id IS NULL OR list_id = ");
+            builder.push_bind(list_id.clone());
+            builder.push(")");
+        } else {
+            builder.push(" AND list_id = ");
+            builder.push_bind(list_id.clone());
+        }
+    }
+    if filter.overdue {
+        push_overdue_filter(builder, chrono::Utc::now());
+    }
+    if let Some(priority) = filter.min_priority {
+        builder.push(" AND priority >= ");
+        builder.push_bind(priority);
+    }
+    if !filter.include_archived {
+        builder.push(" AND archived = 0");
+    }
+    if let Some(completed) = filter.completed {
+        builder.push(" AND completed = ");
+        builder.push_bind(completed);
+    }
+    if let Some(text) = &filter.text {
+        crate::search::push_text_filter(builder, "todos", text);
+    }
+
+    let date_column = match filter.date_field {
+        TodoDateField::CreatedAt => "created_at",
+        TodoDateField::DueAt => "due_at",
+        TodoDateField::RemindTime => "remind_time",
+        TodoDateField::CompletedAt => "completed_at",
+    };
+    if let Some(from) = filter.from {
+        builder.push(format!(" AND {} >= ", date_column));
+        builder.push_bind(from);
+    }
+    if let Some(to) = filter.to {
+        builder.push(format!(" AND {} < ", date_column));
+        builder.push_bind(to);
+    }
+}
+
 #[tauri::command]
-pub async fn get_todos(app: AppHandle) -> Result<Vec<Todo>, String> {
+pub async fn get_todos(app: AppHandle, filter: Option<TodoFilter>) -> Result<Page<Todo>, String> {
     let db: State<Database> = app.state();
-    let todos = sqlx::query_as::<_, Todo>("SELECT * FROM todos ORDER BY created_at DESC")
-        .fetch_all(&db.pool)
+    let filter = filter.unwrap_or_default();
+    let sort = filter.sort.map(todo_sort_column);
+
+    let mut count = QueryBuilder::<Sqlite>::new("SELECT COUNT(*) FROM todos WHERE deleted_at IS NULL");
+    push_todo_filters(&mut count, &filter);
+    let total: i64 = count
+        .build_query_scalar()
+        .fetch_one(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to count todos: {}", e))?;
+
+    let mut builder = QueryBuilder::<Sqlite>::new("SELECT * FROM todos WHERE deleted_at IS NULL");
+    push_todo_filters(&mut builder, &filter);
+    if let Some(cursor) = &filter.cursor {
+        crate::paging::push_after(&mut builder, sort, "created_at", &Cursor::decode(cursor)?);
+    }
+    crate::paging::push_order(&mut builder, sort, "created_at");
+    crate::paging::push_limit(&mut builder, filter.limit);
+
+    let mut todos = builder
+        .build_query_as::<Todo>()
+        .fetch_all(&db.pool()?)
         .await
         .map_err(|e| format!("Failed to fetch todos: {}", e))?;
-    Ok(todos)
+    let next_cursor = crate::paging::finish_page(&mut todos, filter.limit, |todo| Cursor {
+        sort: filter.sort.and_then(|sort| todo_sort_value(todo, sort.field)),
+        tiebreak: todo.created_at,
+        id: todo.id.clone(),
+    });
+
+    let ids: Vec<String> = todos.iter().map(|t| t.id.clone()).collect();
+    let mut tag_ids = crate::tags::load_tag_ids(&db.pool()?, crate::tags::TODO_TAGS, &ids).await?;
+    let progress = crate::subtasks::load_progress(&db.pool()?, &ids).await?;
+    for todo in &mut todos {
+        todo.tag_ids = tag_ids.remove(&todo.id).unwrap_or_default();
+        todo.progress = progress.get(&todo.id).copied();
+    }
+
+    if filter.tree {
+        todos = crate::subtasks::build_tree(todos);
+    }
+    Ok(Page {
+        items: todos,
+        total,
+        next_cursor,
+    })
 }
 
+/// Saves a todo and returns it with its new `updated_at`. A todo that was
+/// saved elsewhere since the caller loaded it comes back as a conflict.
 #[tauri::command]
-pub async fn save_todo(app: AppHandle, todo: Todo) -> Result<(), String> {
+pub async fn save_todo(
+    app: AppHandle,
+    todo: Todo,
+    cascade: Option<bool>,
+) -> Result<Todo, SaveError<Todo>> {
+    let db: State<Database> = app.state();
+    let targets = if cascade.unwrap_or(false) {
+        subtree_targets(&db.pool()?, &todo.id).await?
+    } else {
+        vec![Target::Todo(todo.id.clone())]
+    };
+    let mut pending = journal_begin(&app, "Save todo", targets).await?;
+    let (todo, spawned) = store_todo(&app, todo, cascade).await?;
+    if let Some(id) = spawned {
+        pending.created(Target::Todo(id));
+    }
+    journal_record(&app, pending).await?;
+    Ok(todo)
+}
+
+/// `save_todo` without the journal. Also returns the id of the next
+/// occurrence when completing a recurring todo created one.
+async fn store_todo(
+    app: &AppHandle,
+    todo: Todo,
+    cascade: Option<bool>,
+) -> Result<(Todo, Option<String>), SaveError<Todo>> {
     let db: State<Database> = app.state();
     
     // Store DateTime as string (rfc3339) or rely on sqlx implementation if supported
@@ -28,10 +185,18 @@ pub async fn save_todo(app: AppHandle, todo: Todo) -> Result<(), String> {
     let mut todo = todo;
     let existing = sqlx::query_as::<_, Todo>("SELECT * FROM todos WHERE id = ?")
         .bind(&todo.id)
-        .fetch_optional(&db.pool)
+        .fetch_optional(&db.pool()?)
         .await
         .map_err(|e| format!("Failed to fetch existing todo: {}", e))?;
 
+    if let Some(parent_id) = &todo.parent_id {
+        crate::subtasks::ensure_valid_parent(&db.pool()?, &todo.id, parent_id).await?;
+    }
+    crate::recurrence::prepare_series(&mut todo)?;
+    let completion_changed = existing
+        .as_ref()
+        .is_some_and(|existing_todo| existing_todo.completed != todo.completed);
+
     if let Some(existing_todo) = existing {
         if existing_todo.remind_time == todo.remind_time {
             todo.notified = existing_todo.notified;
@@ -43,76 +208,1562 @@ pub async fn save_todo(app: AppHandle, todo: Todo) -> Result<(), String> {
         } else {
             todo.notified = existing_todo.notified;
         }
+
+        // Same rule for the reminder derived from the due date.
+        if existing_todo.due_remind_at() == todo.due_remind_at() {
+            todo.due_notified = existing_todo.due_notified;
+        } else if todo
+            .due_remind_at()
+            .is_some_and(|remind_at| remind_at > chrono::Utc::now())
+        {
+            todo.due_notified = false;
+        } else {
+            todo.due_notified = existing_todo.due_notified;
+        }
+
+        // Reopening a todo also brings it back from the archive.
+        if existing_todo.completed == todo.completed {
+            todo.completed_at = existing_todo.completed_at;
+            todo.archived = existing_todo.archived;
+        } else {
+            todo.completed_at = todo.completed.then(chrono::Utc::now);
+            todo.archived = existing_todo.archived && todo.completed;
+        }
     } else {
         todo.notified = false;
+        todo.due_notified = false;
+        todo.completed_at = todo.completed.then(chrono::Utc::now);
     }
 
-    sqlx::query(
-        "INSERT OR REPLACE INTO todos (id, title, content, remind_time, completed, created_at, notified) VALUES (?, ?, ?, ?, ?, ?, ?)"
+    // The caller's `updated_at` is the version it edited; the update only
+    // goes through if nobody has saved the todo since.
+    let base_version = todo.updated_at;
+    todo.updated_at = chrono::Utc::now();
+    let saved = sqlx::query(
+        "INSERT INTO todos (id, title, content, remind_time, completed, created_at, notified, search_text, list_id, parent_id,
+         due_at, due_all_day, priority, remind_before_due_minutes, due_remind_at, due_notified,
+         recurrence, series_id, recurrence_index, occurrence_at, next_occurrence_at, completed_at, archived, updated_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT(id) DO UPDATE SET title = excluded.title, content = excluded.content, remind_time = excluded.remind_time,
+         completed = excluded.completed, created_at = excluded.created_at, notified = excluded.notified, search_text = excluded.search_text,
+         list_id = excluded.list_id, parent_id = excluded.parent_id, due_at = excluded.due_at, due_all_day = excluded.due_all_day,
+         priority = excluded.priority, remind_before_due_minutes = excluded.remind_before_due_minutes,
+         due_remind_at = excluded.due_remind_at, due_notified = excluded.due_notified, recurrence = excluded.recurrence,
+         series_id = excluded.series_id, recurrence_index = excluded.recurrence_index, occurrence_at = excluded.occurrence_at,
+         next_occurrence_at = CASE WHEN todos.handed_over THEN NULL ELSE excluded.next_occurrence_at END,
+         completed_at = excluded.completed_at, archived = excluded.archived, updated_at = excluded.updated_at
+         WHERE todos.updated_at = ?"
     )
-    .bind(todo.id)
-    .bind(todo.title)
-    .bind(todo.content)
+    .bind(&todo.id)
+    .bind(&todo.title)
+    .bind(&todo.content)
     .bind(todo.remind_time)
     .bind(todo.completed)
     .bind(todo.created_at)
     .bind(todo.notified)
-    .execute(&db.pool)
+    .bind(crate::html::to_plain_text(&todo.content))
+    .bind(&todo.list_id)
+    .bind(&todo.parent_id)
+    .bind(todo.due_at)
+    .bind(todo.due_all_day)
+    .bind(todo.priority)
+    .bind(todo.remind_before_due_minutes)
+    .bind(todo.due_remind_at())
+    .bind(todo.due_notified)
+    .bind(&todo.recurrence)
+    .bind(&todo.series_id)
+    .bind(todo.recurrence_index)
+    .bind(todo.occurrence_at)
+    .bind(crate::recurrence::next_occurrence(&todo))
+    .bind(todo.completed_at)
+    .bind(todo.archived)
+    .bind(todo.updated_at)
+    .bind(base_version)
+    .execute(&db.pool()?)
     .await
     .map_err(|e| format!("Failed to save todo: {}", e))?;
+    if saved.rows_affected() == 0 {
+        let current = current_todo(&db.pool()?, &todo.id).await?;
+        return Err(SaveError::Conflict { current });
+    }
 
-    Ok(())
+    // Optionally carry a completion toggle down to every subtask.
+    if completion_changed && cascade.unwrap_or(false) {
+        sqlx::query(&format!(
+            "{} UPDATE todos SET completed = ?, completed_at = ?, archived = archived AND ?, updated_at = ?
+             WHERE id IN (SELECT id FROM subtree) AND id != ? AND completed != ?",
+            crate::subtasks::SUBTREE_CTE
+        ))
+        .bind(&todo.id)
+        .bind(todo.completed)
+        .bind(todo.completed_at)
+        .bind(todo.completed)
+        .bind(todo.updated_at)
+        .bind(&todo.id)
+        .bind(todo.completed)
+        .execute(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to update subtasks: {}", e))?;
+    }
+
+    // Finishing an occurrence of a recurring todo schedules the next one.
+    let mut spawned = None;
+    if completion_changed && todo.completed && todo.recurrence.is_some() {
+        spawned = crate::recurrence::spawn_next(&db.pool()?, &todo).await?;
+    }
+
+    Ok((todo, spawned))
+}
+
+/// The stored copy of a todo, with its tags, for a conflict error.
+async fn current_todo(pool: &sqlx::SqlitePool, id: &str) -> Result<Todo, String> {
+    let mut todo = sqlx::query_as::<_, Todo>("SELECT * FROM todos WHERE id = ?")
+        .bind(id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| format!("Failed to fetch todo: {}", e))?;
+    todo.tag_ids = crate::tags::load_tag_ids(pool, crate::tags::TODO_TAGS, &[todo.id.clone()])
+        .await?
+        .remove(&todo.id)
+        .unwrap_or_default();
+    Ok(todo)
 }
 
+/// Applies several todo operations in one transaction. If any of them fails
+/// none are applied; the result reports the outcome of each.
 #[tauri::command]
-pub async fn delete_todo(app: AppHandle, id: String) -> Result<(), String> {
+pub async fn apply_todo_batch(app: AppHandle, ops: Vec<BatchOp>) -> Result<BatchResult, String> {
+    let db: State<Database> = app.state();
+    let mut targets = Vec::new();
+    for op in &ops {
+        let op_targets = match op {
+            BatchOp::Delete { id } => subtree_targets(&db.pool()?, id).await?,
+            _ => vec![Target::Todo(op.id().to_string())],
+        };
+        for target in op_targets {
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
+    }
+
+    let mut pending = journal_begin(&app, "Edit todos", targets).await?;
+    let (result, spawned) = crate::batch::apply(&db.pool()?, ops).await?;
+    if result.committed {
+        for id in spawned {
+            pending.created(Target::Todo(id));
+        }
+        journal_record(&app, pending).await?;
+    }
+    Ok(result)
+}
+
+/// Moves a todo into or out of the archive. Archived todos keep their
+/// completion state and are only listed when `include_archived` is set.
+#[tauri::command]
+pub async fn set_todo_archived(app: AppHandle, id: String, archived: bool) -> Result<(), String> {
     let db: State<Database> = app.state();
-    sqlx::query("DELETE FROM todos WHERE id = ?")
+    let pending = journal_begin(&app, "Archive todo", vec![Target::Todo(id.clone())]).await?;
+    sqlx::query("UPDATE todos SET archived = ?, updated_at = ? WHERE id = ?")
+        .bind(archived)
+        .bind(chrono::Utc::now())
         .bind(id)
-        .execute(&db.pool)
+        .execute(&db.pool()?)
         .await
-        .map_err(|e| format!("Failed to delete todo: {}", e))?;
+        .map_err(|e| format!("Failed to archive todo: {}", e))?;
+    journal_record(&app, pending).await
+}
+
+#[tauri::command]
+pub async fn delete_todo(app: AppHandle, id: String) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    let targets = subtree_targets(&db.pool()?, &id).await?;
+    let pending = journal_begin(&app, "Delete todo", targets).await?;
+    trash_todo(&db.pool()?, &id).await?;
+    journal_record(&app, pending).await
+}
+
+async fn trash_todo(pool: &sqlx::SqlitePool, id: &str) -> Result<(), String> {
+    // Subtasks go to the trash together with their parent.
+    sqlx::query(&format!(
+        "{} UPDATE todos SET deleted_at = ? WHERE id IN (SELECT id FROM subtree) AND deleted_at IS NULL",
+        crate::subtasks::SUBTREE_CTE
+    ))
+    .bind(id)
+    .bind(chrono::Utc::now())
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Failed to delete todo: {}", e))?;
     Ok(())
 }
 
+/// Skips one occurrence of a recurring todo: the next occurrence is created
+/// and this one goes to the trash.
+#[tauri::command]
+pub async fn skip_occurrence(app: AppHandle, id: String) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    let todo = sqlx::query_as::<_, Todo>("SELECT * FROM todos WHERE id = ? AND deleted_at IS NULL")
+        .bind(&id)
+        .fetch_optional(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to fetch todo: {}", e))?
+        .ok_or("Todo not found")?;
+
+    if todo.recurrence.is_none() {
+        return Err("Only recurring todos can skip an occurrence".to_string());
+    }
+
+    let targets = subtree_targets(&db.pool()?, &id).await?;
+    let mut pending = journal_begin(&app, "Skip occurrence", targets).await?;
+    if let Some(next_id) = crate::recurrence::spawn_next(&db.pool()?, &todo).await? {
+        pending.created(Target::Todo(next_id));
+    }
+    trash_todo(&db.pool()?, &id).await?;
+    journal_record(&app, pending).await
+}
+
+fn note_sort_column(sort: NoteSort) -> (&'static str, SortDirection) {
+    let column = match sort.field {
+        NoteSortField::CreatedAt => "created_at",
+        NoteSortField::UpdatedAt => "updated_at",
+        NoteSortField::Title => "title COLLATE NOCASE",
+    };
+    (column, sort.direction)
+}
+
+/// Conditions shared by a note page and its total count.
+fn push_note_filters(builder: &mut QueryBuilder<'_, Sqlite>, filter: &NoteFilter) {
+    crate::tags::push_tag_filter(builder, crate::tags::NOTE_TAGS, &filter.tag_ids, filter.tag_match);
+    if let Some(text) = &filter.text {
+        crate::search::push_text_filter(builder, "notes", text);
+    }
+
+    let date_column = match filter.date_field {
+        NoteDateField::CreatedAt => "created_at",
+        NoteDateField::UpdatedAt => "updated_at",
+    };
+    if let Some(from) = filter.from {
+        builder.push(format!(" AND {} >= ", date_column));
+        builder.push_bind(from);
+    }
+    if let Some(to) = filter.to {
+        builder.push(format!(" AND {} < ", date_column));
+        builder.push_bind(to);
+    }
+}
+
 #[tauri::command]
-pub async fn get_notes(app: AppHandle) -> Result<Vec<Note>, String> {
+pub async fn get_notes(app: AppHandle, filter: Option<NoteFilter>) -> Result<Page<Note>, String> {
     let db: State<Database> = app.state();
-    let notes = sqlx::query_as::<_, Note>("SELECT * FROM notes ORDER BY updated_at DESC")
-        .fetch_all(&db.pool)
+    let filter = filter.unwrap_or_default();
+    let sort = filter.sort.map(note_sort_column);
+
+    let mut count = QueryBuilder::<Sqlite>::new("SELECT COUNT(*) FROM notes WHERE deleted_at IS NULL");
+    push_note_filters(&mut count, &filter);
+    let total: i64 = count
+        .build_query_scalar()
+        .fetch_one(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to count notes: {}", e))?;
+
+    let mut builder = QueryBuilder::<Sqlite>::new("SELECT * FROM notes WHERE deleted_at IS NULL");
+    push_note_filters(&mut builder, &filter);
+    if let Some(cursor) = &filter.cursor {
+        crate::paging::push_after(&mut builder, sort, "updated_at", &Cursor::decode(cursor)?);
+    }
+    crate::paging::push_order(&mut builder, sort, "updated_at");
+    crate::paging::push_limit(&mut builder, filter.limit);
+
+    let mut notes = builder
+        .build_query_as::<Note>()
+        .fetch_all(&db.pool()?)
         .await
         .map_err(|e| format!("Failed to fetch notes: {}", e))?;
-    Ok(notes)
+    let next_cursor = crate::paging::finish_page(&mut notes, filter.limit, |note| Cursor {
+        sort: filter.sort.map(|sort| match sort.field {
+            NoteSortField::CreatedAt => SortValue::Date(note.created_at),
+            NoteSortField::UpdatedAt => SortValue::Date(note.updated_at),
+            NoteSortField::Title => SortValue::Text(note.title.clone()),
+        }),
+        tiebreak: note.updated_at,
+        id: note.id.clone(),
+    });
+
+    let ids: Vec<String> = notes.iter().map(|n| n.id.clone()).collect();
+    let mut tag_ids = crate::tags::load_tag_ids(&db.pool()?, crate::tags::NOTE_TAGS, &ids).await?;
+    for note in &mut notes {
+        note.tag_ids = tag_ids.remove(&note.id).unwrap_or_default();
+    }
+    Ok(Page {
+        items: notes,
+        total,
+        next_cursor,
+    })
 }
 
+/// Saves a note and returns it with its new `updated_at`. When the title
+/// changes and `rewrite_links` is true, other notes' `[[Old Title]]` links are
+/// rewritten to the new title.
 #[tauri::command]
-pub async fn save_note(app: AppHandle, note: Note) -> Result<(), String> {
+pub async fn save_note(
+    app: AppHandle,
+    note: Note,
+    rewrite_links: Option<bool>,
+) -> Result<Note, SaveError<Note>> {
     let db: State<Database> = app.state();
-    sqlx::query(
-        "INSERT OR REPLACE INTO notes (id, title, content, created_at, updated_at) VALUES (?, ?, ?, ?, ?)"
+    let mut targets = vec![Target::Note(note.id.clone())];
+    if rewrite_links.unwrap_or(false) {
+        // Notes whose links a rename would rewrite.
+        let linkers: Vec<String> = sqlx::query_scalar(
+            "SELECT source_id FROM note_links
+             WHERE target_title = (SELECT title FROM notes WHERE id = ?) AND source_id != ?",
+        )
+        .bind(&note.id)
+        .bind(&note.id)
+        .fetch_all(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to fetch linking notes: {}", e))?;
+        targets.extend(linkers.into_iter().map(Target::Note));
+    }
+
+    let pending = journal_begin(&app, "Save note", targets).await?;
+    let note = store_note(&app, note, rewrite_links).await?;
+    journal_record(&app, pending).await?;
+    Ok(note)
+}
+
+/// `save_note` without the journal.
+async fn store_note(
+    app: &AppHandle,
+    mut note: Note,
+    rewrite_links: Option<bool>,
+) -> Result<Note, SaveError<Note>> {
+    let db: State<Database> = app.state();
+    let dir = crate::attachments::app_attachments_dir(app)?;
+    let mut conn = db.pool()?.acquire().await.map_err(|e| format!("Failed to acquire connection: {}", e))?;
+    // The editor hands back the URLs the content was rendered with.
+    note.content = crate::attachments::to_references(&note.content);
+
+    if crate::note_lock::is_locked(&mut conn, &note.id).await? {
+        return save_locked_note(app, &mut conn, note, rewrite_links.unwrap_or(false)).await;
+    }
+
+    // Pasted images arrive as data: URIs; keep them out of the notes table.
+    note.content = crate::attachments::extract_inline_data(&mut conn, &dir, &note.content).await?;
+
+    let old_title: Option<String> = sqlx::query_scalar("SELECT title FROM notes WHERE id = ?")
+        .bind(&note.id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to fetch note: {}", e))?;
+    let renamed_from = old_title.filter(|old| rewrite_links.unwrap_or(false) && *old != note.title);
+    if let Some(old) = &renamed_from {
+        note.content = crate::links::rewrite_links(&note.content, old, &note.title);
+    }
+
+    // Same version check as `save_todo`.
+    let base_version = note.updated_at;
+    note.updated_at = chrono::Utc::now();
+    let saved = sqlx::query(
+        "INSERT INTO notes (id, title, content, created_at, updated_at, search_text) VALUES (?, ?, ?, ?, ?, ?)
+         ON CONFLICT(id) DO UPDATE SET title = excluded.title, content = excluded.content,
+         created_at = excluded.created_at, updated_at = excluded.updated_at, search_text = excluded.search_text
+         WHERE notes.updated_at = ?"
     )
-    .bind(note.id)
-    .bind(note.title)
-    .bind(note.content)
+    .bind(&note.id)
+    .bind(&note.title)
+    .bind(&note.content)
     .bind(note.created_at)
     .bind(note.updated_at)
-    .execute(&db.pool)
+    .bind(crate::html::to_plain_text(&note.content))
+    .bind(base_version)
+    .execute(&mut *conn)
     .await
     .map_err(|e| format!("Failed to save note: {}", e))?;
+    if saved.rows_affected() == 0 {
+        let current = current_note(&mut conn, &note.id).await?;
+        return Err(SaveError::Conflict { current });
+    }
+
+    crate::attachments::sync_note_references(&mut conn, &note.id, &note.content).await?;
+    crate::links::sync_note_links(&mut conn, &note.id, &note.content).await?;
+    drop(conn);
+    crate::revisions::record_revision(&db.pool()?, &note, false).await?;
+
+    if let Some(old) = renamed_from {
+        crate::links::rename_links(&db.pool()?, &note.id, &old, &note.title).await?;
+    }
+    Ok(note)
+}
+
+/// The stored copy of a note, with its tags, for a conflict error.
+async fn current_note(conn: &mut sqlx::SqliteConnection, id: &str) -> Result<Note, String> {
+    let mut note = sqlx::query_as::<_, Note>("SELECT * FROM notes WHERE id = ?")
+        .bind(id)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to fetch note: {}", e))?;
+    let tag_ids: Vec<String> = sqlx::query_scalar("SELECT tag_id FROM note_tags WHERE note_id = ?")
+        .bind(id)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to fetch note tags: {}", e))?;
+    note.tag_ids = tag_ids;
+    Ok(note)
+}
+
+/// Saves a password-protected note: its content is sealed with the session
+/// key and kept out of search, links and revision history.
+async fn save_locked_note(
+    app: &AppHandle,
+    conn: &mut sqlx::SqliteConnection,
+    mut note: Note,
+    rewrite_links: bool,
+) -> Result<Note, SaveError<Note>> {
+    let unlocked: State<crate::note_lock::UnlockedNotes> = app.state();
+    let sealed = crate::note_lock::seal_content(&unlocked, &note.id, &note.content)?;
+
+    let old_title: String = sqlx::query_scalar("SELECT title FROM notes WHERE id = ?")
+        .bind(&note.id)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to fetch note: {}", e))?;
+    let base_version = note.updated_at;
+    note.updated_at = chrono::Utc::now();
+    let saved = sqlx::query(
+        "UPDATE notes SET title = ?, locked_content = ?, updated_at = ? WHERE id = ? AND updated_at = ?",
+    )
+    .bind(&note.title)
+    .bind(sealed)
+    .bind(note.updated_at)
+    .bind(&note.id)
+    .bind(base_version)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to save note: {}", e))?;
+    if saved.rows_affected() == 0 {
+        let current = current_note(conn, &note.id).await?;
+        return Err(SaveError::Conflict { current });
+    }
+
+    if rewrite_links && old_title != note.title {
+        let db: State<Database> = app.state();
+        crate::links::rename_links(&db.pool()?, &note.id, &old_title, &note.title).await?;
+    }
+    Ok(note)
+}
+
+/// Protects a note with its own password. The note comes back locked.
+#[tauri::command]
+pub async fn set_note_password(app: AppHandle, id: String, password: String) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    let mut conn = db.pool()?.acquire().await.map_err(|e| format!("Failed to acquire connection: {}", e))?;
+    crate::note_lock::set_password(&mut conn, &id, &password).await?;
+    // Earlier snapshots hold the plaintext.
+    let journal: State<Journal> = app.state();
+    journal.forget(&Target::Note(id));
+    Ok(())
+}
+
+/// Decrypts a locked note for this session. The returned note carries the
+/// plaintext content; listings keep showing it empty.
+#[tauri::command]
+pub async fn unlock_note(app: AppHandle, id: String, password: String) -> Result<Note, String> {
+    let db: State<Database> = app.state();
+    let unlocked: State<crate::note_lock::UnlockedNotes> = app.state();
+    let mut conn = db.pool()?.acquire().await.map_err(|e| format!("Failed to acquire connection: {}", e))?;
+
+    let content = crate::note_lock::unlock(&mut conn, &unlocked, &id, &password).await?;
+    let mut note = sqlx::query_as::<_, Note>("SELECT * FROM notes WHERE id = ?")
+        .bind(&id)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to fetch note: {}", e))?;
+    note.content = content;
+    Ok(note)
+}
+
+/// Forgets a note's session key; it has to be unlocked again to be edited.
+#[tauri::command]
+pub async fn lock_note(app: AppHandle, id: String) -> Result<(), String> {
+    let unlocked: State<crate::note_lock::UnlockedNotes> = app.state();
+    unlocked.forget(&id);
     Ok(())
 }
 
+/// Removes a note's password, storing its content in the clear again.
+#[tauri::command]
+pub async fn remove_note_password(app: AppHandle, id: String, password: String) -> Result<Note, String> {
+    let db: State<Database> = app.state();
+    let unlocked: State<crate::note_lock::UnlockedNotes> = app.state();
+    let mut conn = db.pool()?.acquire().await.map_err(|e| format!("Failed to acquire connection: {}", e))?;
+
+    let content = crate::note_lock::remove_password(&mut conn, &unlocked, &id, &password).await?;
+    crate::attachments::sync_note_references(&mut conn, &id, &content).await?;
+    crate::links::sync_note_links(&mut conn, &id, &content).await?;
+    let note = sqlx::query_as::<_, Note>("SELECT * FROM notes WHERE id = ?")
+        .bind(&id)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to fetch note: {}", e))?;
+    drop(conn);
+    crate::revisions::record_revision(&db.pool()?, &note, true).await?;
+    let journal: State<Journal> = app.state();
+    journal.forget(&Target::Note(id));
+    Ok(note)
+}
+
+/// Live notes containing a `[[...]]` link to the note `note_id`.
+#[tauri::command]
+pub async fn get_backlinks(app: AppHandle, note_id: String) -> Result<Vec<Note>, String> {
+    let db: State<Database> = app.state();
+    let notes = sqlx::query_as::<_, Note>(
+        "SELECT * FROM notes WHERE deleted_at IS NULL AND id != ? AND id IN (
+            SELECT l.source_id FROM note_links l JOIN notes target ON l.target_title = target.title
+            WHERE target.id = ?
+        ) ORDER BY updated_at DESC",
+    )
+    .bind(&note_id)
+    .bind(&note_id)
+    .fetch_all(&db.pool()?)
+    .await
+    .map_err(|e| format!("Failed to fetch backlinks: {}", e))?;
+    Ok(notes)
+}
+
+/// Links in live notes whose target title matches no live note.
+#[tauri::command]
+pub async fn get_unresolved_links(app: AppHandle) -> Result<Vec<UnresolvedLink>, String> {
+    let db: State<Database> = app.state();
+    let links = sqlx::query_as::<_, UnresolvedLink>(
+        "SELECT n.id AS source_id, n.title AS source_title, l.target_title
+         FROM note_links l JOIN notes n ON n.id = l.source_id
+         WHERE n.deleted_at IS NULL AND NOT EXISTS (
+             SELECT 1 FROM notes target WHERE target.deleted_at IS NULL AND l.target_title = target.title
+         )
+         ORDER BY l.target_title, n.title",
+    )
+    .fetch_all(&db.pool()?)
+    .await
+    .map_err(|e| format!("Failed to fetch unresolved links: {}", e))?;
+    Ok(links)
+}
+
+#[tauri::command]
+pub async fn get_note_revisions(app: AppHandle, note_id: String) -> Result<Vec<NoteRevision>, String> {
+    let db: State<Database> = app.state();
+    let revisions = sqlx::query_as::<_, NoteRevision>(
+        "SELECT * FROM note_revisions WHERE note_id = ? ORDER BY id DESC",
+    )
+    .bind(note_id)
+    .fetch_all(&db.pool()?)
+    .await
+    .map_err(|e| format!("Failed to fetch revisions: {}", e))?;
+    Ok(revisions)
+}
+
+async fn fetch_revision(db: &Database, id: i64) -> Result<NoteRevision, String> {
+    sqlx::query_as::<_, NoteRevision>("SELECT * FROM note_revisions WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to fetch revision: {}", e))?
+        .ok_or_else(|| "Revision not found".to_string())
+}
+
+/// Line diff between the plain text of two revisions; the title is the first line.
+#[tauri::command]
+pub async fn diff_note_revisions(app: AppHandle, from_id: i64, to_id: i64) -> Result<Vec<DiffLine>, String> {
+    let db: State<Database> = app.state();
+    let from = fetch_revision(&db, from_id).await?;
+    let to = fetch_revision(&db, to_id).await?;
+
+    let text = |r: &NoteRevision| format!("{}\n{}", r.title, crate::html::to_plain_text(&r.content));
+    Ok(crate::revisions::diff_lines(&text(&from), &text(&to)))
+}
+
+/// Puts a revision's title and content back into its note. The restore is
+/// itself recorded as a new revision, so it can be undone the same way.
+#[tauri::command]
+pub async fn restore_note_revision(app: AppHandle, revision_id: i64) -> Result<Note, String> {
+    let db: State<Database> = app.state();
+    let revision = fetch_revision(&db, revision_id).await?;
+    let pending = journal_begin(&app, "Restore revision", vec![Target::Note(revision.note_id.clone())]).await?;
+
+    // Protected notes keep no history; never write plaintext over their content.
+    let restored = sqlx::query("UPDATE notes SET title = ?, content = ?, search_text = ?, updated_at = ? WHERE id = ? AND locked = 0")
+        .bind(&revision.title)
+        .bind(&revision.content)
+        .bind(crate::html::to_plain_text(&revision.content))
+        .bind(chrono::Utc::now())
+        .bind(&revision.note_id)
+        .execute(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to restore revision: {}", e))?;
+    if restored.rows_affected() == 0 {
+        return Err("Note is locked".to_string());
+    }
+
+    let note = sqlx::query_as::<_, Note>("SELECT * FROM notes WHERE id = ?")
+        .bind(&revision.note_id)
+        .fetch_one(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to fetch note: {}", e))?;
+
+    let mut conn = db.pool()?.acquire().await.map_err(|e| format!("Failed to acquire connection: {}", e))?;
+    crate::attachments::sync_note_references(&mut conn, &note.id, &note.content).await?;
+    crate::links::sync_note_links(&mut conn, &note.id, &note.content).await?;
+    drop(conn);
+    crate::revisions::record_revision(&db.pool()?, &note, true).await?;
+    journal_record(&app, pending).await?;
+    Ok(note)
+}
+
+/// Stores a file in the attachment store and, given `note_id`, links it to
+/// that note right away. Insert the returned `url` into the note's content.
+#[tauri::command]
+pub async fn add_attachment(
+    app: AppHandle,
+    note_id: Option<String>,
+    mime: String,
+    data_base64: String,
+) -> Result<Attachment, String> {
+    use base64::Engine;
+
+    let db: State<Database> = app.state();
+    let dir = crate::attachments::app_attachments_dir(&app)?;
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data_base64.trim())
+        .map_err(|e| format!("Invalid attachment data: {}", e))?;
+
+    let hash = crate::attachments::store_blob(&dir, &bytes)?;
+    let mut conn = db.pool()?.acquire().await.map_err(|e| format!("Failed to acquire connection: {}", e))?;
+    crate::attachments::insert_attachment(&mut conn, &hash, &mime, bytes.len() as i64).await?;
+
+    if let Some(note_id) = note_id {
+        // The note may not be saved yet; its next save links the blob instead.
+        sqlx::query(
+            "INSERT OR IGNORE INTO note_attachments (note_id, hash)
+             SELECT id, ? FROM notes WHERE id = ?",
+        )
+        .bind(&hash)
+        .bind(&note_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to link attachment: {}", e))?;
+    }
+
+    let mut attachment = sqlx::query_as::<_, Attachment>("SELECT * FROM attachments WHERE hash = ?")
+        .bind(&hash)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to fetch attachment: {}", e))?;
+    attachment.url = crate::attachments::url_for(&attachment.hash);
+    Ok(attachment)
+}
+
+#[tauri::command]
+pub async fn get_attachment(app: AppHandle, hash: String) -> Result<AttachmentData, String> {
+    use base64::Engine;
+
+    let db: State<Database> = app.state();
+    let dir = crate::attachments::app_attachments_dir(&app)?;
+    let mime: String = sqlx::query_scalar("SELECT mime FROM attachments WHERE hash = ?")
+        .bind(&hash)
+        .fetch_optional(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to fetch attachment: {}", e))?
+        .ok_or_else(|| "Attachment not found".to_string())?;
+
+    let path = crate::attachments::blob_path(&dir, &hash).ok_or("Invalid attachment hash")?;
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read attachment: {}", e))?;
+    Ok(AttachmentData {
+        mime,
+        data_base64: base64::engine::general_purpose::STANDARD.encode(bytes),
+    })
+}
+
+/// Removes attachments no longer referenced by any note. Returns how many went.
+#[tauri::command]
+pub async fn gc_attachments(app: AppHandle) -> Result<u64, String> {
+    let db: State<Database> = app.state();
+    let dir = crate::attachments::app_attachments_dir(&app)?;
+    crate::attachments::collect_garbage(&db.pool()?, &dir).await
+}
+
+/// Relates a todo and a note. Linking an already linked pair is a no-op.
+#[tauri::command]
+pub async fn link_todo_note(app: AppHandle, todo_id: String, note_id: String) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    let pending = journal_begin(&app, "Link note", vec![Target::Todo(todo_id.clone())]).await?;
+    insert_todo_note(&db.pool()?, &todo_id, &note_id).await?;
+    journal_record(&app, pending).await
+}
+
+async fn insert_todo_note(pool: &sqlx::SqlitePool, todo_id: &str, note_id: &str) -> Result<(), String> {
+    sqlx::query("INSERT OR IGNORE INTO todo_notes (todo_id, note_id, created_at) VALUES (?, ?, ?)")
+        .bind(todo_id)
+        .bind(note_id)
+        .bind(chrono::Utc::now())
+        .execute(pool)
+        .await
+        .map_err(|e| format!("Failed to link todo and note: {}", e))?;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn unlink_todo_note(app: AppHandle, todo_id: String, note_id: String) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    let pending = journal_begin(&app, "Unlink note", vec![Target::Todo(todo_id.clone())]).await?;
+    sqlx::query("DELETE FROM todo_notes WHERE todo_id = ? AND note_id = ?")
+        .bind(todo_id)
+        .bind(note_id)
+        .execute(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to unlink todo and note: {}", e))?;
+    journal_record(&app, pending).await
+}
+
+/// Live notes linked to a todo. Links to trashed items are kept so a restore
+/// brings them back, and go away with the item when it is purged.
+#[tauri::command]
+pub async fn get_todo_notes(app: AppHandle, todo_id: String) -> Result<Vec<Note>, String> {
+    let db: State<Database> = app.state();
+    let notes = sqlx::query_as::<_, Note>(
+        "SELECT n.* FROM notes n JOIN todo_notes l ON l.note_id = n.id
+         WHERE l.todo_id = ? AND n.deleted_at IS NULL ORDER BY l.created_at",
+    )
+    .bind(todo_id)
+    .fetch_all(&db.pool()?)
+    .await
+    .map_err(|e| format!("Failed to fetch linked notes: {}", e))?;
+    Ok(notes)
+}
+
+/// Live todos linked to a note, see `get_todo_notes`.
+#[tauri::command]
+pub async fn get_note_todos(app: AppHandle, note_id: String) -> Result<Vec<Todo>, String> {
+    let db: State<Database> = app.state();
+    let todos = sqlx::query_as::<_, Todo>(
+        "SELECT t.* FROM todos t JOIN todo_notes l ON l.todo_id = t.id
+         WHERE l.note_id = ? AND t.deleted_at IS NULL ORDER BY l.created_at",
+    )
+    .bind(note_id)
+    .fetch_all(&db.pool()?)
+    .await
+    .map_err(|e| format!("Failed to fetch linked todos: {}", e))?;
+    Ok(todos)
+}
+
+/// Turns text selected in a note into a new Inbox todo linked to that note.
+/// The first line becomes the title; a longer selection is kept as content.
+#[tauri::command]
+pub async fn create_todo_from_selection(app: AppHandle, note_id: String, text: String) -> Result<Todo, String> {
+    let db: State<Database> = app.state();
+    let note_exists: Option<String> =
+        sqlx::query_scalar("SELECT id FROM notes WHERE id = ? AND deleted_at IS NULL")
+            .bind(&note_id)
+            .fetch_optional(&db.pool()?)
+            .await
+            .map_err(|e| format!("Failed to fetch note: {}", e))?;
+    if note_exists.is_none() {
+        return Err("Note not found".to_string());
+    }
+
+    // The first line becomes the title and the rest, if any, the content.
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+    let title = lines.next().ok_or("Selection is empty")?.to_string();
+    let content = crate::html::from_plain_text(&lines.collect::<Vec<_>>().join("\n"));
+
+    let todo = Todo::new(title, content);
+    let mut pending = journal_begin(&app, "Create todo", Vec::new()).await?;
+    pending.created(Target::Todo(todo.id.clone()));
+    let (todo, _) = store_todo(&app, todo, None).await.map_err(|e| e.to_string())?;
+    insert_todo_note(&db.pool()?, &todo.id, &note_id).await?;
+    journal_record(&app, pending).await?;
+    Ok(todo)
+}
+
 #[tauri::command]
 pub async fn delete_note(app: AppHandle, id: String) -> Result<(), String> {
     let db: State<Database> = app.state();
-    sqlx::query("DELETE FROM notes WHERE id = ?")
+    let pending = journal_begin(&app, "Delete note", vec![Target::Note(id.clone())]).await?;
+    sqlx::query("UPDATE notes SET deleted_at = ? WHERE id = ? AND deleted_at IS NULL")
+        .bind(chrono::Utc::now())
         .bind(id)
-        .execute(&db.pool)
+        .execute(&db.pool()?)
         .await
         .map_err(|e| format!("Failed to delete note: {}", e))?;
+    journal_record(&app, pending).await
+}
+
+#[tauri::command]
+pub async fn get_trash(app: AppHandle) -> Result<Trash, String> {
+    let db: State<Database> = app.state();
+    let todos = sqlx::query_as::<_, Todo>(
+        "SELECT * FROM todos WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC",
+    )
+    .fetch_all(&db.pool()?)
+    .await
+    .map_err(|e| format!("Failed to fetch trashed todos: {}", e))?;
+    let notes = sqlx::query_as::<_, Note>(
+        "SELECT * FROM notes WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC",
+    )
+    .fetch_all(&db.pool()?)
+    .await
+    .map_err(|e| format!("Failed to fetch trashed notes: {}", e))?;
+    Ok(Trash { todos, notes })
+}
+
+#[tauri::command]
+pub async fn restore_todo(app: AppHandle, id: String) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    // Compared as stored text so the match is exact.
+    let deleted_at: Option<String> =
+        sqlx::query_scalar("SELECT deleted_at FROM todos WHERE id = ?")
+            .bind(&id)
+            .fetch_optional(&db.pool()?)
+            .await
+            .map_err(|e| format!("Failed to fetch todo: {}", e))?
+            .flatten();
+    let Some(deleted_at) = deleted_at else {
+        return Ok(());
+    };
+    let targets = subtree_targets(&db.pool()?, &id).await?;
+    let pending = journal_begin(&app, "Restore todo", targets).await?;
+
+    // Bring back the subtasks that were trashed in the same delete.
+    sqlx::query(&format!(
+        "{} UPDATE todos SET deleted_at = NULL WHERE id IN (SELECT id FROM subtree) AND deleted_at = ?",
+        crate::subtasks::SUBTREE_CTE
+    ))
+    .bind(&id)
+    .bind(deleted_at)
+    .execute(&db.pool()?)
+    .await
+    .map_err(|e| format!("Failed to restore todo: {}", e))?;
+    journal_record(&app, pending).await
+}
+
+#[tauri::command]
+pub async fn restore_note(app: AppHandle, id: String) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    let trashed: Option<bool> =
+        sqlx::query_scalar("SELECT deleted_at IS NOT NULL FROM notes WHERE id = ?")
+            .bind(&id)
+            .fetch_optional(&db.pool()?)
+            .await
+            .map_err(|e| format!("Failed to fetch note: {}", e))?;
+    if trashed != Some(true) {
+        return Ok(());
+    }
+    let pending = journal_begin(&app, "Restore note", vec![Target::Note(id.clone())]).await?;
+    sqlx::query("UPDATE notes SET deleted_at = NULL WHERE id = ?")
+        .bind(id)
+        .execute(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to restore note: {}", e))?;
+    journal_record(&app, pending).await
+}
+
+#[tauri::command]
+pub async fn purge_todo(app: AppHandle, id: String) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    let mut tx = db
+        .pool()?
+        .begin()
+        .await
+        .map_err(|e| format!("Failed to begin transaction: {}", e))?;
+    let purged: Vec<String> = sqlx::query_scalar(&format!(
+        "{} SELECT id FROM trashed",
+        crate::subtasks::TRASHED_SUBTREE_CTE
+    ))
+    .bind(&id)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| format!("Failed to fetch subtasks: {}", e))?;
+    if purged.is_empty() {
+        return Err("Todo is not in the trash".to_string());
+    }
+
+    // Live subtasks become top-level todos instead of following their parent
+    // through the cascade on `parent_id`.
+    sqlx::query(&format!(
+        "{} UPDATE todos SET parent_id = NULL
+         WHERE deleted_at IS NULL AND parent_id IN (SELECT id FROM trashed)",
+        crate::subtasks::TRASHED_SUBTREE_CTE
+    ))
+    .bind(&id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| format!("Failed to detach subtasks: {}", e))?;
+    sqlx::query(&format!(
+        "{} DELETE FROM todos WHERE id IN (SELECT id FROM trashed)",
+        crate::subtasks::TRASHED_SUBTREE_CTE
+    ))
+    .bind(&id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| format!("Failed to purge todo: {}", e))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("Failed to commit purge: {}", e))?;
+    // Purging cannot be undone, and older entries must not bring the todo back.
+    let journal: State<Journal> = app.state();
+    for id in purged {
+        journal.forget(&Target::Todo(id));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn purge_note(app: AppHandle, id: String) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    let purged = sqlx::query("DELETE FROM notes WHERE id = ? AND deleted_at IS NOT NULL")
+        .bind(&id)
+        .execute(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to purge note: {}", e))?;
+    if purged.rows_affected() == 0 {
+        return Err("Note is not in the trash".to_string());
+    }
+    let journal: State<Journal> = app.state();
+    journal.forget(&Target::Note(id));
     Ok(())
 }
 
+#[tauri::command]
+pub async fn empty_trash(app: AppHandle) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    let journal: State<Journal> = app.state();
+    journal.clear();
+    crate::maintenance::purge_trash_before(&db.pool()?, chrono::Utc::now()).await
+}
+
+#[tauri::command]
+pub async fn search(app: AppHandle, query: String, limit: Option<i64>) -> Result<Vec<SearchHit>, String> {
+    let db: State<Database> = app.state();
+    crate::search::search(&db.pool()?, &query, limit.unwrap_or(50)).await
+}
+
+/// Todos created and completed per day, week or month (local time) between
+/// `from` and `to`, which default to all time up to now.
+#[tauri::command]
+pub async fn get_statistics(
+    app: AppHandle,
+    period: Option<StatsPeriod>,
+    from: Option<chrono::DateTime<chrono::Utc>>,
+    to: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<Statistics, String> {
+    let db: State<Database> = app.state();
+    let pool = db.pool()?;
+    let now = chrono::Utc::now();
+    let from = from.unwrap_or(chrono::DateTime::UNIX_EPOCH);
+    let to = to.unwrap_or(now);
+
+    let buckets = crate::stats::buckets(&pool, period.unwrap_or_default(), from, to).await?;
+    let avg_completion_seconds = crate::stats::avg_completion_seconds(&pool, from, to).await?;
+
+    let mut builder = QueryBuilder::<Sqlite>::new(
+        "SELECT COUNT(*) FROM todos WHERE deleted_at IS NULL AND archived = 0",
+    );
+    push_overdue_filter(&mut builder, now);
+    let overdue: i64 = builder
+        .build_query_scalar()
+        .fetch_one(&pool)
+        .await
+        .map_err(|e| format!("Failed to count overdue todos: {}", e))?;
+
+    Ok(Statistics {
+        buckets,
+        avg_completion_seconds,
+        overdue,
+    })
+}
+
+#[tauri::command]
+pub async fn get_lists(app: AppHandle, include_archived: Option<bool>) -> Result<Vec<TodoList>, String> {
+    let db: State<Database> = app.state();
+    let sql = if include_archived.unwrap_or(false) {
+        "SELECT * FROM todo_lists ORDER BY sort_order, created_at"
+    } else {
+        "SELECT * FROM todo_lists WHERE archived = 0 ORDER BY sort_order, created_at"
+    };
+    let lists = sqlx::query_as::<_, TodoList>(sql)
+        .fetch_all(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to fetch lists: {}", e))?;
+    Ok(lists)
+}
+
+#[tauri::command]
+pub async fn save_list(app: AppHandle, list: TodoList) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    if list.id == INBOX_LIST_ID && list.archived {
+        return Err("The Inbox cannot be archived".to_string());
+    }
+    let pending = journal_begin(&app, "Save list", vec![Target::List(list.id.clone())]).await?;
+
+    sqlx::query(
+        "INSERT INTO todo_lists (id, name, color, sort_order, archived, created_at) VALUES (?, ?, ?, ?, ?, ?)
+         ON CONFLICT(id) DO UPDATE SET name = excluded.name, color = excluded.color,
+         sort_order = excluded.sort_order, archived = excluded.archived"
+    )
+    .bind(list.id)
+    .bind(list.name)
+    .bind(list.color)
+    .bind(list.sort_order)
+    .bind(list.archived)
+    .bind(list.created_at)
+    .execute(&db.pool()?)
+    .await
+    .map_err(|e| format!("Failed to save list: {}", e))?;
+    journal_record(&app, pending).await
+}
+
+/// Rewrites `sort_order` so the lists appear in the given order.
+#[tauri::command]
+pub async fn reorder_lists(app: AppHandle, ids: Vec<String>) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    let targets = ids.iter().cloned().map(Target::List).collect();
+    let pending = journal_begin(&app, "Reorder lists", targets).await?;
+    let mut tx = db
+        .pool()?
+        .begin()
+        .await
+        .map_err(|e| format!("Failed to begin transaction: {}", e))?;
+
+    for (index, id) in ids.into_iter().enumerate() {
+        sqlx::query("UPDATE todo_lists SET sort_order = ? WHERE id = ?")
+            .bind(index as i64)
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to reorder lists: {}", e))?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("Failed to commit list order: {}", e))?;
+    journal_record(&app, pending).await
+}
+
+#[tauri::command]
+pub async fn delete_list(app: AppHandle, id: String, mode: ListDeleteMode) -> Result<(), String> {
+    if id == INBOX_LIST_ID {
+        return Err("The Inbox cannot be deleted".to_string());
+    }
+    let db: State<Database> = app.state();
+    let todo_ids: Vec<String> = sqlx::query_scalar("SELECT id FROM todos WHERE list_id = ?")
+        .bind(&id)
+        .fetch_all(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to fetch list todos: {}", e))?;
+    let mut targets = vec![Target::List(id.clone())];
+    targets.extend(todo_ids.into_iter().map(Target::Todo));
+    let pending = journal_begin(&app, "Delete list", targets).await?;
+    let mut tx = db
+        .pool()?
+        .begin()
+        .await
+        .map_err(|e| format!("Failed to begin transaction: {}", e))?;
+
+    match mode {
+        ListDeleteMode::MoveToInbox => {
+            sqlx::query("UPDATE todos SET list_id = ?, updated_at = ? WHERE list_id = ?")
+                .bind(INBOX_LIST_ID)
+                .bind(chrono::Utc::now())
+                .bind(&id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("Failed to move todos to the Inbox: {}", e))?;
+        }
+        ListDeleteMode::Trash => {
+            // The foreign key clears list_id, so restored todos land in the Inbox.
+            sqlx::query("UPDATE todos SET deleted_at = ? WHERE list_id = ? AND deleted_at IS NULL")
+                .bind(chrono::Utc::now())
+                .bind(&id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("Failed to trash list todos: {}", e))?;
+        }
+    }
+
+    sqlx::query("DELETE FROM todo_lists WHERE id = ?")
+        .bind(&id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to delete list: {}", e))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("Failed to commit list deletion: {}", e))?;
+    journal_record(&app, pending).await
+}
+
+#[tauri::command]
+pub async fn get_tags(app: AppHandle) -> Result<Vec<Tag>, String> {
+    let db: State<Database> = app.state();
+    let tags = sqlx::query_as::<_, Tag>("SELECT * FROM tags ORDER BY name COLLATE NOCASE")
+        .fetch_all(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to fetch tags: {}", e))?;
+    Ok(tags)
+}
+
+#[tauri::command]
+pub async fn create_tag(app: AppHandle, name: String) -> Result<Tag, String> {
+    let db: State<Database> = app.state();
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err("Tag name cannot be empty".to_string());
+    }
+
+    // Names are unique ignoring case, so creating an existing tag just returns it.
+    let existing = sqlx::query_as::<_, Tag>("SELECT * FROM tags WHERE name = ?")
+        .bind(&name)
+        .fetch_optional(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to fetch tag: {}", e))?;
+    if let Some(tag) = existing {
+        return Ok(tag);
+    }
+
+    let tag = Tag {
+        id: uuid::Uuid::new_v4().to_string(),
+        name,
+        created_at: chrono::Utc::now(),
+    };
+    let mut pending = journal_begin(&app, "Create tag", Vec::new()).await?;
+    pending.created(Target::Tag(tag.id.clone()));
+    sqlx::query("INSERT INTO tags (id, name, created_at) VALUES (?, ?, ?)")
+        .bind(&tag.id)
+        .bind(&tag.name)
+        .bind(tag.created_at)
+        .execute(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to create tag: {}", e))?;
+    journal_record(&app, pending).await?;
+    Ok(tag)
+}
+
+#[tauri::command]
+pub async fn rename_tag(app: AppHandle, id: String, name: String) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err("Tag name cannot be empty".to_string());
+    }
+
+    let taken: Option<String> = sqlx::query_scalar("SELECT id FROM tags WHERE name = ? AND id != ?")
+        .bind(&name)
+        .bind(&id)
+        .fetch_optional(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to fetch tag: {}", e))?;
+    if taken.is_some() {
+        return Err(format!("A tag named \"{}\" already exists; merge the tags instead", name));
+    }
+    let pending = journal_begin(&app, "Rename tag", vec![Target::Tag(id.clone())]).await?;
+
+    sqlx::query("UPDATE tags SET name = ? WHERE id = ?")
+        .bind(name)
+        .bind(id)
+        .execute(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to rename tag: {}", e))?;
+    journal_record(&app, pending).await
+}
+
+/// Moves every item tagged `source_id` over to `target_id` and deletes the source tag.
+#[tauri::command]
+pub async fn merge_tags(app: AppHandle, source_id: String, target_id: String) -> Result<(), String> {
+    if source_id == target_id {
+        return Ok(());
+    }
+    let db: State<Database> = app.state();
+    let targets = vec![Target::Tag(source_id.clone()), Target::Tag(target_id.clone())];
+    let pending = journal_begin(&app, "Merge tags", targets).await?;
+    let mut tx = db
+        .pool()?
+        .begin()
+        .await
+        .map_err(|e| format!("Failed to begin transaction: {}", e))?;
+
+    sqlx::query("INSERT OR IGNORE INTO todo_tags (todo_id, tag_id) SELECT todo_id, ? FROM todo_tags WHERE tag_id = ?")
+        .bind(&target_id)
+        .bind(&source_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to merge todo tags: {}", e))?;
+
+    sqlx::query("INSERT OR IGNORE INTO note_tags (note_id, tag_id) SELECT note_id, ? FROM note_tags WHERE tag_id = ?")
+        .bind(&target_id)
+        .bind(&source_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to merge note tags: {}", e))?;
+
+    sqlx::query("DELETE FROM tags WHERE id = ?")
+        .bind(&source_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to delete merged tag: {}", e))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("Failed to commit tag merge: {}", e))?;
+    journal_record(&app, pending).await
+}
+
+#[tauri::command]
+pub async fn delete_tag(app: AppHandle, id: String) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    let pending = journal_begin(&app, "Delete tag", vec![Target::Tag(id.clone())]).await?;
+    sqlx::query("DELETE FROM tags WHERE id = ?")
+        .bind(id)
+        .execute(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to delete tag: {}", e))?;
+    journal_record(&app, pending).await
+}
+
+#[tauri::command]
+pub async fn add_todo_tag(app: AppHandle, todo_id: String, tag_id: String) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    let pending = journal_begin(&app, "Tag todo", vec![Target::Todo(todo_id.clone())]).await?;
+    sqlx::query("INSERT OR IGNORE INTO todo_tags (todo_id, tag_id) VALUES (?, ?)")
+        .bind(todo_id)
+        .bind(tag_id)
+        .execute(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to tag todo: {}", e))?;
+    journal_record(&app, pending).await
+}
+
+#[tauri::command]
+pub async fn remove_todo_tag(app: AppHandle, todo_id: String, tag_id: String) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    let pending = journal_begin(&app, "Untag todo", vec![Target::Todo(todo_id.clone())]).await?;
+    sqlx::query("DELETE FROM todo_tags WHERE todo_id = ? AND tag_id = ?")
+        .bind(todo_id)
+        .bind(tag_id)
+        .execute(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to untag todo: {}", e))?;
+    journal_record(&app, pending).await
+}
+
+#[tauri::command]
+pub async fn add_note_tag(app: AppHandle, note_id: String, tag_id: String) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    let pending = journal_begin(&app, "Tag note", vec![Target::Note(note_id.clone())]).await?;
+    sqlx::query("INSERT OR IGNORE INTO note_tags (note_id, tag_id) VALUES (?, ?)")
+        .bind(note_id)
+        .bind(tag_id)
+        .execute(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to tag note: {}", e))?;
+    journal_record(&app, pending).await
+}
+
+#[tauri::command]
+pub async fn remove_note_tag(app: AppHandle, note_id: String, tag_id: String) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    let pending = journal_begin(&app, "Untag note", vec![Target::Note(note_id.clone())]).await?;
+    sqlx::query("DELETE FROM note_tags WHERE note_id = ? AND tag_id = ?")
+        .bind(note_id)
+        .bind(tag_id)
+        .execute(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to untag note: {}", e))?;
+    journal_record(&app, pending).await
+}
+
+/// Snapshots `targets` for the undo journal ahead of a change.
+async fn journal_begin(app: &AppHandle, label: &str, targets: Vec<Target>) -> Result<Pending, String> {
+    let db: State<Database> = app.state();
+    crate::journal::begin(&db.pool()?, label, targets).await
+}
+
+async fn journal_record(app: &AppHandle, pending: Pending) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    let journal: State<Journal> = app.state();
+    pending.record(&db.pool()?, &journal).await
+}
+
+/// A todo and all of its subtasks.
+async fn subtree_targets(pool: &sqlx::SqlitePool, id: &str) -> Result<Vec<Target>, String> {
+    let ids: Vec<String> = sqlx::query_scalar(&format!("{} SELECT id FROM subtree", crate::subtasks::SUBTREE_CTE))
+        .bind(id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to fetch subtasks: {}", e))?;
+    Ok(ids.into_iter().map(Target::Todo).collect())
+}
+
+/// Reverts the most recent change made from any window and tells every
+/// window to reload. Returns what was undone, or `None` if nothing was.
+#[tauri::command]
+pub async fn undo(app: AppHandle) -> Result<Option<String>, String> {
+    let db: State<Database> = app.state();
+    let journal: State<Journal> = app.state();
+    let label = crate::journal::undo(&db.pool()?, &journal).await?;
+    if label.is_some() {
+        app.emit("refresh-data", ())
+            .map_err(|e| format!("Failed to emit refresh: {}", e))?;
+    }
+    Ok(label)
+}
+
+/// Re-applies the most recently undone change, see `undo`.
+#[tauri::command]
+pub async fn redo(app: AppHandle) -> Result<Option<String>, String> {
+    let db: State<Database> = app.state();
+    let journal: State<Journal> = app.state();
+    let label = crate::journal::redo(&db.pool()?, &journal).await?;
+    if label.is_some() {
+        app.emit("refresh-data", ())
+            .map_err(|e| format!("Failed to emit refresh: {}", e))?;
+    }
+    Ok(label)
+}
+
+#[tauri::command]
+pub async fn get_journal_status(app: AppHandle) -> Result<JournalStatus, String> {
+    let journal: State<Journal> = app.state();
+    let (undo, redo) = journal.status();
+    Ok(JournalStatus { undo, redo })
+}
+
+#[tauri::command]
+pub async fn get_encryption_status(app: AppHandle) -> Result<EncryptionStatus, String> {
+    let db: State<Database> = app.state();
+    Ok(EncryptionStatus {
+        encrypted: crate::encryption::is_encrypted(&db.app_dir),
+        locked: db.is_locked(),
+    })
+}
+
+/// Opens an encrypted database; emits `database-unlocked` so windows can load their data.
+#[tauri::command]
+pub async fn unlock_database(app: AppHandle, passphrase: String) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    crate::encryption::unlock(&db, &passphrase).await?;
+    crate::migration::check_and_migrate_from_json(&app, &db).await?;
+    app.emit("database-unlocked", ())
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Backups in the app data folder, newest first.
+#[tauri::command]
+pub async fn list_backups(app: AppHandle) -> Result<Vec<BackupInfo>, String> {
+    let db: State<Database> = app.state();
+    crate::backup::list(&db.app_dir)
+}
+
+/// Replaces the database with a backup and reopens it. An encrypted database
+/// needs its current passphrase. Undo history and unlocked notes are dropped,
+/// since they describe the replaced data.
+#[tauri::command]
+pub async fn restore_backup(
+    app: AppHandle,
+    name: String,
+    passphrase: Option<String>,
+) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    let key = if crate::encryption::is_encrypted(&db.app_dir) {
+        let passphrase = passphrase.ok_or_else(|| "Passphrase required".to_string())?;
+        Some(crate::encryption::current_key(&db, &passphrase).await?)
+    } else {
+        None
+    };
+
+    let recovery = crate::backup::restore(&db, &name, key.as_ref()).await?;
+    db.set_recovery(recovery);
+
+    let journal: State<Journal> = app.state();
+    journal.clear();
+    let unlocked: State<crate::note_lock::UnlockedNotes> = app.state();
+    unlocked.clear();
+
+    app.emit("refresh-data", ())
+        .map_err(|e| format!("Failed to emit refresh: {}", e))?;
+    Ok(())
+}
+
+/// Asks for a folder and exports into it, by default in every format.
+/// Returns `None` if the user cancels the folder picker.
+#[tauri::command]
+pub async fn export_data(
+    app: AppHandle,
+    formats: Option<Vec<ExportFormat>>,
+) -> Result<Option<ExportReport>, String> {
+    let db: State<Database> = app.state();
+    let pool = db.pool()?;
+    let formats = formats
+        .unwrap_or_else(|| vec![ExportFormat::Json, ExportFormat::Markdown, ExportFormat::Csv]);
+
+    let Some(dir) = app.dialog().file().blocking_pick_folder() else {
+        return Ok(None);
+    };
+    let dir = dir
+        .into_path()
+        .map_err(|e| format!("Invalid export folder: {}", e))?;
+
+    crate::export::export(&pool, &db.app_dir, &dir, &formats)
+        .await
+        .map(Some)
+}
+
+/// Asks for a JSON export and merges it into the database, see import.rs.
+/// Returns `None` if the user cancels the file picker. Undo history is
+/// dropped, as it may describe rows the import replaced.
+#[tauri::command]
+pub async fn import_data(
+    app: AppHandle,
+    strategy: Option<ImportStrategy>,
+) -> Result<Option<ImportReport>, String> {
+    let db: State<Database> = app.state();
+    let pool = db.pool()?;
+
+    let Some(path) = app
+        .dialog()
+        .file()
+        .add_filter("JSON", &["json"])
+        .blocking_pick_file()
+    else {
+        return Ok(None);
+    };
+    let path = path
+        .into_path()
+        .map_err(|e| format!("Invalid import file: {}", e))?;
+    let json = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read import file: {}", e))?;
+    let data = crate::import::parse(&json)?;
+
+    let (report, notes) =
+        crate::import::import(&pool, &db.app_dir, &data, strategy.unwrap_or_default()).await?;
+
+    let journal: State<Journal> = app.state();
+    journal.clear();
+    // Overwritten notes may have been unlocked under another password.
+    let unlocked: State<crate::note_lock::UnlockedNotes> = app.state();
+    for id in &notes {
+        unlocked.forget(id);
+    }
+
+    app.emit("refresh-data", ())
+        .map_err(|e| format!("Failed to emit refresh: {}", e))?;
+    Ok(Some(report))
+}
+
+/// Writes the given todos, or every live todo, to an `.ics` file the user
+/// picks. Returns the file's path, or `None` if the user cancels.
+#[tauri::command]
+pub async fn export_ics(
+    app: AppHandle,
+    ids: Option<Vec<String>>,
+) -> Result<Option<String>, String> {
+    let db: State<Database> = app.state();
+    let mut builder = QueryBuilder::<Sqlite>::new("SELECT * FROM todos WHERE ");
+    match &ids {
+        Some(ids) if ids.is_empty() => return Err("No todos to export".to_string()),
+        Some(ids) => {
+            builder.push("id IN (");
+            let mut separated = builder.separated(", ");
+            for id in ids {
+                separated.push_bind(id.clone());
+            }
+            builder.push(")");
+        }
+        None => {
+            builder.push("deleted_at IS NULL");
+        }
+    }
+    builder.push(" ORDER BY created_at");
+    let todos: Vec<Todo> = builder
+        .build_query_as()
+        .fetch_all(&db.pool()?)
+        .await
+        .map_err(|e| format!("Failed to fetch todos: {}", e))?;
+
+    let Some(path) = app
+        .dialog()
+        .file()
+        .add_filter("iCalendar", &["ics"])
+        .set_file_name("silto.ics")
+        .blocking_save_file()
+    else {
+        return Ok(None);
+    };
+    let path = path
+        .into_path()
+        .map_err(|e| format!("Invalid export file: {}", e))?;
+    std::fs::write(&path, crate::ical::to_ics(&todos))
+        .map_err(|e| format!("Failed to write calendar: {}", e))?;
+    Ok(Some(path.to_string_lossy().into_owned()))
+}
+
+/// Asks for an `.ics` file and imports its VTODO and VEVENT entries, see
+/// ical.rs. Returns `None` if the user cancels the file picker.
+#[tauri::command]
+pub async fn import_ics(app: AppHandle) -> Result<Option<ImportReport>, String> {
+    let db: State<Database> = app.state();
+    let pool = db.pool()?;
+
+    let Some(path) = app
+        .dialog()
+        .file()
+        .add_filter("iCalendar", &["ics"])
+        .blocking_pick_file()
+    else {
+        return Ok(None);
+    };
+    let path = path
+        .into_path()
+        .map_err(|e| format!("Invalid import file: {}", e))?;
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read calendar: {}", e))?;
+
+    let report = crate::ical::import(&pool, &text).await?;
+
+    // Same as `import_data`: the journal may hold rows the import replaced.
+    let journal: State<Journal> = app.state();
+    journal.clear();
+    app.emit("refresh-data", ())
+        .map_err(|e| format!("Failed to emit refresh: {}", e))?;
+    Ok(Some(report))
+}
+
+/// Set once after the database failed its integrity check at startup or
+/// unlock and was rebuilt; `None` afterwards.
+#[tauri::command]
+pub async fn take_recovery_report(app: AppHandle) -> Result<Option<RecoveryReport>, String> {
+    let db: State<Database> = app.state();
+    Ok(db.take_recovery())
+}
+
+#[tauri::command]
+pub async fn enable_encryption(app: AppHandle, passphrase: String) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    crate::encryption::enable(&db, &passphrase).await
+}
+
+#[tauri::command]
+pub async fn change_passphrase(app: AppHandle, current: String, new: String) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    crate::encryption::change_passphrase(&db, &current, &new).await
+}
+
+#[tauri::command]
+pub async fn disable_encryption(app: AppHandle, passphrase: String) -> Result<(), String> {
+    let db: State<Database> = app.state();
+    crate::encryption::disable(&db, &passphrase).await
+}
+
 #[tauri::command]
 pub async fn get_settings(app: AppHandle) -> Result<Settings, String> {
     let store = app.store("store.json").map_err(|e| e.to_string())?;
@@ -1,12 +1,121 @@
-use sqlx::{migrate::MigrateDatabase, sqlite::SqlitePoolOptions, Sqlite, SqlitePool};
+use crate::encryption::DbKey;
+use crate::models::RecoveryReport;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::SqlitePool;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError, RwLock};
 use tauri::AppHandle;
 use tauri::Manager;
 
 pub struct Database {
-    pub pool: SqlitePool,
+    /// `None` while an encrypted database waits for its passphrase.
+    pool: RwLock<Option<SqlitePool>>,
+    pub path: PathBuf,
+    pub app_dir: PathBuf,
+    /// Set when the file failed its integrity check and was rebuilt.
+    recovery: Mutex<Option<RecoveryReport>>,
 }
 
+impl Database {
+    pub fn pool(&self) -> Result<SqlitePool, String> {
+        self.pool
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+            .ok_or_else(|| "Database is locked".to_string())
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.pool
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_none()
+    }
+
+    /// Swaps in `pool` and hands back the previous one so the caller can close it.
+    pub fn replace_pool(&self, pool: Option<SqlitePool>) -> Option<SqlitePool> {
+        std::mem::replace(
+            &mut *self.pool.write().unwrap_or_else(PoisonError::into_inner),
+            pool,
+        )
+    }
+
+    pub fn set_recovery(&self, report: Option<RecoveryReport>) {
+        if report.is_some() {
+            *self.recovery.lock().unwrap_or_else(PoisonError::into_inner) = report;
+        }
+    }
+
+    /// The last recovery report, handed out once.
+    pub fn take_recovery(&self) -> Option<RecoveryReport> {
+        self.recovery
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+}
+
+pub fn connect_options(path: &Path, key: Option<&DbKey>) -> SqliteConnectOptions {
+    let options = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true);
+    match key {
+        Some(key) => options.pragma("key", key.pragma_value()),
+        None => options,
+    }
+}
+
+/// Opens `path` (with `key` if it is encrypted) and brings the schema up to date.
+pub async fn open_pool(
+    path: &Path,
+    app_dir: &Path,
+    key: Option<&DbKey>,
+) -> Result<SqlitePool, String> {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(5)
+        .connect_with(connect_options(path, key))
+        .await
+        .map_err(|e| format!("Failed to connect to database: {}", e))?;
+
+    crate::schema::run_migrations(&pool, app_dir).await?;
+    crate::search::backfill_search_text(&pool).await?;
+
+    Ok(pool)
+}
+
+/// A fresh in-memory database with the current schema.
+#[cfg(test)]
+pub async fn memory_pool() -> SqlitePool {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    crate::schema::run_migrations(&pool, Path::new("."))
+        .await
+        .unwrap();
+    pool
+}
+
+/// `open_pool` after an integrity check. A damaged file is replaced by a new
+/// database holding whatever could be salvaged, described by the report.
+pub async fn open_checked(
+    path: &Path,
+    app_dir: &Path,
+    key: Option<&DbKey>,
+) -> Result<(SqlitePool, Option<RecoveryReport>), String> {
+    let problems = crate::recovery::integrity_problems(path, key).await?;
+    if problems.is_empty() {
+        return Ok((open_pool(path, app_dir, key).await?, None));
+    }
+    eprintln!("Database failed its integrity check: {:?}", problems);
+    let (pool, report) = crate::recovery::recover(path, app_dir, key, problems).await?;
+    Ok((pool, Some(report)))
+}
+
+/// Opens the database. An encrypted one comes back locked and is opened by
+/// `encryption::unlock` once the user has entered the passphrase.
 pub async fn init_db(app: &AppHandle) -> Result<Database, String> {
     let app_dir = app
         .path()
@@ -18,56 +127,17 @@ pub async fn init_db(app: &AppHandle) -> Result<Database, String> {
     }
 
     let db_path = app_dir.join("silto.db");
-    let db_url = format!("sqlite:{}", db_path.to_string_lossy());
-
-    if !Sqlite::database_exists(&db_url)
-        .await
-        .unwrap_or(false)
-    {
-        Sqlite::create_database(&db_url)
-            .await
-            .map_err(|e| format!("Failed to create database: {}", e))?;
-    }
-
-    let pool = SqlitePoolOptions::new()
-        .max_connections(5)
-        .connect(&db_url)
-        .await
-        .map_err(|e| format!("Failed to connect to database: {}", e))?;
+    let (pool, recovery) = if crate::encryption::is_encrypted(&app_dir) {
+        (None, None)
+    } else {
+        let (pool, recovery) = open_checked(&db_path, &app_dir, None).await?;
+        (Some(pool), recovery)
+    };
 
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS todos (
-            id TEXT PRIMARY KEY,
-            title TEXT NOT NULL,
-            content TEXT NOT NULL,
-            remind_time TEXT,
-            completed BOOLEAN NOT NULL DEFAULT 0,
-            created_at TEXT NOT NULL,
-            notified BOOLEAN NOT NULL DEFAULT 0
-        )",
-    )
-    .execute(&pool)
-    .await
-    .map_err(|e| format!("Failed to create todos table: {}", e))?;
-
-    // Attempt to add the column if it doesn't exist (primitive migration)
-    // We ignore error because if column exists it implies success or a different error we can't easily handle without more check
-    let _ = sqlx::query("ALTER TABLE todos ADD COLUMN notified BOOLEAN NOT NULL DEFAULT 0")
-        .execute(&pool)
-        .await;
-
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS notes (
-            id TEXT PRIMARY KEY,
-            title TEXT NOT NULL,
-            content TEXT NOT NULL,
-            created_at TEXT NOT NULL,
-            updated_at TEXT NOT NULL
-        )",
-    )
-    .execute(&pool)
-    .await
-    .map_err(|e| format!("Failed to create notes table: {}", e))?;
-
-    Ok(Database { pool })
+    Ok(Database {
+        pool: RwLock::new(pool),
+        path: db_path,
+        app_dir,
+        recovery: Mutex::new(recovery),
+    })
 }
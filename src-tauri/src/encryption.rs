@@ -0,0 +1,332 @@
+// Optional at-rest encryption of silto.db with SQLCipher. The 256-bit key is
+// derived from the user's passphrase with Argon2id and handed to SQLCipher as
+// a raw key, so SQLCipher's own PBKDF2 step is skipped. The KDF parameters and
+// salt live next to the database in `silto.key.json`; its presence is what
+// marks the database as encrypted.
+
+use crate::db::{self, Database};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use sqlx::{ConnectOptions, Connection, SqliteConnection};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const KEY_FILE: &str = "silto.key.json";
+const SALT_LEN: usize = 16;
+
+/// Argon2id parameters used for keys created by this version.
+const ARGON2_MEMORY_KIB: u32 = 64 * 1024;
+const ARGON2_ITERATIONS: u32 = 3;
+const ARGON2_PARALLELISM: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub salt: String,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl KdfParams {
+    /// Fresh parameters with a random salt.
+    pub fn generate() -> Result<Self, String> {
+        let mut salt = [0u8; SALT_LEN];
+        getrandom::getrandom(&mut salt).map_err(|e| format!("Failed to generate salt: {}", e))?;
+        Ok(KdfParams {
+            salt: BASE64.encode(salt),
+            memory_kib: ARGON2_MEMORY_KIB,
+            iterations: ARGON2_ITERATIONS,
+            parallelism: ARGON2_PARALLELISM,
+        })
+    }
+
+    /// Stretches `passphrase` into a 32-byte key.
+    pub fn derive_key(&self, passphrase: &str) -> Result<[u8; 32], String> {
+        let salt = BASE64
+            .decode(&self.salt)
+            .map_err(|e| format!("Invalid key salt: {}", e))?;
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, Some(32))
+            .map_err(|e| format!("Invalid key parameters: {}", e))?;
+
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|e| format!("Failed to derive key: {}", e))?;
+        Ok(key)
+    }
+}
+
+/// Raw SQLCipher key.
+pub struct DbKey([u8; 32]);
+
+impl DbKey {
+    fn hex(&self) -> String {
+        self.0.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Value for `PRAGMA key`; the `x'..'` form tells SQLCipher not to run its KDF.
+    pub fn pragma_value(&self) -> String {
+        format!("\"x'{}'\"", self.hex())
+    }
+
+    fn attach_value(&self) -> String {
+        format!("x'{}'", self.hex())
+    }
+}
+
+fn key_file(app_dir: &Path) -> PathBuf {
+    app_dir.join(KEY_FILE)
+}
+
+pub fn is_encrypted(app_dir: &Path) -> bool {
+    key_file(app_dir).exists()
+}
+
+fn read_params(app_dir: &Path) -> Result<KdfParams, String> {
+    let json = fs::read_to_string(key_file(app_dir))
+        .map_err(|e| format!("Failed to read key file: {}", e))?;
+    serde_json::from_str(&json).map_err(|e| format!("Invalid key file: {}", e))
+}
+
+fn write_params(app_dir: &Path, params: &KdfParams) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(params)
+        .map_err(|e| format!("Failed to serialize key file: {}", e))?;
+    let path = key_file(app_dir);
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, json).map_err(|e| format!("Failed to write key file: {}", e))?;
+    fs::rename(&tmp, &path).map_err(|e| format!("Failed to write key file: {}", e))
+}
+
+/// Moves the current key file aside and writes `params` in its place (none
+/// when `None`). Returns where the old file went, for `restore_key_file`.
+fn stage_key_file(app_dir: &Path, params: Option<&KdfParams>) -> Result<Option<PathBuf>, String> {
+    let path = key_file(app_dir);
+    let previous = path.with_extension("json.old");
+    let moved = if path.exists() {
+        fs::rename(&path, &previous).map_err(|e| format!("Failed to stage key file: {}", e))?;
+        Some(previous)
+    } else {
+        None
+    };
+    if let Some(params) = params {
+        if let Err(e) = write_params(app_dir, params) {
+            restore_key_file(app_dir, moved.as_deref());
+            return Err(e);
+        }
+    }
+    Ok(moved)
+}
+
+/// Undoes `stage_key_file`.
+fn restore_key_file(app_dir: &Path, previous: Option<&Path>) {
+    let path = key_file(app_dir);
+    let _ = fs::remove_file(&path);
+    if let Some(previous) = previous {
+        let _ = fs::rename(previous, &path);
+    }
+}
+
+fn check_passphrase(passphrase: &str) -> Result<(), String> {
+    if passphrase.is_empty() {
+        return Err("Passphrase must not be empty".to_string());
+    }
+    Ok(())
+}
+
+/// Derives the key for the current database and makes sure it opens it.
+pub async fn current_key(db: &Database, passphrase: &str) -> Result<DbKey, String> {
+    let key = DbKey(read_params(&db.app_dir)?.derive_key(passphrase)?);
+
+    // SQLCipher accepts any key and only fails on the first read.
+    let mut conn = db::connect_options(&db.path, Some(&key))
+        .create_if_missing(false)
+        .connect()
+        .await
+        .map_err(|e| format!("Failed to open database: {}", e))?;
+    let readable = sqlx::query("SELECT count(*) FROM sqlite_master")
+        .execute(&mut conn)
+        .await
+        .is_ok();
+    let _ = conn.close().await;
+
+    if !readable {
+        return Err("Incorrect passphrase".to_string());
+    }
+    Ok(key)
+}
+
+/// Opens a locked database with `passphrase`.
+pub async fn unlock(db: &Database, passphrase: &str) -> Result<(), String> {
+    if !db.is_locked() {
+        return Ok(());
+    }
+    let key = current_key(db, passphrase).await?;
+    let (pool, recovery) = db::open_checked(&db.path, &db.app_dir, Some(&key)).await?;
+    db.replace_pool(Some(pool));
+    db.set_recovery(recovery);
+    Ok(())
+}
+
+/// Copies the database `conn` has open into `target` under `key` (plaintext
+/// when `None`).
+async fn export_to(
+    conn: &mut SqliteConnection,
+    target: &Path,
+    key: Option<&DbKey>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("ATTACH DATABASE ? AS rekeyed KEY ?")
+        .bind(target.to_string_lossy().to_string())
+        .bind(key.map(DbKey::attach_value).unwrap_or_default())
+        .execute(&mut *conn)
+        .await?;
+    let exported = sqlx::query("SELECT sqlcipher_export('rekeyed')")
+        .execute(&mut *conn)
+        .await;
+    sqlx::query("DETACH DATABASE rekeyed")
+        .execute(&mut *conn)
+        .await?;
+    exported.map(|_| ())
+}
+
+/// Copies the database into a new file under `new_key` (plaintext when
+/// `None`), swaps it in for the old file and reopens the pool on it.
+async fn rewrite(
+    db: &Database,
+    old_key: Option<&DbKey>,
+    new_key: Option<&DbKey>,
+    new_params: Option<&KdfParams>,
+) -> Result<(), String> {
+    db.pool()?;
+    let tmp = db.path.with_extension("db.rekey");
+    if tmp.exists() {
+        fs::remove_file(&tmp).map_err(|e| format!("Failed to remove stale file: {}", e))?;
+    }
+
+    // The pool closes before the copy is taken: a write landing after it
+    // would be lost with the old file. Commands fail with "Database is
+    // locked" until the pool is back.
+    if let Some(old) = db.replace_pool(None) {
+        old.close().await;
+    }
+    let exported = match db::connect_options(&db.path, old_key).connect().await {
+        Ok(mut conn) => {
+            let exported = export_to(&mut conn, &tmp, new_key).await;
+            let _ = conn.close().await;
+            exported
+        }
+        Err(e) => Err(e),
+    };
+
+    // The key file changes first; if the database cannot follow, it goes back.
+    let replaced = exported
+        .map_err(|e| format!("Failed to re-encrypt database: {}", e))
+        .and_then(|()| stage_key_file(&db.app_dir, new_params))
+        .and_then(|previous| {
+        match fs::rename(&tmp, &db.path) {
+            Ok(()) => {
+                if let Some(previous) = previous {
+                    let _ = fs::remove_file(previous);
+                }
+                Ok(())
+            }
+            Err(e) => {
+                restore_key_file(&db.app_dir, previous.as_deref());
+                Err(format!("Failed to replace database: {}", e))
+            }
+        }
+    });
+    if replaced.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    let key = if replaced.is_ok() { new_key } else { old_key };
+
+    let pool = db::open_pool(&db.path, &db.app_dir, key).await?;
+    db.replace_pool(Some(pool));
+    replaced
+}
+
+/// Moves the backups (pre-migration and pre-restore copies included) from
+/// `old_key` to `new_key`, so they stay restorable after the key changes. A
+/// backup that cannot be re-keyed could not be restored either and is
+/// deleted.
+async fn rekey_copies(
+    db: &Database,
+    old_key: Option<&DbKey>,
+    new_key: Option<&DbKey>,
+) -> Result<(), String> {
+    let dir = crate::backup::backup_dir(&db.app_dir);
+    for backup in crate::backup::list(&db.app_dir)? {
+        let path = dir.join(&backup.name);
+        let tmp = dir.join(format!("{}.rekey", backup.name));
+        let _ = fs::remove_file(&tmp);
+        // Opened with create allowed: ATTACH can only create the new file then.
+        let rekeyed = match db::connect_options(&path, old_key).connect().await {
+            Ok(mut conn) => {
+                let exported = export_to(&mut conn, &tmp, new_key).await;
+                let _ = conn.close().await;
+                exported.is_ok() && fs::rename(&tmp, &path).is_ok()
+            }
+            Err(_) => false,
+        };
+        if !rekeyed {
+            let _ = fs::remove_file(&tmp);
+            fs::remove_file(&path).map_err(|e| format!("Failed to remove backup: {}", e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Deletes the damaged copies moved aside by the startup check, so no
+/// plaintext copy outlives `enable`.
+fn remove_damaged_copies(db: &Database) -> Result<(), String> {
+    let (Some(parent), Some(name)) = (db.path.parent(), db.path.file_name()) else {
+        return Ok(());
+    };
+    let damaged_prefix = format!("{}.damaged-", name.to_string_lossy());
+    for entry in fs::read_dir(parent).map_err(|e| format!("Failed to read data dir: {}", e))? {
+        let entry = entry.map_err(|e| format!("Failed to read data dir: {}", e))?;
+        if entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(&damaged_prefix)
+        {
+            fs::remove_file(entry.path())
+                .map_err(|e| format!("Failed to remove damaged copy: {}", e))?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn enable(db: &Database, passphrase: &str) -> Result<(), String> {
+    if is_encrypted(&db.app_dir) {
+        return Err("Database is already encrypted".to_string());
+    }
+    check_passphrase(passphrase)?;
+    let params = KdfParams::generate()?;
+    let key = DbKey(params.derive_key(passphrase)?);
+    rewrite(db, None, Some(&key), Some(&params)).await?;
+    rekey_copies(db, None, Some(&key)).await?;
+    remove_damaged_copies(db)
+}
+
+pub async fn change_passphrase(db: &Database, current: &str, new: &str) -> Result<(), String> {
+    if !is_encrypted(&db.app_dir) {
+        return Err("Database is not encrypted".to_string());
+    }
+    check_passphrase(new)?;
+    let old_key = current_key(db, current).await?;
+    let params = KdfParams::generate()?;
+    let key = DbKey(params.derive_key(new)?);
+    rewrite(db, Some(&old_key), Some(&key), Some(&params)).await?;
+    rekey_copies(db, Some(&old_key), Some(&key)).await
+}
+
+pub async fn disable(db: &Database, passphrase: &str) -> Result<(), String> {
+    if !is_encrypted(&db.app_dir) {
+        return Err("Database is not encrypted".to_string());
+    }
+    let old_key = current_key(db, passphrase).await?;
+    rewrite(db, Some(&old_key), None, None).await?;
+    rekey_copies(db, Some(&old_key), None).await
+}
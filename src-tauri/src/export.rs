@@ -0,0 +1,468 @@
+// Writes the user's data into a folder in up to three formats: a JSON dump of
+// the models, note history and attachment files that loses nothing, one
+// Markdown file per note with YAML front matter, and a CSV table of todos for
+// spreadsheets. Markdown and CSV hold
+// live items only; locked notes are exported without their content.
+
+use crate::attachments;
+use crate::models::{
+    ExportData, ExportFormat, ExportReport, ExportedAttachment, Note, NoteLock, NoteRevision,
+    Priority, Tag, Todo, TodoList, TodoNoteLink, INBOX_LIST_ID,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, Utc};
+use sqlx::SqlitePool;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+pub const FORMAT_VERSION: i64 = 1;
+const JSON_FILE: &str = "silto-export.json";
+const CSV_FILE: &str = "todos.csv";
+const NOTES_DIR: &str = "notes";
+const NOTE_ATTACHMENTS_DIR: &str = "attachments";
+
+/// Reads every list, tag, todo and note, including the trash, with the notes'
+/// history and the attachment blobs still in the store.
+pub async fn collect(pool: &SqlitePool, app_dir: &Path) -> Result<ExportData, String> {
+    let lists: Vec<TodoList> =
+        sqlx::query_as("SELECT * FROM todo_lists ORDER BY sort_order, created_at")
+            .fetch_all(pool)
+            .await
+            .map_err(|e| format!("Failed to fetch lists: {}", e))?;
+    let tags: Vec<Tag> = sqlx::query_as("SELECT * FROM tags ORDER BY name")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to fetch tags: {}", e))?;
+
+    let mut todos: Vec<Todo> = sqlx::query_as("SELECT * FROM todos ORDER BY created_at, id")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to fetch todos: {}", e))?;
+    let ids: Vec<String> = todos.iter().map(|t| t.id.clone()).collect();
+    let mut tag_ids = crate::tags::load_tag_ids(pool, ("todo_tags", "todo_id"), &ids).await?;
+    for todo in &mut todos {
+        todo.tag_ids = tag_ids.remove(&todo.id).unwrap_or_default();
+    }
+
+    let mut notes: Vec<Note> = sqlx::query_as("SELECT * FROM notes ORDER BY created_at, id")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to fetch notes: {}", e))?;
+    let ids: Vec<String> = notes.iter().map(|n| n.id.clone()).collect();
+    let mut tag_ids = crate::tags::load_tag_ids(pool, ("note_tags", "note_id"), &ids).await?;
+    for note in &mut notes {
+        note.tag_ids = tag_ids.remove(&note.id).unwrap_or_default();
+    }
+
+    let todo_notes: Vec<TodoNoteLink> =
+        sqlx::query_as("SELECT todo_id, note_id, created_at FROM todo_notes ORDER BY created_at")
+            .fetch_all(pool)
+            .await
+            .map_err(|e| format!("Failed to fetch todo links: {}", e))?;
+    let note_locks: Vec<NoteLock> = sqlx::query_as(
+        "SELECT id AS note_id, lock_params, locked_content FROM notes
+         WHERE locked = 1 AND lock_params IS NOT NULL AND locked_content IS NOT NULL",
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch locked notes: {}", e))?;
+    let note_revisions: Vec<NoteRevision> =
+        sqlx::query_as("SELECT * FROM note_revisions ORDER BY note_id, id")
+            .fetch_all(pool)
+            .await
+            .map_err(|e| format!("Failed to fetch note revisions: {}", e))?;
+
+    let stored: Vec<(String, String)> =
+        sqlx::query_as("SELECT hash, mime FROM attachments ORDER BY hash")
+            .fetch_all(pool)
+            .await
+            .map_err(|e| format!("Failed to fetch attachments: {}", e))?;
+    let store = attachments::attachments_dir(app_dir);
+    let mut blobs = Vec::new();
+    for (hash, mime) in stored {
+        let Some(path) = attachments::blob_path(&store, &hash).filter(|p| p.exists()) else {
+            continue;
+        };
+        let bytes = fs::read(&path).map_err(|e| format!("Failed to read attachment: {}", e))?;
+        blobs.push(ExportedAttachment {
+            hash,
+            mime,
+            data_base64: BASE64.encode(bytes),
+        });
+    }
+
+    Ok(ExportData {
+        format_version: FORMAT_VERSION,
+        exported_at: Utc::now(),
+        lists,
+        tags,
+        todos,
+        notes,
+        todo_notes,
+        note_locks,
+        note_revisions,
+        attachments: blobs,
+    })
+}
+
+/// Writes `formats` into `dir` and returns what was written.
+pub async fn export(
+    pool: &SqlitePool,
+    app_dir: &Path,
+    dir: &Path,
+    formats: &[ExportFormat],
+) -> Result<ExportReport, String> {
+    let data = collect(pool, app_dir).await?;
+    let mut files = Vec::new();
+    let mut done = HashSet::new();
+    for &format in formats {
+        if !done.insert(format) {
+            continue;
+        }
+        match format {
+            ExportFormat::Json => files.push(write_json(dir, &data)?),
+            ExportFormat::Markdown => {
+                files.extend(write_markdown(pool, app_dir, dir, &data).await?)
+            }
+            ExportFormat::Csv => files.push(write_csv(dir, &data)?),
+        }
+    }
+
+    Ok(ExportReport {
+        dir: dir.to_string_lossy().into_owned(),
+        files,
+        todos: data.todos.iter().filter(|t| t.deleted_at.is_none()).count(),
+        notes: data.notes.iter().filter(|n| n.deleted_at.is_none()).count(),
+    })
+}
+
+fn write_json(dir: &Path, data: &ExportData) -> Result<String, String> {
+    let json = serde_json::to_string_pretty(data)
+        .map_err(|e| format!("Failed to serialize export: {}", e))?;
+    fs::write(dir.join(JSON_FILE), json)
+        .map_err(|e| format!("Failed to write {}: {}", JSON_FILE, e))?;
+    Ok(JSON_FILE.to_string())
+}
+
+fn tag_names(data: &ExportData, tag_ids: &[String]) -> Vec<String> {
+    tag_ids
+        .iter()
+        .filter_map(|id| data.tags.iter().find(|tag| &tag.id == id))
+        .map(|tag| tag.name.clone())
+        .collect()
+}
+
+/// Device names Windows reserves, with or without an extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// A file name for `title` that is valid on every platform.
+fn file_stem(title: &str) -> String {
+    let cleaned: String = title
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .take(100)
+        .collect();
+    let cleaned = cleaned.trim().trim_matches('.').trim();
+    let base = cleaned.split('.').next().unwrap_or_default().trim_end();
+    if cleaned.is_empty() {
+        "Untitled".to_string()
+    } else if RESERVED_NAMES.contains(&base.to_ascii_uppercase().as_str()) {
+        format!("_{}", cleaned)
+    } else {
+        cleaned.to_string()
+    }
+}
+
+fn extension_for(mime: &str) -> &'static str {
+    match mime {
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/svg+xml" => "svg",
+        "image/bmp" => "bmp",
+        "application/pdf" => "pdf",
+        _ => "bin",
+    }
+}
+
+/// Double-quoted YAML scalar; JSON string syntax is a subset of it.
+fn yaml_string(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+fn front_matter(note: &Note, tags: &[String]) -> String {
+    let tags = tags
+        .iter()
+        .map(|t| yaml_string(t))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut out = format!(
+        "---\nid: {}\ntitle: {}\ncreated_at: {}\nupdated_at: {}\ntags: [{}]\n",
+        yaml_string(&note.id),
+        yaml_string(&note.title),
+        note.created_at.to_rfc3339(),
+        note.updated_at.to_rfc3339(),
+        tags
+    );
+    if note.locked {
+        out.push_str("locked: true\n");
+    }
+    out.push_str("---\n");
+    out
+}
+
+/// Writes `notes/<title>.md` for each live note. Attachments are copied next
+/// to them and the notes link to the copies.
+async fn write_markdown(
+    pool: &SqlitePool,
+    app_dir: &Path,
+    dir: &Path,
+    data: &ExportData,
+) -> Result<Vec<String>, String> {
+    let notes_dir = dir.join(NOTES_DIR);
+    fs::create_dir_all(&notes_dir).map_err(|e| format!("Failed to create notes folder: {}", e))?;
+    let store = attachments::attachments_dir(app_dir);
+    let mut copied: HashMap<String, Option<String>> = HashMap::new();
+    let mut taken = HashSet::new();
+    let mut files = Vec::new();
+
+    for note in data.notes.iter().filter(|n| n.deleted_at.is_none()) {
+        let mut content = note.content.clone();
+        for hash in attachments::referenced_hashes(&note.content) {
+            if !copied.contains_key(&hash) {
+                let copy = copy_attachment(pool, &store, &notes_dir, &hash).await?;
+                copied.insert(hash.clone(), copy);
+            }
+            if let Some(Some(file)) = copied.get(&hash) {
+                content = content.replace(&attachments::reference_for(&hash), file);
+            }
+        }
+
+        let stem = file_stem(&note.title);
+        let mut name = format!("{}.md", stem);
+        let mut n = 2;
+        while !taken.insert(name.to_lowercase()) {
+            name = format!("{} ({}).md", stem, n);
+            n += 1;
+        }
+
+        let mut text = front_matter(note, &tag_names(data, &note.tag_ids));
+        if !note.locked {
+            text.push('\n');
+            text.push_str(&crate::html::to_markdown(&content));
+        }
+        fs::write(notes_dir.join(&name), text)
+            .map_err(|e| format!("Failed to write {}: {}", name, e))?;
+        files.push(format!("{}/{}", NOTES_DIR, name));
+    }
+    Ok(files)
+}
+
+/// Copies a blob into `notes/attachments`; returns its path relative to the
+/// notes, or `None` if the blob is missing from the store.
+async fn copy_attachment(
+    pool: &SqlitePool,
+    store: &Path,
+    notes_dir: &Path,
+    hash: &str,
+) -> Result<Option<String>, String> {
+    let Some(source) = attachments::blob_path(store, hash).filter(|p| p.exists()) else {
+        return Ok(None);
+    };
+    let mime: Option<String> = sqlx::query_scalar("SELECT mime FROM attachments WHERE hash = ?")
+        .bind(hash)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| format!("Failed to fetch attachment: {}", e))?;
+    let file = format!(
+        "{}.{}",
+        hash,
+        extension_for(mime.as_deref().unwrap_or_default())
+    );
+
+    let target_dir = notes_dir.join(NOTE_ATTACHMENTS_DIR);
+    fs::create_dir_all(&target_dir)
+        .map_err(|e| format!("Failed to create attachments folder: {}", e))?;
+    fs::copy(&source, target_dir.join(&file))
+        .map_err(|e| format!("Failed to copy attachment: {}", e))?;
+    Ok(Some(format!("{}/{}", NOTE_ATTACHMENTS_DIR, file)))
+}
+
+/// Quotes a CSV field when it needs it (RFC 4180). A field a spreadsheet would
+/// run as a formula gets a leading `'`.
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn csv_time(time: Option<DateTime<Utc>>) -> String {
+    time.map(|t| t.to_rfc3339()).unwrap_or_default()
+}
+
+fn priority_name(priority: Priority) -> &'static str {
+    match priority {
+        Priority::None => "",
+        Priority::Low => "low",
+        Priority::Medium => "medium",
+        Priority::High => "high",
+    }
+}
+
+/// Writes `todos.csv`, one row per live todo. The BOM makes Excel read it as UTF-8.
+fn write_csv(dir: &Path, data: &ExportData) -> Result<String, String> {
+    let list_name = |id: Option<&str>| {
+        let id = id.unwrap_or(INBOX_LIST_ID);
+        data.lists
+            .iter()
+            .find(|list| list.id == id)
+            .map(|list| list.name.clone())
+            .unwrap_or_default()
+    };
+
+    let mut out = String::from("\u{feff}");
+    out.push_str(
+        "id,title,content,completed,created_at,completed_at,due_at,due_all_day,priority,\
+         list,tags,remind_time,recurrence,parent_id,archived\r\n",
+    );
+    for todo in data.todos.iter().filter(|t| t.deleted_at.is_none()) {
+        let row = [
+            todo.id.clone(),
+            todo.title.clone(),
+            crate::html::to_plain_text(&todo.content),
+            todo.completed.to_string(),
+            todo.created_at.to_rfc3339(),
+            csv_time(todo.completed_at),
+            csv_time(todo.due_at),
+            todo.due_all_day.to_string(),
+            priority_name(todo.priority).to_string(),
+            list_name(todo.list_id.as_deref()),
+            tag_names(data, &todo.tag_ids).join(";"),
+            csv_time(todo.remind_time),
+            todo.recurrence.clone().unwrap_or_default(),
+            todo.parent_id.clone().unwrap_or_default(),
+            todo.archived.to_string(),
+        ];
+        let row: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+        out.push_str(&row.join(","));
+        out.push_str("\r\n");
+    }
+
+    fs::write(dir.join(CSV_FILE), out)
+        .map_err(|e| format!("Failed to write {}: {}", CSV_FILE, e))?;
+    Ok(CSV_FILE.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ImportStrategy;
+
+    #[test]
+    fn file_stem_avoids_windows_device_names() {
+        assert_eq!(file_stem("con"), "_con");
+        assert_eq!(file_stem("LPT1.notes"), "_LPT1.notes");
+        assert_eq!(file_stem("Com9 "), "_Com9");
+        assert_eq!(file_stem("Console"), "Console");
+        assert_eq!(file_stem("COM10"), "COM10");
+    }
+
+    #[test]
+    fn file_stem_replaces_invalid_characters() {
+        assert_eq!(file_stem("a/b:c?"), "a-b-c-");
+        assert_eq!(file_stem(" ..hidden.. "), "hidden");
+        assert_eq!(file_stem("..."), "Untitled");
+    }
+
+    #[tokio::test]
+    async fn json_export_carries_attachments_and_history() {
+        let root = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let (source_dir, target_dir) = (root.join("source"), root.join("target"));
+        let pool = &crate::db::memory_pool().await;
+        let store = attachments::attachments_dir(&source_dir);
+        let hash = attachments::store_blob(&store, b"png bytes").unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+        attachments::insert_attachment(&mut conn, &hash, "image/png", 9)
+            .await
+            .unwrap();
+        drop(conn);
+        let mut note: Note = sqlx::query_as(
+            "INSERT INTO notes (id, title, content, created_at, updated_at)
+             VALUES ('n', 'Photo', ?, ?, ?) RETURNING *",
+        )
+        .bind(format!(
+            "<img src=\"{}\">",
+            attachments::reference_for(&hash)
+        ))
+        .bind(Utc::now())
+        .bind(Utc::now())
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        note.title = "Draft".to_string();
+        crate::revisions::record_revision(pool, &note, true)
+            .await
+            .unwrap();
+        note.title = "Photo".to_string();
+        crate::revisions::record_revision(pool, &note, true)
+            .await
+            .unwrap();
+
+        let data = collect(pool, &source_dir).await.unwrap();
+        assert_eq!(data.attachments.len(), 1);
+        assert_eq!(data.note_revisions.len(), 2);
+        let data = crate::import::parse(&serde_json::to_string(&data).unwrap()).unwrap();
+
+        let target = crate::db::memory_pool().await;
+        for _ in 0..2 {
+            crate::import::import(
+                &target,
+                &target_dir,
+                &data,
+                ImportStrategy::OverwriteIfNewer,
+            )
+            .await
+            .unwrap();
+        }
+        let blob = attachments::blob_path(&attachments::attachments_dir(&target_dir), &hash);
+        assert_eq!(fs::read(blob.unwrap()).unwrap(), b"png bytes");
+        let linked: Vec<String> = sqlx::query_scalar("SELECT hash FROM note_attachments")
+            .fetch_all(&target)
+            .await
+            .unwrap();
+        assert_eq!(linked, [hash]);
+        let titles: Vec<String> =
+            sqlx::query_scalar("SELECT title FROM note_revisions ORDER BY id")
+                .fetch_all(&target)
+                .await
+                .unwrap();
+        assert_eq!(titles, ["Draft", "Photo"]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn csv_field_defuses_formulas() {
+        assert_eq!(csv_field("=1+1"), "'=1+1");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(csv_field("-2,3"), "\"'-2,3\"");
+        assert_eq!(csv_field("\tx"), "'\tx");
+        assert_eq!(csv_field("a \"b\""), "\"a \"\"b\"\"\"");
+        assert_eq!(csv_field("plain"), "plain");
+    }
+}
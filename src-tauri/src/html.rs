@@ -0,0 +1,519 @@
+// Helpers for the TipTap HTML stored in `content` columns.
+
+const BLOCK_TAGS: &[&str] = &[
+    "p",
+    "div",
+    "br",
+    "li",
+    "ul",
+    "ol",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "blockquote",
+    "pre",
+    "tr",
+    "table",
+    "hr",
+];
+
+fn tag_name(tag: &str) -> String {
+    tag.trim_start_matches('/')
+        .split(|c: char| c.is_whitespace() || c == '/' || c == '>')
+        .next()
+        .unwrap_or("")
+        .to_ascii_lowercase()
+}
+
+/// Decodes the handful of entities TipTap emits plus numeric references.
+pub fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let ch = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" | "#39" => Some('\''),
+                "nbsp" => Some(' '),
+                _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+                    u32::from_str_radix(&entity[2..], 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                }
+                _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(char::from_u32),
+                _ => None,
+            };
+            ch.map(|c| (c, end))
+        });
+
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Extracts readable text from an HTML fragment: tags are dropped, block
+/// elements become line breaks and entities are decoded. Input without any
+/// markup comes back unchanged apart from whitespace normalisation.
+pub fn to_plain_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    let mut skip_until: Option<&str> = None;
+
+    while let Some(start) = rest.find('<') {
+        if skip_until.is_none() {
+            text.push_str(&rest[..start]);
+        }
+        let Some(end) = rest[start..].find('>') else {
+            // A lone '<' is just text.
+            if skip_until.is_none() {
+                text.push_str(&rest[start..]);
+            }
+            rest = "";
+            break;
+        };
+
+        let tag = &rest[start + 1..start + end];
+        let name = tag_name(tag);
+        rest = &rest[start + end + 1..];
+
+        if let Some(closing) = skip_until {
+            if tag.starts_with('/') && name == closing {
+                skip_until = None;
+            }
+            continue;
+        }
+
+        match name.as_str() {
+            "script" | "style" if !tag.starts_with('/') => {
+                skip_until = Some(if name == "script" { "script" } else { "style" });
+            }
+            _ if BLOCK_TAGS.contains(&name.as_str()) => text.push('\n'),
+            "td" | "th" if !tag.starts_with('/') => text.push(' '),
+            _ => {}
+        }
+    }
+    if skip_until.is_none() {
+        text.push_str(rest);
+    }
+
+    let decoded = decode_entities(&text);
+    decoded
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Escapes text for use inside an HTML text node.
+pub fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Wraps each non-empty line of plain `text` in a paragraph.
+pub fn from_plain_text(text: &str) -> String {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| format!("<p>{}</p>", escape_text(line)))
+        .collect()
+}
+
+/// Value of attribute `name` in the inside of a start tag, entities decoded.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+    while let Some(pos) = rest.find(name) {
+        let before = rest[..pos].chars().last();
+        let after = rest[pos + name.len()..].trim_start();
+        rest = &rest[pos + name.len()..];
+        if !before.is_some_and(char::is_whitespace) {
+            continue;
+        }
+        let Some(value) = after.strip_prefix('=') else {
+            continue;
+        };
+        let value = value.trim_start();
+        let quote = value.chars().next()?;
+        let value = if quote == '"' || quote == '\'' {
+            let inner = &value[1..];
+            &inner[..inner.find(quote)?]
+        } else {
+            value
+                .split(|c: char| c.is_whitespace() || c == '>')
+                .next()
+                .unwrap_or("")
+        };
+        return Some(decode_entities(value));
+    }
+    None
+}
+
+/// Backslash-escapes the characters Markdown would read as formatting.
+fn escape_markdown(text: &str, line_start: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    for (i, c) in text.chars().enumerate() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '>')
+            || (c == '#' && i == 0 && line_start)
+        {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+struct MarkdownWriter {
+    out: String,
+    /// One entry per open list: `None` for bullets, the next number otherwise.
+    lists: Vec<Option<u32>>,
+    quote_depth: usize,
+    links: Vec<String>,
+    in_pre: bool,
+    in_cell: bool,
+    /// Nothing is written on the current line yet, not even its prefix.
+    line_start: bool,
+    /// The last line is the blank one separating two blocks.
+    after_blank: bool,
+    /// A list marker was just written; the item's first paragraph goes after it.
+    after_marker: bool,
+}
+
+impl MarkdownWriter {
+    fn quote_prefix(&self) -> String {
+        "> ".repeat(self.quote_depth)
+    }
+
+    /// Written lazily before a line's first content, so a line that stays
+    /// empty gets no prefix.
+    fn write(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        if self.line_start {
+            let prefix = self.quote_prefix() + &"   ".repeat(self.lists.len());
+            self.out.push_str(&prefix);
+            self.line_start = false;
+        }
+        self.out.push_str(text);
+        self.after_blank = false;
+        self.after_marker = false;
+    }
+
+    fn end_line(&mut self) {
+        if !self.line_start {
+            self.out.push('\n');
+            self.line_start = true;
+        }
+    }
+
+    /// Separates blocks with a blank line; list items stay tight.
+    fn block_break(&mut self) {
+        if self.out.is_empty() || self.after_blank {
+            return;
+        }
+        self.end_line();
+        if self.lists.is_empty() {
+            self.out.push_str(self.quote_prefix().trim_end());
+            self.out.push('\n');
+            self.after_blank = true;
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        let decoded = decode_entities(text);
+        if self.in_pre {
+            for (i, line) in decoded.split('\n').enumerate() {
+                if i > 0 {
+                    self.out.push('\n');
+                    self.line_start = true;
+                }
+                self.write(line);
+            }
+            return;
+        }
+        let words = decoded.split_whitespace().collect::<Vec<_>>().join(" ");
+        if decoded.starts_with(char::is_whitespace) && !self.line_start && !self.out.ends_with(' ')
+        {
+            self.write(" ");
+        }
+        if words.is_empty() {
+            return;
+        }
+        let escaped = escape_markdown(&words, self.line_start);
+        self.write(&escaped);
+        if decoded.ends_with(char::is_whitespace) {
+            self.write(" ");
+        }
+    }
+
+    fn tag(&mut self, tag: &str) {
+        let closing = tag.starts_with('/');
+        let name = tag_name(tag);
+        match (name.as_str(), closing) {
+            // The first paragraph of a list item goes on the marker's line.
+            ("p" | "div", _) if self.in_cell => {}
+            ("p" | "div", false) if !self.after_marker => self.block_break(),
+            ("p" | "div", true) => self.block_break(),
+            ("br", _) => {
+                self.write("\\");
+                self.end_line();
+            }
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", false) => {
+                self.block_break();
+                let level = name[1..].parse().unwrap_or(1);
+                self.write(&format!("{} ", "#".repeat(level)));
+            }
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", true) => self.block_break(),
+            ("strong" | "b", _) => self.write("**"),
+            ("em" | "i", _) => self.write("*"),
+            ("s" | "del" | "strike", _) => self.write("~~"),
+            ("code", _) if !self.in_pre => self.write("`"),
+            ("pre", false) => {
+                self.block_break();
+                self.write("```");
+                self.end_line();
+                self.in_pre = true;
+            }
+            ("pre", true) => {
+                self.in_pre = false;
+                self.end_line();
+                self.write("```");
+                self.block_break();
+            }
+            ("blockquote", false) => {
+                self.block_break();
+                self.quote_depth += 1;
+            }
+            ("blockquote", true) => {
+                // Drop the quote's own trailing blank line.
+                if self.after_blank {
+                    let blank = self.quote_prefix().trim_end().len() + 1;
+                    self.out.truncate(self.out.len() - blank);
+                    self.after_blank = false;
+                }
+                self.quote_depth = self.quote_depth.saturating_sub(1);
+                self.block_break();
+            }
+            ("ul" | "ol", false) => {
+                if self.lists.is_empty() {
+                    self.block_break();
+                } else {
+                    self.end_line();
+                }
+                self.lists.push((name == "ol").then(|| {
+                    attribute(tag, "start")
+                        .and_then(|start| start.parse().ok())
+                        .unwrap_or(1)
+                }));
+            }
+            ("ul" | "ol", true) => {
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.block_break();
+                }
+            }
+            ("li", false) => {
+                self.end_line();
+                let mut marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "- ".to_string(),
+                };
+                // TipTap task lists mark each item with data-checked.
+                match attribute(tag, "data-checked").as_deref() {
+                    Some("true") => marker.push_str("[x] "),
+                    Some(_) => marker.push_str("[ ] "),
+                    None => {}
+                }
+                let indent = "   ".repeat(self.lists.len().saturating_sub(1));
+                self.out
+                    .push_str(&(self.quote_prefix() + &indent + &marker));
+                self.line_start = false;
+                self.after_blank = false;
+                self.after_marker = true;
+            }
+            ("a", false) => {
+                self.links.push(attribute(tag, "href").unwrap_or_default());
+                self.write("[");
+            }
+            ("a", true) => {
+                let href = self.links.pop().unwrap_or_default();
+                self.write(&format!("]({})", href));
+            }
+            ("img", _) => {
+                let alt = attribute(tag, "alt").unwrap_or_default();
+                let src = attribute(tag, "src").unwrap_or_default();
+                self.write(&format!("![{}]({})", escape_markdown(&alt, false), src));
+            }
+            ("hr", _) => {
+                self.block_break();
+                self.write("---");
+                self.block_break();
+            }
+            ("table", _) => self.block_break(),
+            ("td" | "th", false) => self.in_cell = true,
+            ("td" | "th", true) => {
+                self.in_cell = false;
+                self.write(" | ");
+            }
+            ("tr", true) => {
+                if self.out.ends_with(" | ") {
+                    self.out.truncate(self.out.len() - 3);
+                }
+                self.end_line();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Converts TipTap HTML to Markdown. Covers what the editor produces:
+/// paragraphs, headings, emphasis, code, quotes, (task) lists, links, images
+/// and rules; tables come out as plain rows.
+pub fn to_markdown(html: &str) -> String {
+    let mut writer = MarkdownWriter {
+        out: String::with_capacity(html.len()),
+        lists: Vec::new(),
+        quote_depth: 0,
+        links: Vec::new(),
+        in_pre: false,
+        in_cell: false,
+        line_start: true,
+        after_blank: false,
+        after_marker: false,
+    };
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        writer.text(&rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            writer.text(&rest[start..]);
+            rest = "";
+            break;
+        };
+        writer.tag(&rest[start + 1..start + end]);
+        rest = &rest[start + end + 1..];
+    }
+    writer.text(rest);
+
+    let markdown = writer
+        .out
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n");
+    markdown.trim().to_string() + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_inline_formatting_and_paragraphs() {
+        assert_eq!(
+            to_markdown(
+                "<p>Hello <strong>bold</strong> and <em>it</em> <s>gone</s> <code>x</code></p>\
+                 <p>Second &amp; last</p>"
+            ),
+            "Hello **bold** and *it* ~~gone~~ `x`\n\nSecond & last\n"
+        );
+        assert_eq!(
+            to_markdown("<h2>Title</h2><p>body</p>"),
+            "## Title\n\nbody\n"
+        );
+        assert_eq!(
+            to_markdown("<p>line<br>break</p><hr><p>end</p>"),
+            "line\\\nbreak\n\n---\n\nend\n"
+        );
+    }
+
+    #[test]
+    fn escapes_markdown_syntax_in_text() {
+        assert_eq!(
+            to_markdown("<p>1 * 2 = [x] _y_ # not</p>"),
+            "1 \\* 2 = \\[x\\] \\_y\\_ # not\n"
+        );
+        assert_eq!(to_markdown("<p># heading?</p>"), "\\# heading?\n");
+    }
+
+    #[test]
+    fn keeps_lists_tight_and_indents_nested_ones() {
+        assert_eq!(
+            to_markdown(
+                "<ul><li><p>one</p></li><li><p>two</p><ul><li><p>nested</p></li></ul></li></ul>\
+                 <p>after</p>"
+            ),
+            "- one\n- two\n   - nested\n\nafter\n"
+        );
+        assert_eq!(
+            to_markdown("<ol start=\"3\"><li><p>c</p></li><li><p>d</p></li></ol>"),
+            "3. c\n4. d\n"
+        );
+        assert_eq!(
+            to_markdown(
+                "<ul data-type=\"taskList\"><li data-checked=\"true\"><p>done</p></li>\
+                 <li data-checked=\"false\"><p>todo</p></li></ul>"
+            ),
+            "- [x] done\n- [ ] todo\n"
+        );
+    }
+
+    #[test]
+    fn prefixes_every_quoted_line() {
+        assert_eq!(
+            to_markdown("<blockquote><p>quoted</p><p>more</p></blockquote><p>out</p>"),
+            "> quoted\n>\n> more\n\nout\n"
+        );
+    }
+
+    #[test]
+    fn leaves_code_blocks_verbatim() {
+        assert_eq!(
+            to_markdown("<pre><code>fn main() {\n    let a = 1 * 2;\n}</code></pre>"),
+            "```\nfn main() {\n    let a = 1 * 2;\n}\n```\n"
+        );
+    }
+
+    #[test]
+    fn converts_links_images_and_tables() {
+        assert_eq!(
+            to_markdown(
+                "<p><a href=\"https://x.y/?a=1&amp;b=2\">link</a> \
+                 <img src=\"attachment:ab\" alt=\"pic [1]\"></p>"
+            ),
+            "[link](https://x.y/?a=1&b=2) ![pic \\[1\\]](attachment:ab)\n"
+        );
+        assert_eq!(
+            to_markdown(
+                "<table><tr><th>a</th><th>b</th></tr><tr><td>1</td><td>2</td></tr></table>"
+            ),
+            "a | b\n1 | 2\n"
+        );
+    }
+}
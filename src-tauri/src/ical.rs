@@ -0,0 +1,1014 @@
+// iCalendar (RFC 5545) exchange. Todos are written as VTODO components;
+// VTODO and VEVENT components are read back as todos. An imported todo keeps
+// the UID it came with in `ical_uid` and is exported under it again, so a
+// calendar and Silto can trade the same file back and forth without creating
+// duplicates. A TZID is looked up in the tz database, then in the calendar's
+// own VTIMEZONE definitions; dates and floating times are local.
+
+use crate::models::{ImportReport, Priority, Todo};
+use chrono::{
+    DateTime, Datelike, Days, Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone,
+    Utc, Weekday,
+};
+use sha2::{Digest, Sha256};
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::HashMap;
+
+const PRODID: &str = "-//Silto//Silto//EN";
+const STAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// One content line, e.g. `DUE;VALUE=DATE:20240501`.
+#[derive(Debug, Clone)]
+struct Property {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl Property {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug, Default)]
+struct Component {
+    name: String,
+    properties: Vec<Property>,
+    children: Vec<Component>,
+}
+
+impl Component {
+    fn get(&self, name: &str) -> Option<&Property> {
+        self.properties.iter().find(|p| p.name == name)
+    }
+
+    fn text(&self, name: &str) -> Option<String> {
+        self.get(name).map(|p| unescape(&p.value))
+    }
+
+    fn time(&self, name: &str, zones: &TimeZones) -> Option<(DateTime<Utc>, bool)> {
+        self.get(name).and_then(|p| parse_time(p, zones))
+    }
+}
+
+/// The VTIMEZONE definitions of a calendar by TZID.
+#[derive(Default)]
+struct TimeZones<'a>(HashMap<&'a str, &'a Component>);
+
+impl<'a> TimeZones<'a> {
+    fn of(calendar: &'a Component) -> Self {
+        TimeZones(
+            calendar
+                .children
+                .iter()
+                .filter(|c| c.name == "VTIMEZONE")
+                .filter_map(|zone| Some((zone.get("TZID")?.value.trim(), zone)))
+                .collect(),
+        )
+    }
+
+    /// `time` on the wall clock of `tzid`; local time when there is none or
+    /// it is unknown.
+    fn to_utc(&self, time: NaiveDateTime, tzid: Option<&str>) -> Option<DateTime<Utc>> {
+        let Some(tzid) = tzid.map(str::trim) else {
+            return from_wall_clock(&Local, time);
+        };
+        // Some producers prefix the IANA name with a slash.
+        if let Ok(tz) = tzid.trim_start_matches('/').parse::<chrono_tz::Tz>() {
+            return from_wall_clock(&tz, time);
+        }
+        match self.0.get(tzid).and_then(|zone| zone_offset(zone, time)) {
+            Some(offset) => from_wall_clock(&offset, time),
+            None => from_wall_clock(&Local, time),
+        }
+    }
+}
+
+/// `time` on `tz`'s wall clock; a time in a DST gap is shifted past it.
+fn from_wall_clock<Tz: TimeZone>(tz: &Tz, time: NaiveDateTime) -> Option<DateTime<Utc>> {
+    tz.from_local_datetime(&time)
+        .earliest()
+        .or_else(|| {
+            tz.from_local_datetime(&(time + Duration::hours(1)))
+                .earliest()
+        })
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// The offset a VTIMEZONE gives at wall-clock `time`: that of the STANDARD or
+/// DAYLIGHT observance that began last. Yearly `BYMONTH`/`BYDAY` rules are
+/// followed; any other observance counts from its DTSTART only.
+fn zone_offset(zone: &Component, time: NaiveDateTime) -> Option<FixedOffset> {
+    zone.children
+        .iter()
+        .filter(|c| c.name == "STANDARD" || c.name == "DAYLIGHT")
+        .filter_map(|observance| {
+            let start = observance.get("DTSTART")?.value.trim();
+            let start = NaiveDateTime::parse_from_str(start, "%Y%m%dT%H%M%S").ok()?;
+            let offset = parse_offset(&observance.get("TZOFFSETTO")?.value)?;
+            let onset = observance
+                .get("RRULE")
+                .and_then(|rule| yearly_onset(&rule.value, start, time))
+                .unwrap_or(start);
+            (onset <= time).then_some((onset, offset))
+        })
+        .max_by_key(|(onset, _)| *onset)
+        .map(|(_, offset)| offset)
+}
+
+/// The last onset at or before `time` of a rule like
+/// `FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU` that took effect at `start`.
+fn yearly_onset(rule: &str, start: NaiveDateTime, time: NaiveDateTime) -> Option<NaiveDateTime> {
+    let part = |name: &str| {
+        rule.split(';')
+            .filter_map(|part| part.split_once('='))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim().to_ascii_uppercase())
+    };
+    if part("FREQ")? != "YEARLY" {
+        return None;
+    }
+    let month: u32 = part("BYMONTH")?.parse().ok()?;
+    let byday = part("BYDAY")?;
+    let (ordinal, code) = byday.split_at(byday.len().checked_sub(2)?);
+    let weekday = crate::recurrence::parse_weekday(code)?;
+    let ordinal: i32 = match ordinal.trim_start_matches('+') {
+        "" => 1,
+        n => n.parse().ok()?,
+    };
+
+    let onset = |year| nth_weekday(year, month, weekday, ordinal).map(|d| d.and_time(start.time()));
+    let this_year = onset(time.year())?;
+    let onset = if this_year <= time {
+        this_year
+    } else {
+        onset(time.year() - 1)?
+    };
+    (onset >= start).then_some(onset)
+}
+
+/// The `ordinal`th `weekday` of the month, counted from its end when negative.
+fn nth_weekday(year: i32, month: u32, weekday: Weekday, ordinal: i32) -> Option<NaiveDate> {
+    if ordinal > 0 {
+        return NaiveDate::from_weekday_of_month_opt(year, month, weekday, ordinal as u8);
+    }
+    if ordinal == 0 {
+        return None;
+    }
+    let last = NaiveDate::from_ymd_opt(year, month, 1)?
+        .checked_add_months(chrono::Months::new(1))?
+        .pred_opt()?;
+    let back = (last.weekday().num_days_from_monday() + 7 - weekday.num_days_from_monday()) % 7;
+    let weeks = (-ordinal - 1) as u64;
+    last.checked_sub_days(Days::new(back as u64 + 7 * weeks))
+        .filter(|date| date.month() == month)
+}
+
+/// A UTC offset such as `+0100`, `-0530` or `+013000`.
+fn parse_offset(value: &str) -> Option<FixedOffset> {
+    let value = value.trim();
+    let (sign, digits) = if let Some(digits) = value.strip_prefix('+') {
+        (1, digits)
+    } else {
+        (-1, value.strip_prefix('-')?)
+    };
+    if !matches!(digits.len(), 4 | 6) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let field = |range: std::ops::Range<usize>| digits.get(range).map_or(Ok(0), str::parse::<i32>);
+    let seconds = field(0..2).ok()? * 3600 + field(2..4).ok()? * 60 + field(4..6).ok()?;
+    FixedOffset::east_opt(sign * seconds)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Appends `line` folded to 75 octets per line, as the RFC asks.
+fn push_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn parse_property(line: &str) -> Option<Property> {
+    // The name and parameters end at the first colon outside quotes.
+    let mut quoted = false;
+    let split = line.char_indices().find(|&(_, c)| {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        c == ':' && !quoted
+    })?;
+    let (head, value) = (&line[..split.0], &line[split.0 + 1..]);
+
+    let mut parts = head.split(';');
+    let name = parts.next()?.trim().to_ascii_uppercase();
+    let params = parts
+        .filter_map(|param| param.split_once('='))
+        .map(|(key, value)| {
+            (
+                key.to_ascii_uppercase(),
+                value.trim_matches('"').to_string(),
+            )
+        })
+        .collect();
+    Some(Property {
+        name,
+        params,
+        value: value.to_string(),
+    })
+}
+
+/// Parses the components of an iCalendar file into a tree; anything outside
+/// BEGIN/END pairs is ignored.
+fn parse(text: &str) -> Vec<Component> {
+    let mut lines: Vec<String> = Vec::new();
+    for raw in text.split('\n') {
+        let raw = raw.strip_suffix('\r').unwrap_or(raw);
+        match (raw.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(raw.to_string()),
+        }
+    }
+
+    let mut stack: Vec<Component> = Vec::new();
+    let mut roots = Vec::new();
+    for property in lines.iter().filter_map(|line| parse_property(line)) {
+        match property.name.as_str() {
+            "BEGIN" => stack.push(Component {
+                name: property.value.trim().to_ascii_uppercase(),
+                ..Default::default()
+            }),
+            "END" => {
+                let Some(done) = stack.pop() else { continue };
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(done),
+                    None => roots.push(done),
+                }
+            }
+            _ => {
+                if let Some(current) = stack.last_mut() {
+                    current.properties.push(property);
+                }
+            }
+        }
+    }
+    roots
+}
+
+/// A DATE or DATE-TIME value; the flag is set for dates. Dates and floating
+/// times are local; a TZID is resolved through `zones`.
+fn parse_time(property: &Property, zones: &TimeZones) -> Option<(DateTime<Utc>, bool)> {
+    let value = property.value.trim();
+    if property.param("VALUE") == Some("DATE") || value.len() == 8 {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+        let start = Local
+            .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+            .earliest()?;
+        return Some((start.with_timezone(&Utc), true));
+    }
+    if let Some(utc) = value.strip_suffix('Z') {
+        let time = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        return Some((time.and_utc(), false));
+    }
+    let time = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+    Some((zones.to_utc(time, property.param("TZID"))?, false))
+}
+
+/// Parses a DURATION value such as `-PT15M` or `P1DT2H`.
+fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let value = value.strip_prefix('P')?;
+
+    let mut seconds = 0i64;
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in value.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' => in_time = true,
+            _ => {
+                let n: i64 = number.parse().ok()?;
+                number.clear();
+                seconds += n * match (c, in_time) {
+                    ('W', false) => 7 * 86400,
+                    ('D', false) => 86400,
+                    ('H', true) => 3600,
+                    ('M', true) => 60,
+                    ('S', true) => 1,
+                    _ => return None,
+                };
+            }
+        }
+    }
+    if !number.is_empty() {
+        return None;
+    }
+    Some(Duration::seconds(if negative { -seconds } else { seconds }))
+}
+
+fn format_duration(duration: Duration) -> String {
+    let minutes = duration.num_minutes();
+    let sign = if minutes < 0 { "-" } else { "" };
+    format!("{}PT{}M", sign, minutes.abs())
+}
+
+fn stamp(time: DateTime<Utc>) -> String {
+    time.format(STAMP_FORMAT).to_string()
+}
+
+/// The RFC ranks 1 highest and 9 lowest; 0 means undefined.
+fn priority_value(priority: Priority) -> Option<u8> {
+    match priority {
+        Priority::None => None,
+        Priority::High => Some(1),
+        Priority::Medium => Some(5),
+        Priority::Low => Some(9),
+    }
+}
+
+fn priority_from(value: &str) -> Priority {
+    match value.trim().parse::<u8>() {
+        Ok(1..=4) => Priority::High,
+        Ok(5) => Priority::Medium,
+        Ok(6..=9) => Priority::Low,
+        _ => Priority::None,
+    }
+}
+
+fn push_vtodo(out: &mut String, todo: &Todo, now: DateTime<Utc>) {
+    push_line(out, "BEGIN:VTODO");
+    push_line(
+        out,
+        &format!("UID:{}", escape(todo.ical_uid.as_ref().unwrap_or(&todo.id))),
+    );
+    push_line(out, &format!("DTSTAMP:{}", stamp(now)));
+    push_line(out, &format!("CREATED:{}", stamp(todo.created_at)));
+    push_line(out, &format!("LAST-MODIFIED:{}", stamp(todo.updated_at)));
+    push_line(out, &format!("SUMMARY:{}", escape(&todo.title)));
+    let description = crate::html::to_plain_text(&todo.content);
+    if !description.trim().is_empty() {
+        push_line(out, &format!("DESCRIPTION:{}", escape(description.trim())));
+    }
+    if let Some(due) = todo.due_at {
+        if todo.due_all_day {
+            let day = due.with_timezone(&Local).format("%Y%m%d");
+            push_line(out, &format!("DUE;VALUE=DATE:{}", day));
+        } else {
+            push_line(out, &format!("DUE:{}", stamp(due)));
+        }
+    }
+    if let Some(priority) = priority_value(todo.priority) {
+        push_line(out, &format!("PRIORITY:{}", priority));
+    }
+    if todo.completed {
+        push_line(out, "STATUS:COMPLETED");
+        push_line(
+            out,
+            &format!(
+                "COMPLETED:{}",
+                stamp(todo.completed_at.unwrap_or(todo.updated_at))
+            ),
+        );
+    } else {
+        push_line(out, "STATUS:NEEDS-ACTION");
+        // Each occurrence is its own todo; only the open one carries the rule,
+        // so calendars do not expand the series once per occurrence.
+        if let Some(rule) = &todo.recurrence {
+            push_line(out, &format!("RRULE:{}", rule));
+        }
+    }
+
+    if let Some(remind) = todo.remind_time {
+        push_alarm(
+            out,
+            &todo.title,
+            &format!("TRIGGER;VALUE=DATE-TIME:{}", stamp(remind)),
+        );
+    }
+    if let (Some(minutes), Some(_)) = (todo.remind_before_due_minutes, todo.due_at) {
+        let trigger = format_duration(-Duration::minutes(minutes));
+        push_alarm(
+            out,
+            &todo.title,
+            &format!("TRIGGER;RELATED=END:{}", trigger),
+        );
+    }
+    push_line(out, "END:VTODO");
+}
+
+fn push_alarm(out: &mut String, title: &str, trigger: &str) {
+    push_line(out, "BEGIN:VALARM");
+    push_line(out, "ACTION:DISPLAY");
+    push_line(out, &format!("DESCRIPTION:{}", escape(title)));
+    push_line(out, trigger);
+    push_line(out, "END:VALARM");
+}
+
+/// Renders `todos` as an iCalendar file.
+pub fn to_ics(todos: &[Todo]) -> String {
+    let now = Utc::now();
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, &format!("PRODID:{}", PRODID));
+    for todo in todos {
+        push_vtodo(&mut out, todo, now);
+    }
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+/// A todo read from a VTODO or VEVENT, with the UID it is matched by.
+struct Entry {
+    uid: String,
+    title: String,
+    description: Option<String>,
+    due: Option<(DateTime<Utc>, bool)>,
+    completed_at: Option<DateTime<Utc>>,
+    completed: bool,
+    priority: Priority,
+    recurrence: Option<String>,
+    remind_time: Option<DateTime<Utc>>,
+    remind_before_due_minutes: Option<i64>,
+    created_at: Option<DateTime<Utc>>,
+    /// LAST-MODIFIED, else DTSTAMP; decides whether a known entry is updated.
+    modified_at: Option<DateTime<Utc>>,
+}
+
+/// Reads an entry; `None` for components that are not todos or events and
+/// for overrides of single occurrences (RECURRENCE-ID), which share the
+/// series' UID.
+fn read_entry(component: &Component, zones: &TimeZones) -> Option<Entry> {
+    let event = match component.name.as_str() {
+        "VTODO" => false,
+        "VEVENT" => true,
+        _ => return None,
+    };
+    if component.get("RECURRENCE-ID").is_some() {
+        return None;
+    }
+
+    let start = component.time("DTSTART", zones);
+    let due = if event {
+        start
+    } else {
+        component.time("DUE", zones).or(start)
+    };
+    let end = if event {
+        component.time("DTEND", zones)
+    } else {
+        due
+    };
+    let title = component.text("SUMMARY").unwrap_or_default();
+
+    // Entries without a UID get one from their content, so importing the
+    // same file twice still matches them up.
+    let uid = component.text("UID").filter(|uid| !uid.trim().is_empty());
+    let uid = uid.unwrap_or_else(|| {
+        let seed = format!(
+            "{}\n{}\n{:?}",
+            title,
+            component
+                .get("DTSTART")
+                .map(|p| p.value.as_str())
+                .unwrap_or_default(),
+            component.get("DTSTAMP").map(|p| p.value.as_str()),
+        );
+        format!("{:x}", Sha256::digest(seed.as_bytes()))
+    });
+
+    let completed_at = component.time("COMPLETED", zones).map(|(time, _)| time);
+    let status = component.text("STATUS").unwrap_or_default();
+    let mut entry = Entry {
+        uid,
+        title,
+        description: component.text("DESCRIPTION"),
+        due,
+        completed: !event && (completed_at.is_some() || status.eq_ignore_ascii_case("COMPLETED")),
+        completed_at,
+        priority: component
+            .get("PRIORITY")
+            .map(|p| priority_from(&p.value))
+            .unwrap_or_default(),
+        recurrence: component.get("RRULE").map(|p| p.value.trim().to_string()),
+        remind_time: None,
+        remind_before_due_minutes: None,
+        created_at: component.time("CREATED", zones).map(|(time, _)| time),
+        modified_at: component
+            .time("LAST-MODIFIED", zones)
+            .or_else(|| component.time("DTSTAMP", zones))
+            .map(|(time, _)| time),
+    };
+
+    // The first alarm at a fixed time becomes the reminder; the first one
+    // before the due moment becomes the "remind before due" rule.
+    for alarm in component.children.iter().filter(|c| c.name == "VALARM") {
+        let Some(trigger) = alarm.get("TRIGGER") else {
+            continue;
+        };
+        if trigger.param("VALUE") == Some("DATE-TIME") {
+            if entry.remind_time.is_none() {
+                entry.remind_time = parse_time(trigger, zones).map(|(time, _)| time);
+            }
+            continue;
+        }
+        let Some(offset) = parse_duration(&trigger.value) else {
+            continue;
+        };
+        let anchor = if trigger.param("RELATED") == Some("END") {
+            end
+        } else {
+            start.or(due)
+        };
+        let Some((anchor, _)) = anchor else {
+            continue;
+        };
+        if Some(anchor) == due.map(|(time, _)| time) && offset <= Duration::zero() {
+            if entry.remind_before_due_minutes.is_none() {
+                entry.remind_before_due_minutes = Some(-offset.num_minutes());
+            }
+        } else if entry.remind_time.is_none() {
+            entry.remind_time = Some(anchor + offset);
+        }
+    }
+    Some(entry)
+}
+
+/// Copies what iCalendar carries onto `todo`, leaving the rest alone.
+/// Reminders that are already past count as delivered, so importing an old
+/// calendar does not set off a burst of notifications.
+fn apply_entry(todo: &mut Todo, entry: &Entry) {
+    let now = Utc::now();
+    let old_due_remind = todo.due_remind_at();
+    todo.title = entry.title.clone();
+    // Rich text survives unless the description was actually edited.
+    let description = entry
+        .description
+        .as_deref()
+        .map(crate::html::from_plain_text)
+        .unwrap_or_default();
+    if crate::html::to_plain_text(&description) != crate::html::to_plain_text(&todo.content) {
+        todo.content = description;
+    }
+    todo.due_at = entry.due.map(|(time, _)| time);
+    todo.due_all_day = entry.due.is_some_and(|(_, all_day)| all_day);
+    todo.priority = entry.priority;
+    if todo.remind_time != entry.remind_time {
+        todo.remind_time = entry.remind_time;
+        todo.notified = entry.remind_time.is_some_and(|at| at <= now);
+    }
+    todo.remind_before_due_minutes = entry.remind_before_due_minutes;
+    if todo.due_remind_at() != old_due_remind {
+        todo.due_notified = todo.due_remind_at().is_some_and(|at| at <= now);
+    }
+    if todo.completed != entry.completed {
+        todo.completed = entry.completed;
+        todo.completed_at = entry
+            .completed
+            .then(|| entry.completed_at.or(entry.modified_at).unwrap_or(now));
+    }
+    todo.updated_at = entry.modified_at.unwrap_or(now);
+
+    if todo.recurrence != entry.recurrence {
+        todo.recurrence = entry.recurrence.clone();
+        todo.occurrence_at = None;
+        // Rules this app cannot schedule are dropped rather than failing the import.
+        if crate::recurrence::prepare_series(todo).is_err() {
+            todo.recurrence = None;
+        }
+    }
+}
+
+/// Whether writing `todo` would take the UID or series slot of another todo.
+async fn collides(conn: &mut SqliteConnection, todo: &Todo) -> Result<bool, String> {
+    sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM todos WHERE id != ? AND (ical_uid = ?
+         OR (series_id = ? AND recurrence_index = ?)))",
+    )
+    .bind(&todo.id)
+    .bind(&todo.ical_uid)
+    .bind(&todo.series_id)
+    .bind(todo.recurrence_index)
+    .fetch_one(conn)
+    .await
+    .map_err(|e| format!("Failed to look up todo: {}", e))
+}
+
+/// Imports the VTODO and VEVENT entries of an iCalendar file in one
+/// transaction. An entry whose UID is already known updates that todo if it
+/// was modified later than the todo; otherwise it is skipped, as are entries
+/// matching a trashed todo or clashing with another one.
+pub async fn import(pool: &SqlitePool, text: &str) -> Result<ImportReport, String> {
+    let mut report = ImportReport {
+        inserted: 0,
+        updated: 0,
+        skipped: 0,
+    };
+    let calendars = parse(text);
+    if calendars.is_empty() {
+        return Err("Not an iCalendar file".to_string());
+    }
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| format!("Failed to begin transaction: {}", e))?;
+    for calendar in &calendars {
+        let zones = TimeZones::of(calendar);
+        for component in &calendar.children {
+            let Some(entry) = read_entry(component, &zones) else {
+                continue;
+            };
+
+            let existing: Option<Todo> = sqlx::query_as(
+                "SELECT * FROM todos WHERE ical_uid = ? OR (id = ? AND ical_uid IS NULL)
+                 ORDER BY ical_uid IS NULL LIMIT 1",
+            )
+            .bind(&entry.uid)
+            .bind(&entry.uid)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to look up todo: {}", e))?;
+
+            let (todo, updated) = match existing {
+                Some(todo)
+                    if todo.deleted_at.is_none()
+                        && entry.modified_at.is_some_and(|at| at > todo.updated_at) =>
+                {
+                    let mut todo = todo;
+                    apply_entry(&mut todo, &entry);
+                    (todo, true)
+                }
+                Some(_) => {
+                    report.skipped += 1;
+                    continue;
+                }
+                None => {
+                    let mut todo = Todo::new(String::new(), String::new());
+                    todo.ical_uid = Some(entry.uid.clone());
+                    todo.created_at = entry.created_at.unwrap_or(todo.created_at);
+                    apply_entry(&mut todo, &entry);
+                    (todo, false)
+                }
+            };
+            if collides(&mut tx, &todo).await? {
+                report.skipped += 1;
+                continue;
+            }
+            if updated {
+                report.updated += 1;
+            } else {
+                report.inserted += 1;
+            }
+            crate::import::write_todo(&mut tx, &todo).await?;
+        }
+    }
+    tx.commit()
+        .await
+        .map_err(|e| format!("Failed to commit import: {}", e))?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn property(line: &str) -> Property {
+        parse_property(line).unwrap()
+    }
+
+    fn time(line: &str) -> Option<(DateTime<Utc>, bool)> {
+        parse_time(&property(line), &TimeZones::default())
+    }
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    /// The entries of a calendar holding `body`.
+    fn entries(body: &str) -> Vec<Entry> {
+        let calendars = parse(&format!("BEGIN:VCALENDAR\n{}END:VCALENDAR\n", body));
+        let zones = TimeZones::of(&calendars[0]);
+        calendars[0]
+            .children
+            .iter()
+            .filter_map(|c| read_entry(c, &zones))
+            .collect()
+    }
+
+    async fn titles(pool: &SqlitePool) -> Vec<(String, Option<String>)> {
+        sqlx::query_as("SELECT title, ical_uid FROM todos ORDER BY title")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    fn local(text: &str) -> DateTime<Utc> {
+        let time = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap();
+        Local
+            .from_local_datetime(&time)
+            .earliest()
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn push_line_folds_at_75_octets() {
+        let mut out = String::new();
+        push_line(&mut out, &format!("SUMMARY:{}", "é".repeat(60)));
+        let lines: Vec<&str> = out.trim_end_matches("\r\n").split("\r\n").collect();
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|line| line.len() <= 75));
+        assert!(lines[1..].iter().all(|line| line.starts_with(' ')));
+    }
+
+    #[test]
+    fn parse_unfolds_lines() {
+        let mut text = String::from("BEGIN:VCALENDAR\r\nBEGIN:VTODO\r\n");
+        let summary = format!("SUMMARY:{}", "word ".repeat(30));
+        push_line(&mut text, &summary);
+        text.push_str("DESCRIPTION:tab\n\tfolded\nEND:VTODO\nEND:VCALENDAR\n");
+
+        let calendars = parse(&text);
+        assert_eq!(calendars.len(), 1);
+        let todo = &calendars[0].children[0];
+        assert_eq!(todo.name, "VTODO");
+        assert_eq!(
+            todo.get("SUMMARY").unwrap().value,
+            summary["SUMMARY:".len()..]
+        );
+        assert_eq!(todo.text("DESCRIPTION").unwrap(), "tabfolded");
+    }
+
+    #[test]
+    fn parse_ignores_lines_outside_components() {
+        let calendars = parse("SUMMARY:stray\nBEGIN:VCALENDAR\nEND:VCALENDAR\nEND:VTODO\n");
+        assert_eq!(calendars.len(), 1);
+        assert!(calendars[0].properties.is_empty());
+    }
+
+    #[test]
+    fn escaping_round_trips() {
+        let text = "a, b; c\\d\nnext line";
+        assert_eq!(escape(text), r"a\, b\; c\\d\nnext line");
+        assert_eq!(unescape(&escape(text)), text);
+        assert_eq!(unescape(&escape("crlf\r\nline")), "crlf\nline");
+        assert_eq!(unescape("upper\\Ncase"), "upper\ncase");
+    }
+
+    #[test]
+    fn parse_property_handles_params_and_quotes() {
+        let p = property("dtstart;TZID=\"Europe/Berlin\";value=DATE-TIME:20240301T090000");
+        assert_eq!(p.name, "DTSTART");
+        assert_eq!(p.param("TZID"), Some("Europe/Berlin"));
+        assert_eq!(p.param("VALUE"), Some("DATE-TIME"));
+        assert_eq!(p.value, "20240301T090000");
+
+        let p = property("ATTENDEE;CN=\"Doe: Jane\":mailto:jane@example.com");
+        assert_eq!(p.param("CN"), Some("Doe: Jane"));
+        assert_eq!(p.value, "mailto:jane@example.com");
+    }
+
+    #[test]
+    fn parse_time_reads_dates_and_date_times() {
+        let date = time("DUE;VALUE=DATE:20240301").unwrap();
+        assert_eq!(date, (local("2024-03-01 00:00"), true));
+        assert_eq!(time("DUE:20240301").unwrap(), date);
+
+        let zulu = time("DUE:20240301T093000Z").unwrap();
+        assert_eq!(zulu, (utc("2024-03-01T09:30:00Z"), false));
+
+        let floating = time("DUE:20240301T093000").unwrap();
+        assert_eq!(floating, (local("2024-03-01 09:30"), false));
+        // Without a definition, an unknown zone falls back to local time.
+        assert_eq!(time("DUE;TZID=Nowhere:20240301T093000").unwrap(), floating);
+
+        assert!(time("DUE:2024-03-01").is_none());
+        assert!(time("DUE;VALUE=DATE:20240301T093000").is_none());
+    }
+
+    #[test]
+    fn parse_time_resolves_tzids() {
+        let winter = time("DUE;TZID=Europe/Berlin:20240301T093000").unwrap();
+        assert_eq!(winter.0, utc("2024-03-01T08:30:00Z"));
+        let summer = time("DUE;TZID=Europe/Berlin:20240701T093000").unwrap();
+        assert_eq!(summer.0, utc("2024-07-01T07:30:00Z"));
+        let slashed = time("DUE;TZID=/America/New_York:20240701T093000").unwrap();
+        assert_eq!(slashed.0, utc("2024-07-01T13:30:00Z"));
+        // 02:30 does not exist on the morning clocks spring forward.
+        let gap = time("DUE;TZID=Europe/Berlin:20240331T023000").unwrap();
+        assert_eq!(gap.0, utc("2024-03-31T01:30:00Z"));
+    }
+
+    #[test]
+    fn vtimezone_defines_zones_the_tz_database_lacks() {
+        let entries = entries(
+            "BEGIN:VTIMEZONE\nTZID:W. Europe Standard Time\n\
+             BEGIN:STANDARD\nDTSTART:16010101T030000\nTZOFFSETFROM:+0200\nTZOFFSETTO:+0100\n\
+             RRULE:FREQ=YEARLY;BYDAY=-1SU;BYMONTH=10\nEND:STANDARD\n\
+             BEGIN:DAYLIGHT\nDTSTART:16010101T020000\nTZOFFSETFROM:+0100\nTZOFFSETTO:+0200\n\
+             RRULE:FREQ=YEARLY;BYDAY=-1SU;BYMONTH=3\nEND:DAYLIGHT\nEND:VTIMEZONE\n\
+             BEGIN:VEVENT\nUID:winter\nDTSTART;TZID=W. Europe Standard Time:20240115T090000\nEND:VEVENT\n\
+             BEGIN:VEVENT\nUID:summer\nDTSTART;TZID=W. Europe Standard Time:20240701T090000\nEND:VEVENT\n\
+             BEGIN:VEVENT\nUID:switch\nDTSTART;TZID=W. Europe Standard Time:20240331T040000\nEND:VEVENT\n",
+        );
+        let due: Vec<_> = entries.iter().map(|e| e.due.unwrap().0).collect();
+        assert_eq!(
+            due,
+            [
+                utc("2024-01-15T08:00:00Z"),
+                utc("2024-07-01T07:00:00Z"),
+                utc("2024-03-31T02:00:00Z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn vtimezone_helpers() {
+        assert_eq!(parse_offset("+0100"), FixedOffset::east_opt(3600));
+        assert_eq!(parse_offset("-0530"), FixedOffset::east_opt(-19800));
+        assert_eq!(parse_offset("+013015"), FixedOffset::east_opt(5415));
+        assert_eq!(parse_offset("0100"), None);
+        assert_eq!(parse_offset("+1"), None);
+
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d);
+        assert_eq!(nth_weekday(2024, 3, Weekday::Sun, -1), date(2024, 3, 31));
+        assert_eq!(nth_weekday(2024, 10, Weekday::Sun, -1), date(2024, 10, 27));
+        assert_eq!(nth_weekday(2024, 3, Weekday::Sun, 2), date(2024, 3, 10));
+        assert_eq!(nth_weekday(2024, 2, Weekday::Fri, -5), None);
+        assert_eq!(nth_weekday(2024, 2, Weekday::Thu, 0), None);
+    }
+
+    #[test]
+    fn read_entry_derives_a_stable_uid() {
+        let body = "BEGIN:VTODO\nSUMMARY:No UID\nDTSTART:20240301T090000Z\nEND:VTODO\n";
+        let first = &entries(body)[0];
+        assert_eq!(first.uid.len(), 64);
+        assert_eq!(entries(body)[0].uid, first.uid);
+        let other = body.replace("No UID", "Other");
+        assert_ne!(entries(&other)[0].uid, first.uid);
+
+        let body = "BEGIN:VTODO\nUID:kept\nSUMMARY:x\nEND:VTODO\n\
+                    BEGIN:VTODO\nUID:kept\nRECURRENCE-ID:20240301T090000Z\nEND:VTODO\n\
+                    BEGIN:VJOURNAL\nUID:journal\nEND:VJOURNAL\n";
+        let uids: Vec<_> = entries(body).into_iter().map(|e| e.uid).collect();
+        assert_eq!(uids, ["kept"]);
+    }
+
+    #[test]
+    fn apply_entry_keeps_rich_text_with_the_same_description() {
+        let mut todo = Todo::new(
+            "Old".to_string(),
+            "<p><strong>Buy</strong> milk</p>".to_string(),
+        );
+        let body = "BEGIN:VTODO\nUID:a\nSUMMARY:New\nDESCRIPTION:Buy milk\nEND:VTODO\n";
+        apply_entry(&mut todo, &entries(body)[0]);
+        assert_eq!(todo.title, "New");
+        assert_eq!(todo.content, "<p><strong>Buy</strong> milk</p>");
+
+        let body = body.replace("Buy milk", "Buy bread");
+        apply_entry(&mut todo, &entries(&body)[0]);
+        assert_eq!(crate::html::to_plain_text(&todo.content), "Buy bread");
+    }
+
+    #[tokio::test]
+    async fn import_matches_entries_by_uid() {
+        let pool = crate::db::memory_pool().await;
+        let file = |summary: &str, modified: &str| {
+            format!(
+                "BEGIN:VCALENDAR\nBEGIN:VTODO\nUID:a\nSUMMARY:{}\nLAST-MODIFIED:{}\nEND:VTODO\n\
+                 BEGIN:VTODO\nSUMMARY:No UID\nEND:VTODO\nEND:VCALENDAR\n",
+                summary, modified
+            )
+        };
+
+        let report = import(&pool, &file("First", "20240101T000000Z"))
+            .await
+            .unwrap();
+        assert_eq!((report.inserted, report.updated, report.skipped), (2, 0, 0));
+        let report = import(&pool, &file("First", "20240101T000000Z"))
+            .await
+            .unwrap();
+        assert_eq!((report.inserted, report.updated, report.skipped), (0, 0, 2));
+
+        // Only an entry modified after the todo updates it.
+        sqlx::query("UPDATE todos SET updated_at = ? WHERE ical_uid = 'a'")
+            .bind(utc("2024-06-01T00:00:00Z"))
+            .execute(&pool)
+            .await
+            .unwrap();
+        let report = import(&pool, &file("Stale", "20240301T000000Z"))
+            .await
+            .unwrap();
+        assert_eq!((report.inserted, report.updated, report.skipped), (0, 0, 2));
+        let report = import(&pool, &file("Second", "20240901T000000Z"))
+            .await
+            .unwrap();
+        assert_eq!((report.inserted, report.updated, report.skipped), (0, 1, 1));
+
+        let todos = titles(&pool).await;
+        assert_eq!(todos.len(), 2);
+        assert_eq!(todos[1], ("Second".to_string(), Some("a".to_string())));
+    }
+
+    #[tokio::test]
+    async fn import_skips_trashed_todos() {
+        let pool = crate::db::memory_pool().await;
+        let file = "BEGIN:VCALENDAR\nBEGIN:VTODO\nUID:a\nSUMMARY:Back\n\
+                    LAST-MODIFIED:20990101T000000Z\nEND:VTODO\nEND:VCALENDAR\n";
+        import(&pool, file).await.unwrap();
+        sqlx::query("UPDATE todos SET deleted_at = ?, updated_at = ?, title = 'Trashed'")
+            .bind(Utc::now())
+            .bind(utc("2024-01-01T00:00:00Z"))
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let report = import(&pool, file).await.unwrap();
+        assert_eq!((report.inserted, report.updated, report.skipped), (0, 0, 1));
+        assert_eq!(titles(&pool).await[0].0, "Trashed");
+    }
+
+    #[tokio::test]
+    async fn collides_with_another_todos_uid_or_series_slot() {
+        let pool = crate::db::memory_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+        let mut stored = Todo::new("Stored".to_string(), String::new());
+        stored.ical_uid = Some("a".to_string());
+        stored.recurrence = Some("FREQ=DAILY".to_string());
+        crate::recurrence::prepare_series(&mut stored).unwrap();
+        crate::import::write_todo(&mut conn, &stored).await.unwrap();
+        assert!(!collides(&mut conn, &stored).await.unwrap());
+
+        let mut same_uid = Todo::new("Same UID".to_string(), String::new());
+        same_uid.ical_uid = Some("a".to_string());
+        assert!(collides(&mut conn, &same_uid).await.unwrap());
+
+        let mut same_slot = Todo::new("Same slot".to_string(), String::new());
+        same_slot.series_id = stored.series_id.clone();
+        assert!(collides(&mut conn, &same_slot).await.unwrap());
+        same_slot.recurrence_index = 1;
+        assert!(!collides(&mut conn, &same_slot).await.unwrap());
+    }
+
+    #[test]
+    fn parse_duration_reads_signed_durations() {
+        assert_eq!(parse_duration("-PT15M"), Some(Duration::minutes(-15)));
+        assert_eq!(parse_duration("+PT1H"), Some(Duration::hours(1)));
+        assert_eq!(
+            parse_duration("P1DT2H3M4S"),
+            Some(Duration::seconds(86400 + 7200 + 180 + 4))
+        );
+        assert_eq!(parse_duration("-P2W"), Some(Duration::weeks(-2)));
+        assert_eq!(parse_duration(" P0D "), Some(Duration::zero()));
+    }
+
+    #[test]
+    fn parse_duration_rejects_malformed_values() {
+        assert_eq!(parse_duration("PT15"), None);
+        assert_eq!(parse_duration("P1H"), None);
+        assert_eq!(parse_duration("PT1D"), None);
+        assert_eq!(parse_duration("15M"), None);
+        assert_eq!(parse_duration("PTM"), None);
+    }
+}
@@ -0,0 +1,501 @@
+// Reads a JSON export (see export.rs) back in, next to the data already in
+// the database. Lists are matched by id and tags by id, then name; missing
+// ones are added. Todos and notes whose id is taken are resolved by the
+// chosen `ImportStrategy`, and the notes written bring their history along.
+// Attachment blobs go into the store first; everything else happens in one
+// transaction.
+
+use crate::models::{ExportData, ImportReport, ImportStrategy, Note, NoteLock, Todo};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, Utc};
+use sqlx::SqliteConnection;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::path::Path;
+
+pub fn parse(json: &str) -> Result<ExportData, String> {
+    let data: ExportData =
+        serde_json::from_str(json).map_err(|e| format!("Failed to parse export: {}", e))?;
+    if data.format_version > crate::export::FORMAT_VERSION {
+        return Err(format!(
+            "Export format version {} is newer than this app supports ({}). Please update Silto.",
+            data.format_version,
+            crate::export::FORMAT_VERSION
+        ));
+    }
+    Ok(data)
+}
+
+enum Action {
+    /// Insert under this id, which differs from the exported one for duplicates.
+    Insert(String),
+    Update,
+    Skip,
+}
+
+async fn exists(conn: &mut SqliteConnection, table: &str, id: &str) -> Result<bool, String> {
+    sqlx::query_scalar(&format!(
+        "SELECT EXISTS(SELECT 1 FROM {} WHERE id = ?)",
+        table
+    ))
+    .bind(id)
+    .fetch_one(conn)
+    .await
+    .map_err(|e| format!("Failed to look up {}: {}", table, e))
+}
+
+async fn decide(
+    conn: &mut SqliteConnection,
+    table: &str,
+    id: &str,
+    updated_at: DateTime<Utc>,
+    strategy: ImportStrategy,
+) -> Result<Action, String> {
+    let local: Option<DateTime<Utc>> =
+        sqlx::query_scalar(&format!("SELECT updated_at FROM {} WHERE id = ?", table))
+            .bind(id)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to look up {}: {}", table, e))?;
+    let Some(local) = local else {
+        return Ok(Action::Insert(id.to_string()));
+    };
+    Ok(match strategy {
+        ImportStrategy::Skip => Action::Skip,
+        ImportStrategy::OverwriteIfNewer if updated_at > local => Action::Update,
+        ImportStrategy::OverwriteIfNewer => Action::Skip,
+        ImportStrategy::Duplicate => Action::Insert(uuid::Uuid::new_v4().to_string()),
+    })
+}
+
+/// Adds missing lists and tags; returns exported tag ids mapped to local ones.
+async fn merge_lists_and_tags(
+    conn: &mut SqliteConnection,
+    data: &ExportData,
+) -> Result<HashMap<String, String>, String> {
+    for list in &data.lists {
+        sqlx::query(
+            "INSERT OR IGNORE INTO todo_lists (id, name, color, sort_order, archived, created_at)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&list.id)
+        .bind(&list.name)
+        .bind(&list.color)
+        .bind(list.sort_order)
+        .bind(list.archived)
+        .bind(list.created_at)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to import list: {}", e))?;
+    }
+
+    let mut tag_ids = HashMap::new();
+    for tag in &data.tags {
+        let local: Option<String> =
+            sqlx::query_scalar("SELECT id FROM tags WHERE id = ? OR name = ? ORDER BY id = ? DESC")
+                .bind(&tag.id)
+                .bind(&tag.name)
+                .bind(&tag.id)
+                .fetch_optional(&mut *conn)
+                .await
+                .map_err(|e| format!("Failed to look up tag: {}", e))?;
+        let id = match local {
+            Some(id) => id,
+            None => {
+                sqlx::query("INSERT INTO tags (id, name, created_at) VALUES (?, ?, ?)")
+                    .bind(&tag.id)
+                    .bind(&tag.name)
+                    .bind(tag.created_at)
+                    .execute(&mut *conn)
+                    .await
+                    .map_err(|e| format!("Failed to import tag: {}", e))?;
+                tag.id.clone()
+            }
+        };
+        tag_ids.insert(tag.id.clone(), id);
+    }
+    Ok(tag_ids)
+}
+
+async fn replace_tags(
+    conn: &mut SqliteConnection,
+    (join_table, item_column): (&str, &str),
+    item_id: &str,
+    exported: &[String],
+    tag_ids: &HashMap<String, String>,
+) -> Result<(), String> {
+    sqlx::query(&format!(
+        "DELETE FROM {} WHERE {} = ?",
+        join_table, item_column
+    ))
+    .bind(item_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to clear tags: {}", e))?;
+    for tag_id in exported.iter().filter_map(|id| tag_ids.get(id)) {
+        sqlx::query(&format!(
+            "INSERT OR IGNORE INTO {} ({}, tag_id) VALUES (?, ?)",
+            join_table, item_column
+        ))
+        .bind(item_id)
+        .bind(tag_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to import tags: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Inserts `todo` or replaces every column of the stored copy.
+pub async fn write_todo(conn: &mut SqliteConnection, todo: &Todo) -> Result<(), String> {
+    sqlx::query(
+        "INSERT INTO todos (id, title, content, remind_time, completed, created_at, notified, search_text, list_id, parent_id,
+         due_at, due_all_day, priority, remind_before_due_minutes, due_remind_at, due_notified,
+         recurrence, series_id, recurrence_index, occurrence_at, next_occurrence_at, handed_over, completed_at, archived, updated_at,
+         deleted_at, ical_uid)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT(id) DO UPDATE SET title = excluded.title, content = excluded.content, remind_time = excluded.remind_time,
+         completed = excluded.completed, created_at = excluded.created_at, notified = excluded.notified, search_text = excluded.search_text,
+         list_id = excluded.list_id, parent_id = excluded.parent_id, due_at = excluded.due_at, due_all_day = excluded.due_all_day,
+         priority = excluded.priority, remind_before_due_minutes = excluded.remind_before_due_minutes,
+         due_remind_at = excluded.due_remind_at, due_notified = excluded.due_notified, recurrence = excluded.recurrence,
+         series_id = excluded.series_id, recurrence_index = excluded.recurrence_index, occurrence_at = excluded.occurrence_at,
+         next_occurrence_at = CASE WHEN todos.handed_over THEN NULL ELSE excluded.next_occurrence_at END,
+         handed_over = todos.handed_over OR excluded.handed_over, completed_at = excluded.completed_at,
+         archived = excluded.archived, updated_at = excluded.updated_at, deleted_at = excluded.deleted_at,
+         ical_uid = excluded.ical_uid",
+    )
+    .bind(&todo.id)
+    .bind(&todo.title)
+    .bind(&todo.content)
+    .bind(todo.remind_time)
+    .bind(todo.completed)
+    .bind(todo.created_at)
+    .bind(todo.notified)
+    .bind(crate::html::to_plain_text(&todo.content))
+    .bind(&todo.list_id)
+    .bind(&todo.parent_id)
+    .bind(todo.due_at)
+    .bind(todo.due_all_day)
+    .bind(todo.priority)
+    .bind(todo.remind_before_due_minutes)
+    .bind(todo.due_remind_at())
+    .bind(todo.due_notified)
+    .bind(&todo.recurrence)
+    .bind(&todo.series_id)
+    .bind(todo.recurrence_index)
+    .bind(todo.occurrence_at)
+    .bind(if todo.handed_over {
+        None
+    } else {
+        crate::recurrence::next_occurrence(todo)
+    })
+    .bind(todo.handed_over)
+    .bind(todo.completed_at)
+    .bind(todo.archived)
+    .bind(todo.updated_at)
+    .bind(todo.deleted_at)
+    .bind(&todo.ical_uid)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to import todo: {}", e))?;
+    Ok(())
+}
+
+/// Writes a note; a locked one gets its sealed content and no plaintext.
+async fn write_note(
+    conn: &mut SqliteConnection,
+    note: &Note,
+    lock: Option<&NoteLock>,
+) -> Result<(), String> {
+    let search_text = if lock.is_some() {
+        String::new()
+    } else {
+        crate::html::to_plain_text(&note.content)
+    };
+    sqlx::query(
+        "INSERT INTO notes (id, title, content, created_at, updated_at, deleted_at, search_text, locked, lock_params, locked_content)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT(id) DO UPDATE SET title = excluded.title, content = excluded.content,
+         created_at = excluded.created_at, updated_at = excluded.updated_at, deleted_at = excluded.deleted_at,
+         search_text = excluded.search_text, locked = excluded.locked, lock_params = excluded.lock_params,
+         locked_content = excluded.locked_content",
+    )
+    .bind(&note.id)
+    .bind(&note.title)
+    .bind(if lock.is_some() { "" } else { note.content.as_str() })
+    .bind(note.created_at)
+    .bind(note.updated_at)
+    .bind(note.deleted_at)
+    .bind(search_text)
+    .bind(lock.is_some())
+    .bind(lock.map(|l| &l.lock_params))
+    .bind(lock.map(|l| &l.locked_content))
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to import note: {}", e))?;
+
+    if lock.is_some() {
+        // Same as locking: no plaintext may stay behind in the history.
+        sqlx::query("DELETE FROM note_revisions WHERE note_id = ?")
+            .bind(&note.id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to clear note revisions: {}", e))?;
+        crate::links::sync_note_links(conn, &note.id, "").await
+    } else {
+        crate::attachments::sync_note_references(&mut *conn, &note.id, &note.content).await?;
+        crate::links::sync_note_links(conn, &note.id, &note.content).await
+    }
+}
+
+/// Puts the exported blobs into the store under `app_dir`. A blob that does
+/// not match its hash is left to garbage collection.
+async fn import_attachments(
+    conn: &mut SqliteConnection,
+    app_dir: &Path,
+    data: &ExportData,
+) -> Result<(), String> {
+    let dir = crate::attachments::attachments_dir(app_dir);
+    for attachment in &data.attachments {
+        let bytes = BASE64
+            .decode(&attachment.data_base64)
+            .map_err(|e| format!("Failed to decode attachment: {}", e))?;
+        let hash = crate::attachments::store_blob(&dir, &bytes)?;
+        if hash == attachment.hash {
+            crate::attachments::insert_attachment(
+                &mut *conn,
+                &hash,
+                &attachment.mime,
+                bytes.len() as i64,
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Adds the exported history of the notes written, keyed by exported id.
+/// Revisions already held are not added again.
+async fn import_revisions(
+    conn: &mut SqliteConnection,
+    data: &ExportData,
+    note_ids: &HashMap<String, String>,
+) -> Result<(), String> {
+    for revision in &data.note_revisions {
+        let Some(note_id) = note_ids.get(&revision.note_id) else {
+            continue;
+        };
+        sqlx::query(
+            "INSERT INTO note_revisions (note_id, title, content, created_at, saved_at)
+             SELECT ?, ?, ?, ?, ? WHERE NOT EXISTS (
+                SELECT 1 FROM note_revisions WHERE note_id = ? AND created_at = ? AND content = ?
+             )",
+        )
+        .bind(note_id)
+        .bind(&revision.title)
+        .bind(crate::attachments::to_references(&revision.content))
+        .bind(revision.created_at)
+        .bind(revision.saved_at)
+        .bind(note_id)
+        .bind(revision.created_at)
+        .bind(crate::attachments::to_references(&revision.content))
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to import note revision: {}", e))?;
+    }
+
+    for note_id in note_ids.values() {
+        sqlx::query(
+            "DELETE FROM note_revisions WHERE note_id = ? AND id NOT IN (
+                SELECT id FROM note_revisions WHERE note_id = ? ORDER BY id DESC LIMIT ?
+            )",
+        )
+        .bind(note_id)
+        .bind(note_id)
+        .bind(crate::revisions::MAX_REVISIONS_PER_NOTE)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to prune revisions: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Imports `data` and returns the report along with the ids of the notes it
+/// wrote. Attachment blobs are stored under `app_dir`.
+pub async fn import(
+    pool: &SqlitePool,
+    app_dir: &Path,
+    data: &ExportData,
+    strategy: ImportStrategy,
+) -> Result<(ImportReport, Vec<String>), String> {
+    let mut report = ImportReport {
+        inserted: 0,
+        updated: 0,
+        skipped: 0,
+    };
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| format!("Failed to begin transaction: {}", e))?;
+    // Subtasks may come before their parents.
+    sqlx::query("PRAGMA defer_foreign_keys = ON")
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to defer foreign keys: {}", e))?;
+
+    let tag_ids = merge_lists_and_tags(&mut tx, data).await?;
+    // Before the notes, so their references find the blobs.
+    import_attachments(&mut tx, app_dir, data).await?;
+
+    // Decide everything first, so references can follow duplicated ids.
+    let mut todo_actions = Vec::new();
+    let mut todo_ids = HashMap::new();
+    for todo in &data.todos {
+        let action = decide(&mut tx, "todos", &todo.id, todo.updated_at, strategy).await?;
+        let id = match &action {
+            Action::Insert(id) => id.clone(),
+            _ => todo.id.clone(),
+        };
+        todo_ids.insert(todo.id.clone(), id);
+        todo_actions.push(action);
+    }
+
+    // Duplicated occurrences form a new series, the unique index on
+    // (series_id, recurrence_index) would reject them otherwise.
+    let mut series_ids: HashMap<String, String> = HashMap::new();
+    for (todo, action) in data.todos.iter().zip(todo_actions) {
+        let id = match action {
+            Action::Skip => {
+                report.skipped += 1;
+                continue;
+            }
+            Action::Update => {
+                report.updated += 1;
+                todo.id.clone()
+            }
+            Action::Insert(id) => {
+                report.inserted += 1;
+                id
+            }
+        };
+
+        let mut todo = todo.clone();
+        if id != todo.id {
+            // The UID stays with the original, see `ical_uid`.
+            todo.ical_uid = None;
+            todo.series_id = todo.series_id.map(|series| {
+                series_ids
+                    .entry(series)
+                    .or_insert_with(|| uuid::Uuid::new_v4().to_string())
+                    .clone()
+            });
+        }
+        todo.id = id;
+        todo.parent_id = match todo.parent_id.take() {
+            Some(parent) if todo_ids.contains_key(&parent) => todo_ids.get(&parent).cloned(),
+            Some(parent) if exists(&mut tx, "todos", &parent).await? => Some(parent),
+            _ => None,
+        };
+        if let Some(list_id) = todo.list_id.take() {
+            if exists(&mut tx, "todo_lists", &list_id).await? {
+                todo.list_id = Some(list_id);
+            }
+        }
+
+        write_todo(&mut tx, &todo).await?;
+        replace_tags(
+            &mut tx,
+            ("todo_tags", "todo_id"),
+            &todo.id,
+            &todo.tag_ids,
+            &tag_ids,
+        )
+        .await?;
+    }
+
+    let locks: HashMap<&str, &NoteLock> = data
+        .note_locks
+        .iter()
+        .map(|lock| (lock.note_id.as_str(), lock))
+        .collect();
+    // Both maps only hold items that exist once the import is done.
+    let mut note_ids = HashMap::new();
+    let mut revised = HashMap::new();
+    let mut written = Vec::new();
+    for note in &data.notes {
+        let lock = locks.get(note.id.as_str()).copied();
+        let action = decide(&mut tx, "notes", &note.id, note.updated_at, strategy).await?;
+        let id = match action {
+            // Sealed content is bound to the note id and cannot move to
+            // another one; without it there is nothing to restore.
+            Action::Insert(id) if note.locked && (lock.is_none() || id != note.id) => {
+                report.skipped += 1;
+                continue;
+            }
+            Action::Update if note.locked && lock.is_none() => {
+                report.skipped += 1;
+                continue;
+            }
+            Action::Skip => {
+                report.skipped += 1;
+                note_ids.insert(note.id.clone(), note.id.clone());
+                continue;
+            }
+            Action::Update => {
+                report.updated += 1;
+                note.id.clone()
+            }
+            Action::Insert(id) => {
+                report.inserted += 1;
+                id
+            }
+        };
+        note_ids.insert(note.id.clone(), id.clone());
+
+        let exported_id = note.id.clone();
+        let mut note = note.clone();
+        note.id = id;
+        note.content = crate::attachments::to_references(&note.content);
+        write_note(&mut tx, &note, lock.filter(|_| note.locked)).await?;
+        replace_tags(
+            &mut tx,
+            ("note_tags", "note_id"),
+            &note.id,
+            &note.tag_ids,
+            &tag_ids,
+        )
+        .await?;
+        if !note.locked {
+            revised.insert(exported_id.clone(), note.id.clone());
+        }
+        written.push(note);
+    }
+    import_revisions(&mut tx, data, &revised).await?;
+
+    for link in &data.todo_notes {
+        let (Some(todo_id), Some(note_id)) =
+            (todo_ids.get(&link.todo_id), note_ids.get(&link.note_id))
+        else {
+            continue;
+        };
+        sqlx::query(
+            "INSERT OR IGNORE INTO todo_notes (todo_id, note_id, created_at) VALUES (?, ?, ?)",
+        )
+        .bind(todo_id)
+        .bind(note_id)
+        .bind(link.created_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to import todo link: {}", e))?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("Failed to commit import: {}", e))?;
+
+    for note in written.iter().filter(|note| !note.locked) {
+        crate::revisions::record_revision(pool, note, true).await?;
+    }
+    Ok((report, written.into_iter().map(|note| note.id).collect()))
+}
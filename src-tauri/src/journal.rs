@@ -0,0 +1,333 @@
+// Undo/redo for data changes made through the commands. Each change is kept
+// as a pair of snapshots of the rows it touched, taken before and after; undo
+// writes the "before" rows back and redo the "after" rows. Rows are captured
+// generically as JSON objects keyed by column name, so schema changes need no
+// code here. The journal lives in memory and is shared by every window.
+//
+// Not journaled: permanent deletes (purging drops the item's entries), note
+// passwords (locking or unlocking drops the note's entries so no plaintext
+// copy survives in the journal), settings, attachments and encryption.
+
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::VecDeque;
+use std::sync::{Mutex, PoisonError};
+
+const JOURNAL_LIMIT: usize = 100;
+
+/// Something a command changes, together with the rows that belong to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Todo(String),
+    Note(String),
+    List(String),
+    Tag(String),
+}
+
+impl Target {
+    /// `(table, column)` pairs holding the target's rows; the first is its own row.
+    fn scopes(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Target::Todo(_) => &[
+                ("todos", "id"),
+                ("todo_tags", "todo_id"),
+                ("todo_notes", "todo_id"),
+            ],
+            Target::Note(_) => &[
+                ("notes", "id"),
+                ("note_tags", "note_id"),
+                ("todo_notes", "note_id"),
+                ("note_links", "source_id"),
+                ("note_attachments", "note_id"),
+            ],
+            Target::List(_) => &[("todo_lists", "id")],
+            Target::Tag(_) => &[
+                ("tags", "id"),
+                ("todo_tags", "tag_id"),
+                ("note_tags", "tag_id"),
+            ],
+        }
+    }
+
+    fn key(&self) -> &str {
+        match self {
+            Target::Todo(id) | Target::Note(id) | Target::List(id) | Target::Tag(id) => id,
+        }
+    }
+}
+
+/// The rows of `table` whose `column` equals `key`, as JSON objects.
+#[derive(Debug, Clone)]
+struct Part {
+    table: &'static str,
+    column: &'static str,
+    key: String,
+    rows: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    label: String,
+    targets: Vec<Target>,
+    before: Vec<Part>,
+    after: Vec<Part>,
+}
+
+#[derive(Default)]
+struct JournalState {
+    undo: VecDeque<Entry>,
+    redo: Vec<Entry>,
+}
+
+#[derive(Default)]
+pub struct Journal(Mutex<JournalState>);
+
+impl Journal {
+    fn state(&self) -> std::sync::MutexGuard<'_, JournalState> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn push(&self, entry: Entry) {
+        let mut state = self.state();
+        state.redo.clear();
+        state.undo.push_back(entry);
+        if state.undo.len() > JOURNAL_LIMIT {
+            state.undo.pop_front();
+        }
+    }
+
+    /// Drops every entry that touches `target`.
+    pub fn forget(&self, target: &Target) {
+        let mut state = self.state();
+        state.undo.retain(|entry| !entry.targets.contains(target));
+        state.redo.retain(|entry| !entry.targets.contains(target));
+    }
+
+    pub fn clear(&self) {
+        let mut state = self.state();
+        state.undo.clear();
+        state.redo.clear();
+    }
+
+    /// Labels of the changes `undo` and `redo` would apply next.
+    pub fn status(&self) -> (Option<String>, Option<String>) {
+        let state = self.state();
+        (
+            state.undo.back().map(|entry| entry.label.clone()),
+            state.redo.last().map(|entry| entry.label.clone()),
+        )
+    }
+}
+
+async fn columns(conn: &mut SqliteConnection, table: &str) -> Result<Vec<String>, String> {
+    sqlx::query_scalar(&format!("SELECT name FROM pragma_table_info('{}')", table))
+        .fetch_all(conn)
+        .await
+        .map_err(|e| format!("Failed to read columns of {}: {}", table, e))
+}
+
+async fn capture(conn: &mut SqliteConnection, targets: &[Target]) -> Result<Vec<Part>, String> {
+    let mut parts = Vec::new();
+    for target in targets {
+        for &(table, column) in target.scopes() {
+            let fields = columns(&mut *conn, table)
+                .await?
+                .iter()
+                .map(|c| format!("'{0}', \"{0}\"", c))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let rows = sqlx::query_scalar(&format!(
+                "SELECT json_object({}) FROM {} WHERE {} = ?",
+                fields, table, column
+            ))
+            .bind(target.key())
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to snapshot {}: {}", table, e))?;
+            parts.push(Part {
+                table,
+                column,
+                key: target.key().to_string(),
+                rows,
+            });
+        }
+    }
+    Ok(parts)
+}
+
+/// Writes `parts` back. An item's own row is upserted (or deleted if it did
+/// not exist), so rows referencing it survive; the other rows are replaced.
+async fn restore(conn: &mut SqliteConnection, parts: &[Part]) -> Result<(), String> {
+    let now = chrono::Utc::now();
+    for part in parts {
+        let cols = columns(&mut *conn, part.table).await?;
+        let names = cols
+            .iter()
+            .map(|c| format!("\"{}\"", c))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let values = cols
+            .iter()
+            .map(|c| format!("json_extract(j.row, '$.{}')", c))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let own_row = part.column == "id";
+
+        if own_row && part.rows.is_empty() {
+            sqlx::query(&format!("DELETE FROM {} WHERE id = ?", part.table))
+                .bind(&part.key)
+                .execute(&mut *conn)
+                .await
+                .map_err(|e| format!("Failed to restore {}: {}", part.table, e))?;
+            continue;
+        }
+
+        let sql = if own_row {
+            let updates = cols
+                .iter()
+                .filter(|c| *c != "id")
+                .map(|c| format!("\"{0}\" = excluded.\"{0}\"", c))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!(
+                "INSERT INTO {} ({}) SELECT {} FROM (SELECT ? AS row) j WHERE true
+                 ON CONFLICT(id) DO UPDATE SET {}",
+                part.table, names, values, updates
+            )
+        } else {
+            sqlx::query(&format!(
+                "DELETE FROM {} WHERE {} = ?",
+                part.table, part.column
+            ))
+            .bind(&part.key)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to restore {}: {}", part.table, e))?;
+            format!(
+                "INSERT OR REPLACE INTO {} ({}) SELECT {} FROM (SELECT ? AS row) j",
+                part.table, names, values
+            )
+        };
+        for row in &part.rows {
+            sqlx::query(&sql)
+                .bind(row)
+                .execute(&mut *conn)
+                .await
+                .map_err(|e| format!("Failed to restore {}: {}", part.table, e))?;
+        }
+
+        // A new version, so windows holding the undone copy cannot save over it.
+        if own_row && cols.iter().any(|c| c == "updated_at") {
+            sqlx::query(&format!(
+                "UPDATE {} SET updated_at = ? WHERE id = ?",
+                part.table
+            ))
+            .bind(now)
+            .bind(&part.key)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to restore {}: {}", part.table, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Rows captured before a change, waiting for `Pending::record`.
+pub struct Pending {
+    label: String,
+    targets: Vec<Target>,
+    before: Vec<Part>,
+}
+
+/// Snapshots `targets` ahead of a change described by `label`.
+pub async fn begin(
+    pool: &SqlitePool,
+    label: &str,
+    targets: Vec<Target>,
+) -> Result<Pending, String> {
+    let mut conn = pool
+        .acquire()
+        .await
+        .map_err(|e| format!("Failed to acquire connection: {}", e))?;
+    let before = capture(&mut conn, &targets).await?;
+    Ok(Pending {
+        label: label.to_string(),
+        targets,
+        before,
+    })
+}
+
+impl Pending {
+    /// Adds an item the change created, which had no rows before it.
+    pub fn created(&mut self, target: Target) {
+        for &(table, column) in target.scopes() {
+            self.before.push(Part {
+                table,
+                column,
+                key: target.key().to_string(),
+                rows: Vec::new(),
+            });
+        }
+        self.targets.push(target);
+    }
+
+    /// Snapshots the targets again and journals the change.
+    pub async fn record(self, pool: &SqlitePool, journal: &Journal) -> Result<(), String> {
+        let mut conn = pool
+            .acquire()
+            .await
+            .map_err(|e| format!("Failed to acquire connection: {}", e))?;
+        let after = capture(&mut conn, &self.targets).await?;
+        journal.push(Entry {
+            label: self.label,
+            targets: self.targets,
+            before: self.before,
+            after,
+        });
+        Ok(())
+    }
+}
+
+async fn apply(pool: &SqlitePool, parts: &[Part]) -> Result<(), String> {
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| format!("Failed to begin transaction: {}", e))?;
+    // Rows come back in snapshot order, not dependency order; check the
+    // foreign keys once everything is in place.
+    sqlx::query("PRAGMA defer_foreign_keys = ON")
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to defer foreign keys: {}", e))?;
+    restore(&mut tx, parts).await?;
+    tx.commit()
+        .await
+        .map_err(|e| format!("Failed to commit: {}", e))
+}
+
+/// Reverts the most recent change; returns its label, or `None` when there is
+/// nothing to undo. A change that cannot be reverted is dropped, so the
+/// older ones stay reachable.
+pub async fn undo(pool: &SqlitePool, journal: &Journal) -> Result<Option<String>, String> {
+    let Some(entry) = journal.state().undo.pop_back() else {
+        return Ok(None);
+    };
+    if let Err(e) = apply(pool, &entry.before).await {
+        return Err(format!("Failed to undo \"{}\": {}", entry.label, e));
+    }
+    let label = entry.label.clone();
+    journal.state().redo.push(entry);
+    Ok(Some(label))
+}
+
+/// Re-applies the most recently undone change, see `undo`.
+pub async fn redo(pool: &SqlitePool, journal: &Journal) -> Result<Option<String>, String> {
+    let Some(entry) = journal.state().redo.pop() else {
+        return Ok(None);
+    };
+    if let Err(e) = apply(pool, &entry.after).await {
+        return Err(format!("Failed to redo \"{}\": {}", entry.label, e));
+    }
+    let label = entry.label.clone();
+    journal.state().undo.push_back(entry);
+    Ok(Some(label))
+}
@@ -1,8 +1,29 @@
+mod attachments;
+mod backup;
+mod batch;
 mod commands;
 mod db;
+mod encryption;
+mod export;
+mod html;
+mod ical;
+mod import;
+mod journal;
+mod links;
+mod maintenance;
 mod migration;
 mod models;
+mod note_lock;
 mod notification;
+mod paging;
+mod recovery;
+mod recurrence;
+mod revisions;
+mod schema;
+mod search;
+mod stats;
+mod subtasks;
+mod tags;
 mod tray;
 
 
@@ -20,32 +41,42 @@ pub fn run() {
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_sql::Builder::default().build())
         .plugin(tauri_plugin_sql::Builder::default().build())
+        // 笔记附件：macOS/Linux 为 silto-attachment://localhost/<hash>，Windows 为 http://silto-attachment.localhost/<hash>
+        .register_asynchronous_uri_scheme_protocol(attachments::URL_SCHEME, |ctx, request, responder| {
+            let app = ctx.app_handle().clone();
+            let path = request.uri().path().to_string();
+            tauri::async_runtime::spawn(async move {
+                responder.respond(attachments::serve(&app, &path).await);
+            });
+        })
         .setup(|app| {
             // 初始化数据库
             let db = tauri::async_runtime::block_on(db::init_db(app.handle()))
-                 .map_err(|e| Box::new(std::io::Error::new(std::io::ErrorKind::Other, e)) as Box<dyn std::error::Error>)?;
+                 .map_err(|e| Box::new(std::io::Error::other(e)) as Box<dyn std::error::Error>)?;
             
-            // 检查并迁移数据
-            tauri::async_runtime::block_on(migration::check_and_migrate_from_json(app.handle(), &db))
-                 .map_err(|e| Box::new(std::io::Error::new(std::io::ErrorKind::Other, e)) as Box<dyn std::error::Error>)?;
+            // 检查并迁移数据（加密数据库在解锁后再检查）
+            if !db.is_locked() {
+                tauri::async_runtime::block_on(migration::check_and_migrate_from_json(app.handle(), &db))
+                     .map_err(|e| Box::new(std::io::Error::other(e)) as Box<dyn std::error::Error>)?;
+            }
 
             app.manage(db);
+            app.manage(note_lock::UnlockedNotes::default());
+            app.manage(journal::Journal::default());
 
             // 创建系统托盘
             tray::create_tray(app.handle())?;
 
             // Apply vibrancy (MacOS only) - 仅对主窗口应用，悬浮窗使用 CSS 模糊以支持异形（箭头）
+            #[cfg(target_os = "macos")]
             if let Some(window) = app.get_webview_window("main") {
-                #[cfg(target_os = "macos")]
-                {
-                    use window_vibrancy::{apply_vibrancy, NSVisualEffectMaterial};
-                    let _ = apply_vibrancy(
-                        &window,
-                        NSVisualEffectMaterial::UnderWindowBackground,
-                        None,
-                        Some(16.0),
-                    );
-                }
+                use window_vibrancy::{apply_vibrancy, NSVisualEffectMaterial};
+                let _ = apply_vibrancy(
+                    &window,
+                    NSVisualEffectMaterial::UnderWindowBackground,
+                    None,
+                    Some(16.0),
+                );
             }
 
             // Apply vibrancy to popup window
@@ -56,15 +87,77 @@ pub fn run() {
                 notification::check_reminders(app_handle).await;
             });
 
+            // 启动回收站清理等后台维护任务
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                maintenance::run_maintenance(app_handle).await;
+            });
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             commands::get_todos,
             commands::save_todo,
+            commands::set_todo_archived,
+            commands::apply_todo_batch,
             commands::delete_todo,
+            commands::skip_occurrence,
             commands::get_notes,
             commands::save_note,
             commands::delete_note,
+            commands::add_attachment,
+            commands::get_attachment,
+            commands::gc_attachments,
+            commands::get_note_revisions,
+            commands::diff_note_revisions,
+            commands::restore_note_revision,
+            commands::set_note_password,
+            commands::unlock_note,
+            commands::lock_note,
+            commands::remove_note_password,
+            commands::get_backlinks,
+            commands::get_unresolved_links,
+            commands::link_todo_note,
+            commands::unlink_todo_note,
+            commands::get_todo_notes,
+            commands::get_note_todos,
+            commands::create_todo_from_selection,
+            commands::get_trash,
+            commands::restore_todo,
+            commands::restore_note,
+            commands::purge_todo,
+            commands::purge_note,
+            commands::empty_trash,
+            commands::search,
+            commands::get_statistics,
+            commands::get_lists,
+            commands::save_list,
+            commands::reorder_lists,
+            commands::delete_list,
+            commands::get_tags,
+            commands::create_tag,
+            commands::rename_tag,
+            commands::merge_tags,
+            commands::delete_tag,
+            commands::add_todo_tag,
+            commands::remove_todo_tag,
+            commands::add_note_tag,
+            commands::remove_note_tag,
+            commands::undo,
+            commands::redo,
+            commands::get_journal_status,
+            commands::get_encryption_status,
+            commands::unlock_database,
+            commands::take_recovery_report,
+            commands::list_backups,
+            commands::restore_backup,
+            commands::export_data,
+            commands::import_data,
+            commands::export_ics,
+            commands::import_ics,
+            commands::enable_encryption,
+            commands::change_passphrase,
+            commands::disable_encryption,
             commands::get_settings,
             commands::save_settings,
             commands::apply_vibrancy,
@@ -72,6 +165,8 @@ pub fn run() {
         .build(tauri::generate_context!())
         .expect("error while building tauri application")
         .run(|app, event| {
+            #[cfg(not(target_os = "macos"))]
+            let _ = (app, event);
             #[cfg(target_os = "macos")]
             if let tauri::RunEvent::Reopen {
                 has_visible_windows: _,
@@ -0,0 +1,142 @@
+// `[[Note Title]]` links between notes. Links are stored by target title and
+// resolved at query time, so they start working as soon as a note with that
+// title exists. Titles compare case-insensitively (ASCII), like SQLite NOCASE.
+
+use crate::models::Note;
+use sqlx::{SqliteConnection, SqlitePool};
+
+/// Link targets in `content`, in order of first appearance. `[[Title|label]]`
+/// links to `Title`.
+pub fn parse_links(content: &str) -> Vec<String> {
+    let text = crate::html::to_plain_text(content);
+    let mut targets: Vec<String> = Vec::new();
+    let mut rest = text.as_str();
+
+    while let Some(start) = rest.find("[[") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("]]") else {
+            break;
+        };
+        let inner = &rest[..end];
+        // `[[a [[b]]` links to `b`.
+        if inner.contains("[[") || inner.contains('\n') {
+            continue;
+        }
+        rest = &rest[end + 2..];
+
+        let target = inner.split('|').next().unwrap_or("").trim();
+        if !target.is_empty() && !targets.iter().any(|t| t.eq_ignore_ascii_case(target)) {
+            targets.push(target.to_string());
+        }
+    }
+    targets
+}
+
+/// Makes `note_links` match the links in the note's content.
+pub async fn sync_note_links(
+    conn: &mut SqliteConnection,
+    note_id: &str,
+    content: &str,
+) -> Result<(), String> {
+    sqlx::query("DELETE FROM note_links WHERE source_id = ?")
+        .bind(note_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to clear note links: {}", e))?;
+
+    for target in parse_links(content) {
+        sqlx::query("INSERT OR IGNORE INTO note_links (source_id, target_title) VALUES (?, ?)")
+            .bind(note_id)
+            .bind(&target)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to record note link: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Points every `[[old_title]]` / `[[old_title|label]]` in the HTML `content`
+/// at `new_title` instead.
+pub fn rewrite_links(content: &str, old_title: &str, new_title: &str) -> String {
+    let old = format!("[[{}", crate::html::escape_text(old_title));
+    let new = format!("[[{}", crate::html::escape_text(new_title));
+    let mut out = String::with_capacity(content.len());
+    let mut rest = content;
+
+    while let Some(start) = rest.find("[[") {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let matches = rest
+            .as_bytes()
+            .get(..old.len())
+            .is_some_and(|head| head.eq_ignore_ascii_case(old.as_bytes()))
+            && (rest[old.len()..].starts_with("]]") || rest[old.len()..].starts_with('|'));
+        if matches {
+            out.push_str(&new);
+            rest = &rest[old.len()..];
+        } else {
+            out.push_str("[[");
+            rest = &rest[2..];
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Rewrites links to `old_title` in every other live note that has one.
+pub async fn rename_links(
+    pool: &SqlitePool,
+    note_id: &str,
+    old_title: &str,
+    new_title: &str,
+) -> Result<(), String> {
+    let sources = sqlx::query_as::<_, Note>(
+        "SELECT * FROM notes WHERE deleted_at IS NULL AND id != ? AND id IN (
+            SELECT source_id FROM note_links WHERE target_title = ?
+        )",
+    )
+    .bind(note_id)
+    .bind(old_title)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch linking notes: {}", e))?;
+
+    for mut note in sources {
+        note.content = rewrite_links(&note.content, old_title, new_title);
+        note.updated_at = chrono::Utc::now();
+
+        let mut conn = pool
+            .acquire()
+            .await
+            .map_err(|e| format!("Failed to acquire connection: {}", e))?;
+        sqlx::query("UPDATE notes SET content = ?, search_text = ?, updated_at = ? WHERE id = ?")
+            .bind(&note.content)
+            .bind(crate::html::to_plain_text(&note.content))
+            .bind(note.updated_at)
+            .bind(&note.id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to rewrite note links: {}", e))?;
+        sync_note_links(&mut conn, &note.id, &note.content).await?;
+        drop(conn);
+
+        crate::revisions::record_revision(pool, &note, true).await?;
+    }
+    Ok(())
+}
+
+/// Fills `note_links` for notes saved before links were tracked.
+pub async fn backfill_links(conn: &mut SqliteConnection) -> Result<(), String> {
+    let notes: Vec<(String, String)> =
+        sqlx::query_as("SELECT id, content FROM notes WHERE content LIKE '%[[%]]%'")
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to load notes for link backfill: {}", e))?;
+
+    for (id, content) in notes {
+        sync_note_links(conn, &id, &content).await?;
+    }
+    Ok(())
+}
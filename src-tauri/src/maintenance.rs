@@ -0,0 +1,102 @@
+use crate::db::Database;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use sqlx::SqlitePool;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+
+/// Permanently removes every trashed todo and note deleted at or before `cutoff`.
+pub async fn purge_trash_before(pool: &SqlitePool, cutoff: DateTime<Utc>) -> Result<(), String> {
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| format!("Failed to begin transaction: {}", e))?;
+    // Subtasks that stay (live, or trashed later) become top-level todos
+    // instead of following their parent through the cascade on `parent_id`.
+    sqlx::query(
+        "UPDATE todos SET parent_id = NULL
+         WHERE (deleted_at IS NULL OR deleted_at > ?)
+           AND parent_id IN (SELECT id FROM todos WHERE deleted_at IS NOT NULL AND deleted_at <= ?)",
+    )
+    .bind(cutoff)
+    .bind(cutoff)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| format!("Failed to detach subtasks: {}", e))?;
+    sqlx::query("DELETE FROM todos WHERE deleted_at IS NOT NULL AND deleted_at <= ?")
+        .bind(cutoff)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to purge trashed todos: {}", e))?;
+    tx.commit()
+        .await
+        .map_err(|e| format!("Failed to commit purge: {}", e))?;
+
+    sqlx::query("DELETE FROM notes WHERE deleted_at IS NOT NULL AND deleted_at <= ?")
+        .bind(cutoff)
+        .execute(pool)
+        .await
+        .map_err(|e| format!("Failed to purge trashed notes: {}", e))?;
+
+    Ok(())
+}
+
+/// Archives every live todo completed at or before `cutoff`. Todos completed
+/// before completion times were recorded are left alone.
+pub async fn archive_completed_before(
+    pool: &SqlitePool,
+    cutoff: DateTime<Utc>,
+) -> Result<(), String> {
+    sqlx::query(
+        "UPDATE todos SET archived = 1, updated_at = ?
+         WHERE completed = 1 AND archived = 0 AND deleted_at IS NULL
+           AND completed_at IS NOT NULL AND completed_at <= ?",
+    )
+    .bind(Utc::now())
+    .bind(cutoff)
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Failed to archive completed todos: {}", e))?;
+    Ok(())
+}
+
+async fn run_once(app: &AppHandle) -> Result<(), String> {
+    let settings = crate::commands::get_settings(app.clone()).await?;
+    let db: State<Database> = app.state();
+    // Nothing to do until the database is unlocked.
+    if db.is_locked() {
+        return Ok(());
+    }
+
+    if settings.trash_retention_days > 0 {
+        let cutoff = Utc::now() - ChronoDuration::days(i64::from(settings.trash_retention_days));
+        purge_trash_before(&db.pool()?, cutoff).await?;
+    }
+
+    if settings.auto_archive_days > 0 {
+        let cutoff = Utc::now() - ChronoDuration::days(i64::from(settings.auto_archive_days));
+        archive_completed_before(&db.pool()?, cutoff).await?;
+    }
+
+    let attachments_dir = crate::attachments::app_attachments_dir(app)?;
+    crate::attachments::collect_garbage(&db.pool()?, &attachments_dir).await?;
+
+    crate::backup::run_scheduled(
+        &db.pool()?,
+        &db.app_dir,
+        settings.backup_daily_keep,
+        settings.backup_weekly_keep,
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub async fn run_maintenance(app: AppHandle) {
+    loop {
+        if let Err(e) = run_once(&app).await {
+            eprintln!("Maintenance task failed: {}", e);
+        }
+
+        tokio::time::sleep(Duration::from_secs(60 * 60)).await;
+    }
+}
@@ -7,12 +7,12 @@ use tauri::{AppHandle, Manager};
 pub async fn check_and_migrate_from_json(app: &AppHandle, db: &Database) -> Result<(), String> {
     // Check if we need to migrate (tables empty)
     let todos_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM todos")
-        .fetch_one(&db.pool)
+        .fetch_one(&db.pool()?)
         .await
         .map_err(|e| format!("Failed to count todos: {}", e))?;
 
     let notes_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM notes")
-        .fetch_one(&db.pool)
+        .fetch_one(&db.pool()?)
         .await
         .map_err(|e| format!("Failed to count notes: {}", e))?;
 
@@ -46,7 +46,7 @@ pub async fn check_and_migrate_from_json(app: &AppHandle, db: &Database) -> Resu
             println!("Migrating {} todos...", todos.len());
             for todo in todos {
                 sqlx::query(
-                    "INSERT INTO todos (id, title, content, remind_time, completed, created_at) VALUES (?, ?, ?, ?, ?, ?)"
+                    "INSERT INTO todos (id, title, content, remind_time, completed, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?)"
                 )
                 .bind(todo.id)
                 .bind(todo.title)
@@ -54,7 +54,8 @@ pub async fn check_and_migrate_from_json(app: &AppHandle, db: &Database) -> Resu
                 .bind(todo.remind_time.map(|d| d.to_rfc3339())) // Store DateTime as string
                 .bind(todo.completed)
                 .bind(todo.created_at.to_rfc3339())
-                .execute(&db.pool)
+                .bind(todo.created_at.to_rfc3339())
+                .execute(&db.pool()?)
                 .await
                 .map_err(|e| format!("Failed to insert old todo: {}", e))?;
             }
@@ -74,13 +75,15 @@ pub async fn check_and_migrate_from_json(app: &AppHandle, db: &Database) -> Resu
                 .bind(note.content)
                 .bind(note.created_at.to_rfc3339())
                 .bind(note.updated_at.to_rfc3339())
-                .execute(&db.pool)
+                .execute(&db.pool()?)
                 .await
                 .map_err(|e| format!("Failed to insert old note: {}", e))?;
             }
         }
     }
 
+    crate::search::backfill_search_text(&db.pool()?).await?;
+
     println!("Migration completed successfully.");
     Ok(())
 }
@@ -9,9 +9,127 @@ pub struct Todo {
     pub remind_time: Option<DateTime<Utc>>,
     pub completed: bool,
     pub created_at: DateTime<Utc>,
+    /// Version of the todo: `save_todo` rejects a todo whose `updated_at` no
+    /// longer matches the stored one, and stamps a new one on success.
+    #[sqlx(default)]
+    #[serde(default)]
+    pub updated_at: DateTime<Utc>,
     #[sqlx(default)]
     #[serde(default)]
     pub notified: bool,
+    /// `None` means the todo has not been filed and shows up in the Inbox.
+    #[sqlx(default)]
+    #[serde(default)]
+    pub list_id: Option<String>,
+    #[sqlx(default)]
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    /// For all-day todos this is the start of the due day.
+    #[sqlx(default)]
+    #[serde(default)]
+    pub due_at: Option<DateTime<Utc>>,
+    #[sqlx(default)]
+    #[serde(default)]
+    pub due_all_day: bool,
+    #[sqlx(default)]
+    #[serde(default)]
+    pub priority: Priority,
+    /// Also remind this many minutes before `due_at`, independent of `remind_time`.
+    #[sqlx(default)]
+    #[serde(default)]
+    pub remind_before_due_minutes: Option<i64>,
+    #[sqlx(default)]
+    #[serde(default)]
+    pub due_notified: bool,
+    /// RFC 5545 RRULE value, e.g. `FREQ=WEEKLY;BYDAY=MO,TH`.
+    #[sqlx(default)]
+    #[serde(default)]
+    pub recurrence: Option<String>,
+    /// Shared by every occurrence of a recurring todo.
+    #[sqlx(default)]
+    #[serde(default)]
+    pub series_id: Option<String>,
+    #[sqlx(default)]
+    #[serde(default)]
+    pub recurrence_index: i64,
+    /// The slot the rule scheduled this occurrence for, even if it was moved.
+    #[sqlx(default)]
+    #[serde(default)]
+    pub occurrence_at: Option<DateTime<Utc>>,
+    /// Set once the following occurrence has been created; the todo never
+    /// schedules another one after that, even if its successor is purged.
+    #[sqlx(default)]
+    #[serde(default)]
+    pub handed_over: bool,
+    /// Set by `save_todo` when the todo is completed, cleared when it is reopened.
+    #[sqlx(default)]
+    #[serde(default)]
+    pub completed_at: Option<DateTime<Utc>>,
+    /// Hidden from `get_todos` unless archived todos are asked for.
+    #[sqlx(default)]
+    #[serde(default)]
+    pub archived: bool,
+    #[sqlx(default)]
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// UID of the iCalendar entry the todo was imported from; `.ics` exports
+    /// reuse it, other todos are exported under their `id`.
+    #[sqlx(default)]
+    #[serde(default)]
+    pub ical_uid: Option<String>,
+    /// Filled in by the listing commands; tags are changed through the tag commands.
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub tag_ids: Vec<String>,
+    /// Share of subtasks done (0.0 to 1.0); `None` when the todo has no subtasks.
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub progress: Option<f64>,
+    /// Subtasks, only populated when `get_todos` is asked for a tree.
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub children: Vec<Todo>,
+}
+
+impl Todo {
+    /// A fresh open todo in the Inbox.
+    pub fn new(title: String, content: String) -> Self {
+        Todo {
+            id: uuid::Uuid::new_v4().to_string(),
+            title,
+            content,
+            remind_time: None,
+            completed: false,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            notified: false,
+            list_id: None,
+            parent_id: None,
+            due_at: None,
+            due_all_day: false,
+            priority: Priority::None,
+            remind_before_due_minutes: None,
+            due_notified: false,
+            recurrence: None,
+            series_id: None,
+            recurrence_index: 0,
+            occurrence_at: None,
+            handed_over: false,
+            completed_at: None,
+            archived: false,
+            deleted_at: None,
+            ical_uid: None,
+            tag_ids: Vec::new(),
+            progress: None,
+            children: Vec::new(),
+        }
+    }
+
+    /// When the "remind before due" rule fires, if the todo has one.
+    pub fn due_remind_at(&self) -> Option<DateTime<Utc>> {
+        let minutes = self.remind_before_due_minutes?;
+        self.due_at.map(|due| due - chrono::Duration::minutes(minutes))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
@@ -20,7 +138,501 @@ pub struct Note {
     pub title: String,
     pub content: String,
     pub created_at: DateTime<Utc>,
+    /// Also the note's version; see `Todo::updated_at`.
     pub updated_at: DateTime<Utc>,
+    /// Password protected. `content` is empty except in what `unlock_note` returns.
+    #[sqlx(default)]
+    #[serde(default)]
+    pub locked: bool,
+    #[sqlx(default)]
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Filled in by the listing commands; tags are changed through the tag commands.
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub tag_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum ItemKind {
+    Todo,
+    Note,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct SearchHit {
+    pub kind: ItemKind,
+    pub id: String,
+    pub title: String,
+    /// Plain-text excerpt with matches wrapped in `<mark>` tags.
+    pub snippet: String,
+    /// Lower is better.
+    pub rank: f64,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "lowercase")]
+#[repr(i64)]
+pub enum Priority {
+    #[default]
+    None = 0,
+    Low = 1,
+    Medium = 2,
+    High = 3,
+}
+
+/// Id of the built-in list that collects unfiled todos; it cannot be deleted.
+pub const INBOX_LIST_ID: &str = "inbox";
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TodoList {
+    pub id: String,
+    pub name: String,
+    pub color: Option<String>,
+    pub sort_order: i64,
+    pub archived: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ListDeleteMode {
+    /// Keep the list's todos by refiling them into the Inbox.
+    MoveToInbox,
+    /// Send the list's todos to the trash along with the list.
+    Trash,
+}
+
+/// One step of `apply_todo_batch`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchOp {
+    Complete { id: String },
+    Uncomplete { id: String },
+    /// Moves the todo and its subtasks to the trash.
+    Delete { id: String },
+    /// `None` files the todo into the Inbox.
+    Move { id: String, list_id: Option<String> },
+    Tag { id: String, tag_id: String },
+    Untag { id: String, tag_id: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+    Applied,
+    Failed,
+    /// Succeeded on its own but was undone because another operation failed.
+    RolledBack,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchItemResult {
+    pub id: String,
+    pub status: BatchStatus,
+    pub error: Option<String>,
+}
+
+/// Outcome of `apply_todo_batch`, with one item per operation in order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchResult {
+    pub committed: bool,
+    pub items: Vec<BatchItemResult>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Tag {
+    pub id: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMatch {
+    /// Items carrying every requested tag.
+    #[default]
+    All,
+    /// Items carrying at least one requested tag.
+    Any,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TodoFilter {
+    pub tag_ids: Vec<String>,
+    pub tag_match: TagMatch,
+    /// Restrict to one list; the Inbox also matches unfiled todos.
+    pub list_id: Option<String>,
+    /// Nest subtasks under their parents instead of returning a flat list.
+    pub tree: bool,
+    /// Only open todos whose due date has passed.
+    pub overdue: bool,
+    /// Only todos at or above this priority.
+    pub min_priority: Option<Priority>,
+    /// Defaults to newest first.
+    pub sort: Option<TodoSort>,
+    pub include_archived: bool,
+    /// Only open (`false`) or completed (`true`) todos.
+    pub completed: Option<bool>,
+    /// Every term must appear in the title or content.
+    pub text: Option<String>,
+    /// `from` is inclusive, `to` exclusive; todos without the date are left out.
+    pub date_field: TodoDateField,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    /// Page size; `paging::DEFAULT_PAGE_SIZE` when unset.
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TodoDateField {
+    #[default]
+    CreatedAt,
+    DueAt,
+    RemindTime,
+    CompletedAt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TodoSortField {
+    CreatedAt,
+    DueAt,
+    Priority,
+    RemindTime,
+    Title,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TodoSort {
+    pub field: TodoSortField,
+    #[serde(default)]
+    pub direction: SortDirection,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoteFilter {
+    pub tag_ids: Vec<String>,
+    pub tag_match: TagMatch,
+    /// Defaults to most recently updated first.
+    pub sort: Option<NoteSort>,
+    /// Every term must appear in the title or content.
+    pub text: Option<String>,
+    /// `from` is inclusive, `to` exclusive.
+    pub date_field: NoteDateField,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub cursor: Option<String>,
+    /// See `TodoFilter::limit`.
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteSortField {
+    CreatedAt,
+    UpdatedAt,
+    Title,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteSort {
+    pub field: NoteSortField,
+    #[serde(default)]
+    pub direction: SortDirection,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteDateField {
+    CreatedAt,
+    #[default]
+    UpdatedAt,
+}
+
+/// One page of a listing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Matching rows across all pages.
+    pub total: i64,
+    /// Pass back as `cursor` for the next page; `None` on the last one.
+    pub next_cursor: Option<String>,
+}
+
+/// Error returned by `save_todo` and `save_note`. Serialized as
+/// `{ "kind": "conflict", "current": ... }` or `{ "kind": "failed", "message": ... }`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SaveError<T> {
+    /// The item was changed elsewhere since the caller loaded it; `current` is
+    /// the stored copy, which was left untouched.
+    Conflict { current: T },
+    Failed { message: String },
+}
+
+impl<T> From<String> for SaveError<T> {
+    fn from(message: String) -> Self {
+        SaveError::Failed { message }
+    }
+}
+
+impl<T> std::fmt::Display for SaveError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaveError::Conflict { .. } => write!(f, "Item was changed elsewhere"),
+            SaveError::Failed { message } => write!(f, "{}", message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct NoteRevision {
+    pub id: i64,
+    pub note_id: String,
+    pub title: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    /// Last autosave folded into this revision.
+    pub saved_at: DateTime<Utc>,
+}
+
+/// A `[[target_title]]` link in note `source_id` that points at no note.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct UnresolvedLink {
+    pub source_id: String,
+    pub source_title: String,
+    pub target_title: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Attachment {
+    /// SHA-256 of the file, hex encoded.
+    pub hash: String,
+    pub mime: String,
+    pub size: i64,
+    pub created_at: DateTime<Utc>,
+    /// URL of the blob on this platform, ready for an `src` attribute.
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttachmentData {
+    pub mime: String,
+    pub data_base64: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffLine {
+    pub op: DiffOp,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trash {
+    pub todos: Vec<Todo>,
+    pub notes: Vec<Note>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// `silto-export.json`, an `ExportData` dump.
+    Json,
+    /// One `.md` file per note under `notes/`.
+    Markdown,
+    /// `todos.csv`.
+    Csv,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TodoNoteLink {
+    pub todo_id: String,
+    pub note_id: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The sealed content of a locked note, see note_lock.rs.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct NoteLock {
+    pub note_id: String,
+    pub lock_params: String,
+    pub locked_content: String,
+}
+
+/// An attachment blob in the JSON export.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedAttachment {
+    /// SHA-256 of the file, hex encoded.
+    pub hash: String,
+    pub mime: String,
+    pub data_base64: String,
+}
+
+/// Everything in the database, trash included, as written to the JSON
+/// export, along with the attachment files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportData {
+    pub format_version: i64,
+    pub exported_at: DateTime<Utc>,
+    #[serde(default)]
+    pub lists: Vec<TodoList>,
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+    #[serde(default)]
+    pub todos: Vec<Todo>,
+    #[serde(default)]
+    pub notes: Vec<Note>,
+    #[serde(default)]
+    pub todo_notes: Vec<TodoNoteLink>,
+    #[serde(default)]
+    pub note_locks: Vec<NoteLock>,
+    /// Oldest first within each note.
+    #[serde(default)]
+    pub note_revisions: Vec<NoteRevision>,
+    #[serde(default)]
+    pub attachments: Vec<ExportedAttachment>,
+}
+
+/// What `import_data` does with a todo or note whose id is already taken.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStrategy {
+    /// Keep the local copy.
+    #[default]
+    Skip,
+    /// Replace the local copy if the imported one has a later `updated_at`.
+    OverwriteIfNewer,
+    /// Import it under a new id next to the local copy.
+    Duplicate,
+}
+
+/// Items handled by `import_data` or `import_ics`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub inserted: usize,
+    pub updated: usize,
+    pub skipped: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportReport {
+    pub dir: String,
+    /// Paths relative to `dir`.
+    pub files: Vec<String>,
+    /// Live todos and notes exported; the JSON dump also carries the trash.
+    pub todos: usize,
+    pub notes: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsPeriod {
+    #[default]
+    Day,
+    Week,
+    Month,
+}
+
+/// Activity within one day, week or month.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct StatsBucket {
+    /// Local date the period starts on, `YYYY-MM-DD`.
+    pub period_start: String,
+    pub created: i64,
+    pub completed: i64,
+    /// Completed after their due date.
+    pub completed_late: i64,
+    pub avg_completion_seconds: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Statistics {
+    /// Periods with any activity, oldest first.
+    pub buckets: Vec<StatsBucket>,
+    /// Across the whole range.
+    pub avg_completion_seconds: Option<f64>,
+    /// Open todos overdue right now.
+    pub overdue: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupKind {
+    Daily,
+    Weekly,
+    /// Taken right before a schema migration.
+    PreMigration,
+    /// The state replaced by a restore.
+    PreRestore,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupInfo {
+    /// File name in the backups folder; pass it to `restore_backup`.
+    pub name: String,
+    pub kind: BackupKind,
+    pub created_at: DateTime<Utc>,
+    pub size: u64,
+}
+
+/// What startup recovery copied out of a damaged database.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoveryReport {
+    /// Where the damaged file was moved; it is kept for manual rescue.
+    pub damaged_path: String,
+    pub tables: Vec<RecoveredTable>,
+    /// Integrity check findings and read errors, for support.
+    pub problems: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoveredTable {
+    pub table: String,
+    pub rows: i64,
+    /// Every row was read back; `false` means some were lost.
+    pub complete: bool,
+}
+
+/// Labels of the changes `undo` and `redo` would apply next, if any.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalStatus {
+    pub undo: Option<String>,
+    pub redo: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptionStatus {
+    pub encrypted: bool,
+    /// Encrypted and not yet unlocked this session; every other command fails until then.
+    pub locked: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +640,34 @@ pub struct Settings {
     pub left_click_action: LeftClickAction,
     pub theme: Theme,
     pub language: String,
+    /// Days a trashed todo or note is kept before it is purged; 0 keeps it forever.
+    #[serde(default = "default_trash_retention_days")]
+    pub trash_retention_days: u32,
+    /// Days after completion a todo is archived automatically; 0 turns this off.
+    #[serde(default = "default_auto_archive_days")]
+    pub auto_archive_days: u32,
+    /// Daily backups kept; 0 turns them off.
+    #[serde(default = "default_backup_daily_keep")]
+    pub backup_daily_keep: u32,
+    /// Weekly backups kept; 0 turns them off.
+    #[serde(default = "default_backup_weekly_keep")]
+    pub backup_weekly_keep: u32,
+}
+
+fn default_trash_retention_days() -> u32 {
+    30
+}
+
+fn default_auto_archive_days() -> u32 {
+    0
+}
+
+fn default_backup_daily_keep() -> u32 {
+    7
+}
+
+fn default_backup_weekly_keep() -> u32 {
+    4
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -51,6 +691,10 @@ impl Default for Settings {
             left_click_action: LeftClickAction::Todo,
             theme: Theme::System,
             language: "zh-CN".to_string(),
+            trash_retention_days: default_trash_retention_days(),
+            auto_archive_days: default_auto_archive_days(),
+            backup_daily_keep: default_backup_daily_keep(),
+            backup_weekly_keep: default_backup_weekly_keep(),
         }
     }
 }
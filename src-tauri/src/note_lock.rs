@@ -0,0 +1,220 @@
+// Password-protected notes. A locked note keeps an empty `content` and stores
+// its real content in `locked_content` as base64(nonce || ciphertext), sealed
+// with XChaCha20-Poly1305 under an Argon2id key derived from the note's own
+// password. The note id is bound as associated data, so ciphertext cannot be
+// moved to another note. Keys of notes unlocked this session are kept in
+// `UnlockedNotes` and never written anywhere.
+
+use crate::encryption::KdfParams;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use sqlx::SqliteConnection;
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+
+const NONCE_LEN: usize = 24;
+
+#[derive(Default)]
+pub struct UnlockedNotes(Mutex<HashMap<String, [u8; 32]>>);
+
+impl UnlockedNotes {
+    pub fn key(&self, note_id: &str) -> Option<[u8; 32]> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(note_id)
+            .copied()
+    }
+
+    fn insert(&self, note_id: &str, key: [u8; 32]) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(note_id.to_string(), key);
+    }
+
+    pub fn forget(&self, note_id: &str) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(note_id);
+    }
+
+    pub fn clear(&self) {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+}
+
+fn seal(key: &[u8; 32], note_id: &str, plaintext: &str) -> Result<String, String> {
+    let mut nonce = [0u8; NONCE_LEN];
+    getrandom::getrandom(&mut nonce).map_err(|e| format!("Failed to generate nonce: {}", e))?;
+
+    let ciphertext = XChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext.as_bytes(),
+                aad: note_id.as_bytes(),
+            },
+        )
+        .map_err(|_| "Failed to encrypt note".to_string())?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    Ok(BASE64.encode(sealed))
+}
+
+fn open(key: &[u8; 32], note_id: &str, sealed: &str) -> Result<String, String> {
+    let sealed = BASE64
+        .decode(sealed)
+        .map_err(|e| format!("Corrupt locked note: {}", e))?;
+    if sealed.len() < NONCE_LEN {
+        return Err("Corrupt locked note".to_string());
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+
+    // Authentication fails for a wrong password just as for tampered data.
+    let plaintext = XChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: note_id.as_bytes(),
+            },
+        )
+        .map_err(|_| "Incorrect password".to_string())?;
+    String::from_utf8(plaintext).map_err(|_| "Corrupt locked note".to_string())
+}
+
+pub async fn is_locked(conn: &mut SqliteConnection, note_id: &str) -> Result<bool, String> {
+    let locked: Option<bool> = sqlx::query_scalar("SELECT locked FROM notes WHERE id = ?")
+        .bind(note_id)
+        .fetch_optional(conn)
+        .await
+        .map_err(|e| format!("Failed to fetch note: {}", e))?;
+    Ok(locked.unwrap_or(false))
+}
+
+async fn fetch_sealed(
+    conn: &mut SqliteConnection,
+    note_id: &str,
+) -> Result<(KdfParams, String), String> {
+    let row: Option<(Option<String>, Option<String>)> =
+        sqlx::query_as("SELECT lock_params, locked_content FROM notes WHERE id = ? AND locked = 1")
+            .bind(note_id)
+            .fetch_optional(conn)
+            .await
+            .map_err(|e| format!("Failed to fetch note: {}", e))?;
+    let Some((Some(params), Some(sealed))) = row else {
+        return Err("Note is not locked".to_string());
+    };
+    let params =
+        serde_json::from_str(&params).map_err(|e| format!("Invalid lock parameters: {}", e))?;
+    Ok((params, sealed))
+}
+
+/// Encrypts the note's content under `password`. Its revision history and
+/// outgoing links hold plaintext, so both are dropped. Attachments stay in the
+/// shared blob store unencrypted.
+pub async fn set_password(
+    conn: &mut SqliteConnection,
+    note_id: &str,
+    password: &str,
+) -> Result<(), String> {
+    if password.is_empty() {
+        return Err("Password must not be empty".to_string());
+    }
+    let row: Option<(String, bool)> =
+        sqlx::query_as("SELECT content, locked FROM notes WHERE id = ?")
+            .bind(note_id)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to fetch note: {}", e))?;
+    let Some((content, locked)) = row else {
+        return Err("Note not found".to_string());
+    };
+    if locked {
+        return Err("Note is already locked".to_string());
+    }
+
+    let params = KdfParams::generate()?;
+    let key = params.derive_key(password)?;
+    let sealed = seal(&key, note_id, &content)?;
+    let params = serde_json::to_string(&params)
+        .map_err(|e| format!("Failed to serialize lock parameters: {}", e))?;
+
+    sqlx::query(
+        "UPDATE notes SET locked = 1, lock_params = ?, locked_content = ?, content = '', search_text = '',
+         updated_at = ? WHERE id = ?",
+    )
+    .bind(params)
+    .bind(sealed)
+    .bind(chrono::Utc::now())
+    .bind(note_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to lock note: {}", e))?;
+
+    sqlx::query("DELETE FROM note_revisions WHERE note_id = ?")
+        .bind(note_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to clear note revisions: {}", e))?;
+    sqlx::query("DELETE FROM note_links WHERE source_id = ?")
+        .bind(note_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to clear note links: {}", e))?;
+    Ok(())
+}
+
+/// Checks `password` and returns the decrypted content, remembering the key
+/// for the rest of the session.
+pub async fn unlock(
+    conn: &mut SqliteConnection,
+    unlocked: &UnlockedNotes,
+    note_id: &str,
+    password: &str,
+) -> Result<String, String> {
+    let (params, sealed) = fetch_sealed(conn, note_id).await?;
+    let key = params.derive_key(password)?;
+    let content = open(&key, note_id, &sealed)?;
+    unlocked.insert(note_id, key);
+    Ok(content)
+}
+
+/// Encrypts new content for a locked note with its session key.
+pub fn seal_content(
+    unlocked: &UnlockedNotes,
+    note_id: &str,
+    content: &str,
+) -> Result<String, String> {
+    let key = unlocked
+        .key(note_id)
+        .ok_or_else(|| "Note is locked".to_string())?;
+    seal(&key, note_id, content)
+}
+
+/// Decrypts the note for good and clears its password.
+pub async fn remove_password(
+    conn: &mut SqliteConnection,
+    unlocked: &UnlockedNotes,
+    note_id: &str,
+    password: &str,
+) -> Result<String, String> {
+    let content = unlock(conn, unlocked, note_id, password).await?;
+    sqlx::query(
+        "UPDATE notes SET locked = 0, lock_params = NULL, locked_content = NULL, content = ?, search_text = ?,
+         updated_at = ? WHERE id = ?",
+    )
+    .bind(&content)
+    .bind(crate::html::to_plain_text(&content))
+    .bind(chrono::Utc::now())
+    .bind(note_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to unlock note: {}", e))?;
+    unlocked.forget(note_id);
+    Ok(content)
+}
@@ -1,6 +1,7 @@
 use crate::db::Database;
 use crate::models::Todo;
-use chrono::Utc;
+use crate::recurrence;
+use chrono::{Local, Utc};
 use std::time::Duration;
 use tauri::{AppHandle, Manager, State};
 use tauri_plugin_notification::NotificationExt;
@@ -11,15 +12,33 @@ pub async fn check_reminders(app: AppHandle) {
 
         let now = Utc::now();
         let db_state: State<Database> = app.state();
+        // 数据库未解锁时跳过本轮
+        let Ok(pool) = db_state.pool() else {
+            continue;
+        };
+
+        // 循环任务：到了下一次的时间就生成下一条，即使上一条还没完成
+        let due_series = sqlx::query_as::<_, Todo>(
+            "SELECT * FROM todos WHERE deleted_at IS NULL AND completed = 0 AND next_occurrence_at IS NOT NULL AND next_occurrence_at <= ? LIMIT 100"
+        )
+        .bind(now)
+        .fetch_all(&pool)
+        .await;
+
+        if let Ok(todos) = due_series {
+            for todo in todos {
+                let _ = recurrence::spawn_next(&pool, &todo).await;
+            }
+        }
         
         // Optimize: Only fetch overdue reminders that haven't been notified
         // Filter by time in SQL to avoid loading future tasks into memory
         // LIMIT 100 to prevent memory spikes if there's a massive backlog of overdue tasks
         let todos_result = sqlx::query_as::<_, Todo>(
-            "SELECT * FROM todos WHERE completed = 0 AND notified = 0 AND remind_time IS NOT NULL AND remind_time <= ? LIMIT 100"
+            "SELECT * FROM todos WHERE deleted_at IS NULL AND completed = 0 AND notified = 0 AND remind_time IS NOT NULL AND remind_time <= ? LIMIT 100"
         )
         .bind(now)
-        .fetch_all(&db_state.pool)
+        .fetch_all(&pool)
         .await;
 
         if let Ok(todos) = todos_result {
@@ -32,15 +51,44 @@ pub async fn check_reminders(app: AppHandle) {
                     .notification()
                     .builder()
                             .title("Todo 提醒")
-                            .body(&format!("{}: {}", todo.title, todo.content))
+                            .body(format!("{}: {}", todo.title, todo.content))
                             .show();
 
                         // 更新 notified 状态
                         let _ = sqlx::query("UPDATE todos SET notified = 1 WHERE id = ?")
                             .bind(todo.id)
-                            .execute(&db_state.pool)
+                            .execute(&pool)
                             .await;
             }
         }
+
+        // "提前 N 分钟" 的截止提醒，due_remind_at 由 save_todo 预先算好
+        let due_result = sqlx::query_as::<_, Todo>(
+            "SELECT * FROM todos WHERE deleted_at IS NULL AND completed = 0 AND due_notified = 0 AND due_remind_at IS NOT NULL AND due_remind_at <= ? LIMIT 100"
+        )
+        .bind(now)
+        .fetch_all(&pool)
+        .await;
+
+        if let Ok(todos) = due_result {
+            for todo in todos {
+                let format = if todo.due_all_day { "%Y-%m-%d" } else { "%Y-%m-%d %H:%M" };
+                let due = todo
+                    .due_at
+                    .map(|due| due.with_timezone(&Local).format(format).to_string())
+                    .unwrap_or_default();
+                let _ = app
+                    .notification()
+                    .builder()
+                    .title("Todo 即将到期")
+                    .body(format!("{} ({})", todo.title, due))
+                    .show();
+
+                let _ = sqlx::query("UPDATE todos SET due_notified = 1 WHERE id = ?")
+                    .bind(todo.id)
+                    .execute(&pool)
+                    .await;
+            }
+        }
     }
 }
@@ -0,0 +1,149 @@
+// Keyset pagination for the listing commands. Lists are ordered by
+// `<sort> IS NULL, <sort> <dir>, <tiebreak> DESC, id` (or just the tiebreak
+// and id without a sort), and a cursor holds those values for the last row
+// of a page, so inserts and deletes between requests never shift the pages.
+
+use crate::models::SortDirection;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64, Engine};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{QueryBuilder, Sqlite};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SortValue {
+    Int(i64),
+    Text(String),
+    Date(DateTime<Utc>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cursor {
+    pub sort: Option<SortValue>,
+    pub tiebreak: DateTime<Utc>,
+    pub id: String,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        BASE64.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, String> {
+        BASE64
+            .decode(cursor)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or_else(|| "Invalid cursor".to_string())
+    }
+}
+
+fn push_value(builder: &mut QueryBuilder<'_, Sqlite>, value: &SortValue) {
+    match value {
+        SortValue::Int(v) => builder.push_bind(*v),
+        SortValue::Text(v) => builder.push_bind(v.clone()),
+        SortValue::Date(v) => builder.push_bind(*v),
+    };
+}
+
+fn push_tiebreak_after(builder: &mut QueryBuilder<'_, Sqlite>, tiebreak: &str, cursor: &Cursor) {
+    builder.push(format!("({} < ", tiebreak));
+    builder.push_bind(cursor.tiebreak);
+    builder.push(format!(" OR ({} = ", tiebreak));
+    builder.push_bind(cursor.tiebreak);
+    builder.push(" AND id > ");
+    builder.push_bind(cursor.id.clone());
+    builder.push("))");
+}
+
+/// Appends `AND ...` keeping only rows that come after `cursor`.
+pub fn push_after(
+    builder: &mut QueryBuilder<'_, Sqlite>,
+    sort: Option<(&str, SortDirection)>,
+    tiebreak: &str,
+    cursor: &Cursor,
+) {
+    builder.push(" AND ");
+    let Some((column, direction)) = sort else {
+        push_tiebreak_after(builder, tiebreak, cursor);
+        return;
+    };
+
+    match &cursor.sort {
+        // Rows without a value come last, so only the tiebreak is left to compare.
+        None => {
+            builder.push(format!("({} IS NULL AND ", column));
+            push_tiebreak_after(builder, tiebreak, cursor);
+            builder.push(")");
+        }
+        Some(value) => {
+            let op = match direction {
+                SortDirection::Asc => ">",
+                SortDirection::Desc => "<",
+            };
+            builder.push(format!("({0} IS NULL OR {0} {1} ", column, op));
+            push_value(builder, value);
+            builder.push(format!(" OR ({} = ", column));
+            push_value(builder, value);
+            builder.push(" AND ");
+            push_tiebreak_after(builder, tiebreak, cursor);
+            builder.push("))");
+        }
+    }
+}
+
+/// Appends the ORDER BY matching `push_after`.
+pub fn push_order(
+    builder: &mut QueryBuilder<'_, Sqlite>,
+    sort: Option<(&str, SortDirection)>,
+    tiebreak: &str,
+) {
+    match sort {
+        Some((column, direction)) => {
+            let direction = match direction {
+                SortDirection::Asc => "ASC",
+                SortDirection::Desc => "DESC",
+            };
+            // Rows without a value sort last in either direction.
+            builder.push(format!(
+                " ORDER BY {0} IS NULL, {0} {1}, {2} DESC, id",
+                column, direction, tiebreak
+            ));
+        }
+        None => {
+            builder.push(format!(" ORDER BY {} DESC, id", tiebreak));
+        }
+    }
+}
+
+/// Rows per page when the caller does not ask for a size.
+pub const DEFAULT_PAGE_SIZE: i64 = 100;
+/// Largest page handed out; it also bounds the ids bound by the queries
+/// that load tags and progress for a page.
+pub const MAX_PAGE_SIZE: i64 = 500;
+
+/// `limit` clamped to `1..=MAX_PAGE_SIZE`, `DEFAULT_PAGE_SIZE` when unset.
+pub fn page_size(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+}
+
+/// Appends `LIMIT` fetching one row more than the page size, so the caller
+/// can tell whether another page follows.
+pub fn push_limit(builder: &mut QueryBuilder<'_, Sqlite>, limit: Option<i64>) {
+    builder.push(" LIMIT ");
+    builder.push_bind(page_size(limit) + 1);
+}
+
+/// Trims the extra row `push_limit` asked for and returns the cursor of the
+/// page's last row when more rows follow.
+pub fn finish_page<T>(
+    rows: &mut Vec<T>,
+    limit: Option<i64>,
+    cursor_of: impl Fn(&T) -> Cursor,
+) -> Option<String> {
+    let limit = page_size(limit) as usize;
+    if rows.len() <= limit {
+        return None;
+    }
+    rows.truncate(limit);
+    rows.last().map(|row| cursor_of(row).encode())
+}
@@ -0,0 +1,286 @@
+// Startup integrity check of silto.db. A damaged file is moved aside as
+// `silto.db.damaged-<time>` and whatever rows can still be read from it are
+// copied, table by table, into a freshly created database. Each table is read
+// one row at a time in rowid order, forwards until the first unreadable row
+// and then backwards from the end, so only the rows on broken pages are lost.
+// The report is kept on `Database` until the UI picks it up.
+
+use crate::encryption::DbKey;
+use crate::models::{RecoveredTable, RecoveryReport};
+use sqlx::{ConnectOptions, Connection, SqliteConnection, SqlitePool};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+const SQLITE_CORRUPT: i32 = 11;
+const SQLITE_NOTADB: i32 = 26;
+
+async fn integrity_check(path: &Path, key: Option<&DbKey>) -> Result<Vec<String>, sqlx::Error> {
+    let mut conn = crate::db::connect_options(path, key)
+        .create_if_missing(false)
+        .connect()
+        .await?;
+    let rows = sqlx::query_scalar("PRAGMA integrity_check")
+        .fetch_all(&mut conn)
+        .await;
+    let _ = conn.close().await;
+    rows
+}
+
+fn has_plain_header(path: &Path) -> bool {
+    let mut header = [0u8; 16];
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .is_ok()
+        && &header == b"SQLite format 3\0"
+}
+
+/// Whether `error` says the file itself is broken. "Not a database" only
+/// counts for a file with a plaintext header: for anything else it is also
+/// what SQLCipher answers to a wrong or missing key.
+fn is_damage(error: &sqlx::Error, path: &Path, key: Option<&DbKey>) -> bool {
+    let code = error
+        .as_database_error()
+        .and_then(|e| e.code())
+        .and_then(|code| code.parse::<i32>().ok());
+    // Extended result codes carry the primary code in the low byte.
+    match code.map(|code| code & 0xff) {
+        Some(SQLITE_CORRUPT) => true,
+        Some(SQLITE_NOTADB) => key.is_none() && has_plain_header(path),
+        _ => false,
+    }
+}
+
+/// Problems reported by `PRAGMA integrity_check`; empty for a healthy file.
+/// A file SQLite rejects as corrupt counts as damaged too. Any other failure
+/// (locked, unreadable, wrong key) is an error and says nothing about damage.
+pub async fn integrity_problems(path: &Path, key: Option<&DbKey>) -> Result<Vec<String>, String> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    match integrity_check(path, key).await {
+        Ok(rows) if rows.len() == 1 && rows[0] == "ok" => Ok(Vec::new()),
+        Ok(rows) => Ok(rows),
+        Err(e) if is_damage(&e, path, key) => Ok(vec![e.to_string()]),
+        Err(e) => Err(format!("Failed to check database: {}", e)),
+    }
+}
+
+/// Moves the file and its journal files out of the way and returns the new path.
+fn move_aside(path: &Path) -> Result<PathBuf, String> {
+    let stamp = chrono::Utc::now().format("%Y%m%d-%H%M%S");
+    let damaged = PathBuf::from(format!("{}.damaged-{}", path.display(), stamp));
+    fs::rename(path, &damaged).map_err(|e| format!("Failed to move damaged database: {}", e))?;
+    for suffix in ["-wal", "-shm"] {
+        let side = PathBuf::from(format!("{}{}", path.display(), suffix));
+        if side.exists() {
+            fs::rename(&side, format!("{}{}", damaged.display(), suffix))
+                .map_err(|e| format!("Failed to move damaged database: {}", e))?;
+        }
+    }
+    Ok(damaged)
+}
+
+/// Tables worth salvaging: everything except the FTS index, which the
+/// triggers rebuild, and the migration bookkeeping.
+async fn data_tables(conn: &mut SqliteConnection) -> Result<Vec<String>, String> {
+    sqlx::query_scalar(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'
+         AND name NOT LIKE '%_fts%' AND name != 'schema_version' ORDER BY rowid",
+    )
+    .fetch_all(conn)
+    .await
+    .map_err(|e| format!("Failed to list tables: {}", e))
+}
+
+async fn columns(conn: &mut SqliteConnection, table: &str) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar(&format!("SELECT name FROM pragma_table_info('{}')", table))
+        .fetch_all(conn)
+        .await
+}
+
+/// Copies the readable rows of `table` from `damaged` into `fresh`, using the
+/// columns both sides have. Returns the rows copied and whether reading
+/// reached the end of the table.
+async fn salvage_table(
+    damaged: &mut SqliteConnection,
+    fresh: &mut SqliteConnection,
+    table: &str,
+    problems: &mut Vec<String>,
+) -> Result<(i64, bool), String> {
+    let wanted = columns(&mut *fresh, table)
+        .await
+        .map_err(|e| format!("Failed to read columns of {}: {}", table, e))?;
+    let cols: Vec<String> = match columns(&mut *damaged, table).await {
+        Ok(found) => wanted.into_iter().filter(|c| found.contains(c)).collect(),
+        Err(e) => {
+            problems.push(format!("{}: {}", table, e));
+            return Ok((0, false));
+        }
+    };
+    if cols.is_empty() {
+        return Ok((0, true));
+    }
+
+    let fields = cols
+        .iter()
+        .map(|c| format!("'{0}', \"{0}\"", c))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let forward = format!(
+        "SELECT rowid, json_object({}) FROM \"{}\" WHERE rowid > ? ORDER BY rowid LIMIT 1",
+        fields, table
+    );
+    let backward = format!(
+        "SELECT rowid, json_object({}) FROM \"{}\" WHERE rowid < ? ORDER BY rowid DESC LIMIT 1",
+        fields, table
+    );
+    let mut rows = Vec::new();
+    let mut complete = true;
+    let mut last = i64::MIN;
+    loop {
+        match sqlx::query_as::<_, (i64, String)>(&forward)
+            .bind(last)
+            .fetch_optional(&mut *damaged)
+            .await
+        {
+            Ok(Some((rowid, row))) => {
+                last = rowid;
+                rows.push(row);
+            }
+            Ok(None) => break,
+            Err(e) => {
+                problems.push(format!("{}: {}", table, e));
+                complete = false;
+                break;
+            }
+        }
+    }
+    if !complete {
+        let mut first = i64::MAX;
+        while let Ok(Some((rowid, row))) = sqlx::query_as::<_, (i64, String)>(&backward)
+            .bind(first)
+            .fetch_optional(&mut *damaged)
+            .await
+        {
+            if rowid <= last {
+                break;
+            }
+            first = rowid;
+            rows.push(row);
+        }
+    }
+
+    let names = cols
+        .iter()
+        .map(|c| format!("\"{}\"", c))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let values = cols
+        .iter()
+        .map(|c| format!("json_extract(j.row, '$.{}')", c))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let insert = format!(
+        "INSERT OR IGNORE INTO \"{}\" ({}) SELECT {} FROM (SELECT ? AS row) j",
+        table, names, values
+    );
+    let mut copied = 0;
+    for row in rows {
+        match sqlx::query(&insert).bind(row).execute(&mut *fresh).await {
+            Ok(result) => copied += result.rows_affected() as i64,
+            Err(e) => problems.push(format!("{}: {}", table, e)),
+        }
+    }
+    Ok((copied, complete))
+}
+
+/// Drops rows whose parent was lost, so the new database passes its foreign
+/// key checks. Returns the number removed per table.
+async fn drop_orphans(conn: &mut SqliteConnection) -> Result<Vec<(String, i64)>, String> {
+    let mut dropped: Vec<(String, i64)> = Vec::new();
+    let violations: Vec<(String, i64)> =
+        sqlx::query_as("SELECT \"table\", rowid FROM pragma_foreign_key_check")
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to check foreign keys: {}", e))?;
+    for (table, rowid) in violations {
+        sqlx::query(&format!("DELETE FROM \"{}\" WHERE rowid = ?", table))
+            .bind(rowid)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to drop orphaned row: {}", e))?;
+        match dropped.iter_mut().find(|(t, _)| *t == table) {
+            Some((_, count)) => *count += 1,
+            None => dropped.push((table, 1)),
+        }
+    }
+    Ok(dropped)
+}
+
+/// Moves the damaged file at `path` aside, opens a new database in its place
+/// and copies what it can into it.
+pub async fn recover(
+    path: &Path,
+    app_dir: &Path,
+    key: Option<&DbKey>,
+    problems: Vec<String>,
+) -> Result<(SqlitePool, RecoveryReport), String> {
+    let damaged_path = move_aside(path)?;
+    let pool = crate::db::open_pool(path, app_dir, key).await?;
+    let mut report = RecoveryReport {
+        damaged_path: damaged_path.to_string_lossy().to_string(),
+        tables: Vec::new(),
+        problems,
+    };
+
+    let mut damaged = match crate::db::connect_options(&damaged_path, key)
+        .create_if_missing(false)
+        .read_only(true)
+        .connect()
+        .await
+    {
+        Ok(conn) => conn,
+        Err(e) => {
+            report
+                .problems
+                .push(format!("Failed to open damaged database: {}", e));
+            return Ok((pool, report));
+        }
+    };
+    let mut fresh = pool
+        .acquire()
+        .await
+        .map_err(|e| format!("Failed to acquire connection: {}", e))?;
+    // Rows arrive in table order, not dependency order; orphans are dropped below.
+    sqlx::query("PRAGMA foreign_keys = OFF")
+        .execute(&mut *fresh)
+        .await
+        .map_err(|e| format!("Failed to disable foreign keys: {}", e))?;
+
+    for table in data_tables(&mut fresh).await? {
+        let (rows, complete) =
+            salvage_table(&mut damaged, &mut fresh, &table, &mut report.problems).await?;
+        report.tables.push(RecoveredTable {
+            table,
+            rows,
+            complete,
+        });
+    }
+    let _ = damaged.close().await;
+
+    for (table, count) in drop_orphans(&mut fresh).await? {
+        if let Some(entry) = report.tables.iter_mut().find(|t| t.table == table) {
+            entry.rows -= count;
+            entry.complete = false;
+        }
+    }
+    sqlx::query("PRAGMA foreign_keys = ON")
+        .execute(&mut *fresh)
+        .await
+        .map_err(|e| format!("Failed to enable foreign keys: {}", e))?;
+    drop(fresh);
+
+    crate::search::backfill_search_text(&pool).await?;
+    Ok((pool, report))
+}
@@ -0,0 +1,618 @@
+// RFC 5545 RRULE subset for recurring todos: FREQ (DAILY/WEEKLY/MONTHLY/YEARLY),
+// INTERVAL, BYDAY, BYMONTHDAY, COUNT and UNTIL. Occurrences are expanded in the
+// local timezone so "every Monday at 9:00" stays at 9:00 across DST changes.
+
+use crate::models::Todo;
+use chrono::{
+    DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc,
+    Weekday,
+};
+use sqlx::SqlitePool;
+
+// Upper bound on candidate periods scanned when looking for the next match.
+const MAX_PERIODS: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecurrenceRule {
+    pub freq: Frequency,
+    pub interval: u32,
+    /// Weekday with an optional ordinal within the month (`2TU`, `-1FR`).
+    pub by_day: Vec<(Option<i32>, Weekday)>,
+    pub by_month_day: Vec<i32>,
+    pub count: Option<u32>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+pub fn parse_weekday(code: &str) -> Option<Weekday> {
+    match code {
+        "MO" => Some(Weekday::Mon),
+        "TU" => Some(Weekday::Tue),
+        "WE" => Some(Weekday::Wed),
+        "TH" => Some(Weekday::Thu),
+        "FR" => Some(Weekday::Fri),
+        "SA" => Some(Weekday::Sat),
+        "SU" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+fn parse_until(value: &str) -> Option<DateTime<Utc>> {
+    if let Some(utc) = value.strip_suffix('Z') {
+        return NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")
+            .ok()
+            .map(|dt| dt.and_utc());
+    }
+    if let Ok(dt) = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S") {
+        return Local
+            .from_local_datetime(&dt)
+            .earliest()
+            .map(|dt| dt.with_timezone(&Utc));
+    }
+    // A date-only UNTIL includes that whole local day.
+    NaiveDate::parse_from_str(value, "%Y%m%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(23, 59, 59))
+        .and_then(|dt| Local.from_local_datetime(&dt).earliest())
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+impl RecurrenceRule {
+    /// Parses an RRULE value such as `FREQ=WEEKLY;BYDAY=MO,WE;COUNT=10`. A
+    /// leading `RRULE:` is accepted.
+    pub fn parse(rule: &str) -> Result<Self, String> {
+        let rule = rule.trim();
+        let rule = rule.strip_prefix("RRULE:").unwrap_or(rule);
+
+        let mut freq = None;
+        let mut parsed = RecurrenceRule {
+            freq: Frequency::Daily,
+            interval: 1,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            count: None,
+            until: None,
+        };
+
+        for part in rule.split(';').filter(|p| !p.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid RRULE part: {}", part))?;
+            let value = value.trim().to_ascii_uppercase();
+            match key.trim().to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    freq = Some(match value.as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(format!("Unsupported RRULE frequency: {}", value)),
+                    })
+                }
+                "INTERVAL" => {
+                    parsed.interval = value
+                        .parse()
+                        .ok()
+                        .filter(|i| *i > 0)
+                        .ok_or_else(|| format!("Invalid RRULE interval: {}", value))?
+                }
+                "COUNT" => {
+                    parsed.count = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|c| *c > 0)
+                            .ok_or_else(|| format!("Invalid RRULE count: {}", value))?,
+                    )
+                }
+                "UNTIL" => {
+                    parsed.until = Some(
+                        parse_until(&value)
+                            .ok_or_else(|| format!("Invalid RRULE until: {}", value))?,
+                    )
+                }
+                "BYDAY" => {
+                    for day in value.split(',') {
+                        if !day.is_ascii() {
+                            return Err(format!("Invalid RRULE weekday: {}", day));
+                        }
+                        let split = day.len().saturating_sub(2);
+                        let weekday = parse_weekday(&day[split..])
+                            .ok_or_else(|| format!("Invalid RRULE weekday: {}", day))?;
+                        let ordinal = match &day[..split] {
+                            "" => None,
+                            n => Some(
+                                n.trim_start_matches('+')
+                                    .parse::<i32>()
+                                    .ok()
+                                    .filter(|n| *n != 0 && n.abs() <= 5)
+                                    .ok_or_else(|| format!("Invalid RRULE weekday: {}", day))?,
+                            ),
+                        };
+                        parsed.by_day.push((ordinal, weekday));
+                    }
+                }
+                "BYMONTHDAY" => {
+                    for day in value.split(',') {
+                        parsed.by_month_day.push(
+                            day.parse::<i32>()
+                                .ok()
+                                .filter(|d| *d != 0 && d.abs() <= 31)
+                                .ok_or_else(|| format!("Invalid RRULE month day: {}", day))?,
+                        );
+                    }
+                }
+                "WKST" => {}
+                other => return Err(format!("Unsupported RRULE part: {}", other)),
+            }
+        }
+
+        parsed.freq = freq.ok_or("RRULE is missing FREQ")?;
+        if parsed.count.is_some() && parsed.until.is_some() {
+            return Err("RRULE cannot have both COUNT and UNTIL".to_string());
+        }
+        let has_ordinal = parsed.by_day.iter().any(|(ordinal, _)| ordinal.is_some());
+        if has_ordinal && parsed.freq != Frequency::Monthly {
+            return Err("Numbered BYDAY values are only supported with FREQ=MONTHLY".to_string());
+        }
+        if parsed.freq == Frequency::Yearly && !parsed.by_day.is_empty() {
+            return Err("BYDAY is not supported with FREQ=YEARLY".to_string());
+        }
+        Ok(parsed)
+    }
+
+    /// Days in `year`/`month` this rule selects, ascending. Without BYDAY or
+    /// BYMONTHDAY the day of month of the `anchor` occurrence is used.
+    fn month_days(&self, year: i32, month: u32, anchor_day: u32) -> Vec<NaiveDate> {
+        let Some(first) = NaiveDate::from_ymd_opt(year, month, 1) else {
+            return Vec::new();
+        };
+        let last = first
+            .checked_add_months(chrono::Months::new(1))
+            .and_then(|d| d.pred_opt())
+            .unwrap_or(first);
+        let days_in_month = last.day() as i32;
+
+        let mut days: Vec<NaiveDate> = Vec::new();
+        for &day in &self.by_month_day {
+            let day = if day < 0 {
+                days_in_month + day + 1
+            } else {
+                day
+            };
+            if (1..=days_in_month).contains(&day) {
+                days.extend(NaiveDate::from_ymd_opt(year, month, day as u32));
+            }
+        }
+        for &(ordinal, weekday) in &self.by_day {
+            let matching: Vec<NaiveDate> = first
+                .iter_days()
+                .take_while(|d| *d <= last)
+                .filter(|d| d.weekday() == weekday)
+                .collect();
+            match ordinal {
+                None => days.extend(matching),
+                Some(n) if n > 0 => days.extend(matching.get(n as usize - 1)),
+                Some(n) => days.extend(
+                    matching
+                        .len()
+                        .checked_sub(n.unsigned_abs() as usize)
+                        .and_then(|i| matching.get(i)),
+                ),
+            }
+        }
+        if self.by_month_day.is_empty() && self.by_day.is_empty() {
+            // Months without the anchor day (e.g. the 31st) are skipped, per RFC 5545.
+            days.extend(NaiveDate::from_ymd_opt(year, month, anchor_day));
+        }
+
+        days.sort();
+        days.dedup();
+        days
+    }
+
+    fn next_date(&self, current: NaiveDate, anchor_day: u32) -> Option<NaiveDate> {
+        let interval = self.interval;
+        match self.freq {
+            Frequency::Daily => {
+                let mut date = current;
+                for _ in 0..MAX_PERIODS {
+                    date = date.checked_add_signed(Duration::days(interval as i64))?;
+                    if self.by_day.is_empty()
+                        || self.by_day.iter().any(|(_, w)| *w == date.weekday())
+                    {
+                        return Some(date);
+                    }
+                }
+                None
+            }
+            Frequency::Weekly => {
+                if self.by_day.is_empty() {
+                    return current.checked_add_signed(Duration::weeks(interval as i64));
+                }
+                let week_start =
+                    current - Duration::days(current.weekday().num_days_from_monday() as i64);
+                let mut weekdays: Vec<Weekday> = self.by_day.iter().map(|(_, w)| *w).collect();
+                weekdays.sort_by_key(|w| w.num_days_from_monday());
+
+                if let Some(w) = weekdays
+                    .iter()
+                    .find(|w| w.num_days_from_monday() > current.weekday().num_days_from_monday())
+                {
+                    return Some(week_start + Duration::days(w.num_days_from_monday() as i64));
+                }
+                let next_week = week_start.checked_add_signed(Duration::weeks(interval as i64))?;
+                Some(next_week + Duration::days(weekdays[0].num_days_from_monday() as i64))
+            }
+            Frequency::Monthly => {
+                if let Some(day) = self
+                    .month_days(current.year(), current.month(), anchor_day)
+                    .into_iter()
+                    .find(|d| *d > current)
+                {
+                    return Some(day);
+                }
+                let mut month = current.with_day(1)?;
+                for _ in 0..MAX_PERIODS {
+                    month = month.checked_add_months(chrono::Months::new(interval))?;
+                    if let Some(day) = self
+                        .month_days(month.year(), month.month(), anchor_day)
+                        .into_iter()
+                        .next()
+                    {
+                        return Some(day);
+                    }
+                }
+                None
+            }
+            Frequency::Yearly => {
+                let mut year = current.year();
+                for _ in 0..MAX_PERIODS {
+                    year += interval as i32;
+                    if let Some(day) = NaiveDate::from_ymd_opt(year, current.month(), anchor_day) {
+                        return Some(day);
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    /// The occurrence following `current`, where `index` is `current`'s
+    /// zero-based position in the series. `None` once COUNT or UNTIL is reached.
+    pub fn next_after(&self, current: DateTime<Utc>, index: i64) -> Option<DateTime<Utc>> {
+        if self.count.is_some_and(|count| index + 1 >= count as i64) {
+            return None;
+        }
+
+        let local = current.with_timezone(&Local).naive_local();
+        let time: NaiveTime = local.time();
+        let date = self.next_date(local.date(), local.day())?;
+        let next = Local
+            .from_local_datetime(&date.and_time(time))
+            .earliest()
+            // The wall-clock time fell into a DST gap; shift past it.
+            .or_else(|| {
+                Local
+                    .from_local_datetime(&(date.and_time(time) + Duration::hours(1)))
+                    .earliest()
+            })?
+            .with_timezone(&Utc);
+
+        if self.until.is_some_and(|until| next > until) {
+            return None;
+        }
+        Some(next)
+    }
+}
+
+/// Fills in the series bookkeeping for a todo that has just been given a rule.
+pub fn prepare_series(todo: &mut Todo) -> Result<(), String> {
+    let Some(rule) = todo.recurrence.as_deref() else {
+        return Ok(());
+    };
+    RecurrenceRule::parse(rule)?;
+
+    if todo.series_id.is_none() {
+        todo.series_id = Some(todo.id.clone());
+        todo.recurrence_index = 0;
+    }
+    if todo.occurrence_at.is_none() {
+        todo.occurrence_at = Some(todo.remind_time.or(todo.due_at).unwrap_or(todo.created_at));
+    }
+    Ok(())
+}
+
+/// When the occurrence after `todo` is scheduled, if there is one.
+pub fn next_occurrence(todo: &Todo) -> Option<DateTime<Utc>> {
+    let rule = RecurrenceRule::parse(todo.recurrence.as_deref()?).ok()?;
+    rule.next_after(todo.occurrence_at?, todo.recurrence_index)
+}
+
+/// Creates the next instance of `todo`'s series unless `todo` has already
+/// handed over to one, even if that successor has since been purged. Each
+/// instance is an independent todo, so editing one occurrence leaves the
+/// rest of the series alone; scheduling always follows the rule's own slots.
+pub async fn spawn_next(pool: &SqlitePool, todo: &Todo) -> Result<Option<String>, String> {
+    let Some(next_at) = next_occurrence(todo) else {
+        return Ok(None);
+    };
+    let Some(occurrence_at) = todo.occurrence_at else {
+        return Ok(None);
+    };
+    let shift = next_at - occurrence_at;
+    let id = uuid::Uuid::new_v4().to_string();
+    let index = todo.recurrence_index + 1;
+
+    let next = Todo {
+        id: id.clone(),
+        completed: false,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        notified: false,
+        due_notified: false,
+        remind_time: todo.remind_time.map(|_| next_at),
+        due_at: todo.due_at.map(|due| due + shift),
+        occurrence_at: Some(next_at),
+        recurrence_index: index,
+        handed_over: false,
+        completed_at: None,
+        archived: false,
+        deleted_at: None,
+        tag_ids: Vec::new(),
+        progress: None,
+        children: Vec::new(),
+        ..todo.clone()
+    };
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| format!("Failed to begin transaction: {}", e))?;
+
+    let handed_over: Option<bool> =
+        sqlx::query_scalar("SELECT handed_over FROM todos WHERE id = ?")
+            .bind(&todo.id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to fetch occurrence: {}", e))?;
+    if handed_over.unwrap_or(false) {
+        return Ok(None);
+    }
+
+    // The unique (series_id, recurrence_index) index makes this idempotent.
+    let inserted = sqlx::query(
+        "INSERT OR IGNORE INTO todos (id, title, content, remind_time, completed, created_at, notified, search_text, list_id, parent_id,
+         due_at, due_all_day, priority, remind_before_due_minutes, due_remind_at, due_notified,
+         recurrence, series_id, recurrence_index, occurrence_at, next_occurrence_at, updated_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&next.id)
+    .bind(&next.title)
+    .bind(&next.content)
+    .bind(next.remind_time)
+    .bind(next.completed)
+    .bind(next.created_at)
+    .bind(next.notified)
+    .bind(crate::html::to_plain_text(&next.content))
+    .bind(&next.list_id)
+    .bind(&next.parent_id)
+    .bind(next.due_at)
+    .bind(next.due_all_day)
+    .bind(next.priority)
+    .bind(next.remind_before_due_minutes)
+    .bind(next.due_remind_at())
+    .bind(next.due_notified)
+    .bind(&next.recurrence)
+    .bind(&next.series_id)
+    .bind(next.recurrence_index)
+    .bind(next.occurrence_at)
+    .bind(next_occurrence(&next))
+    .bind(next.updated_at)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| format!("Failed to create next occurrence: {}", e))?
+    .rows_affected()
+        > 0;
+
+    if inserted {
+        sqlx::query(
+            "INSERT INTO todo_tags (todo_id, tag_id) SELECT ?, tag_id FROM todo_tags WHERE todo_id = ?",
+        )
+        .bind(&id)
+        .bind(&todo.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to copy tags to next occurrence: {}", e))?;
+    }
+
+    // This instance has handed over to its successor.
+    sqlx::query("UPDATE todos SET next_occurrence_at = NULL, handed_over = 1 WHERE id = ?")
+        .bind(&todo.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to update occurrence: {}", e))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("Failed to commit next occurrence: {}", e))?;
+
+    Ok(inserted.then_some(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 09:00 local time on the given day.
+    fn at(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+        Local
+            .with_ymd_and_hms(year, month, day, 9, 0, 0)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    /// The local dates of the first `n` occurrences starting at `start`.
+    fn expand(rule: &str, start: DateTime<Utc>, n: usize) -> Vec<NaiveDate> {
+        let rule = RecurrenceRule::parse(rule).unwrap();
+        let mut dates = vec![start.with_timezone(&Local).date_naive()];
+        let mut current = start;
+        for index in 0..n as i64 - 1 {
+            let Some(next) = rule.next_after(current, index) else {
+                break;
+            };
+            assert_eq!(
+                next.with_timezone(&Local).time(),
+                NaiveTime::from_hms_opt(9, 0, 0).unwrap()
+            );
+            dates.push(next.with_timezone(&Local).date_naive());
+            current = next;
+        }
+        dates
+    }
+
+    fn dates(days: &[(i32, u32, u32)]) -> Vec<NaiveDate> {
+        days.iter()
+            .map(|&(y, m, d)| NaiveDate::from_ymd_opt(y, m, d).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn parses_every_supported_part() {
+        let rule = RecurrenceRule::parse(
+            "RRULE:freq=monthly;INTERVAL=2;BYDAY=2TU,-1FR;BYMONTHDAY=1,-1;COUNT=4;WKST=MO",
+        )
+        .unwrap();
+        assert_eq!(rule.freq, Frequency::Monthly);
+        assert_eq!(rule.interval, 2);
+        assert_eq!(
+            rule.by_day,
+            vec![(Some(2), Weekday::Tue), (Some(-1), Weekday::Fri)]
+        );
+        assert_eq!(rule.by_month_day, vec![1, -1]);
+        assert_eq!(rule.count, Some(4));
+        assert_eq!(rule.until, None);
+
+        let rule = RecurrenceRule::parse("FREQ=DAILY;UNTIL=20260301T120000Z").unwrap();
+        assert_eq!(
+            rule.until,
+            Some(Utc.with_ymd_and_hms(2026, 3, 1, 12, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        for rule in [
+            "INTERVAL=2",
+            "FREQ=HOURLY",
+            "FREQ=DAILY;INTERVAL=0",
+            "FREQ=DAILY;COUNT=0",
+            "FREQ=DAILY;COUNT=3;UNTIL=20260101",
+            "FREQ=WEEKLY;BYDAY=XX",
+            "FREQ=WEEKLY;BYDAY=1MO",
+            "FREQ=MONTHLY;BYDAY=6MO",
+            "FREQ=MONTHLY;BYMONTHDAY=32",
+            "FREQ=YEARLY;BYDAY=MO",
+            "FREQ=DAILY;BYHOUR=9",
+            "FREQ",
+        ] {
+            assert!(RecurrenceRule::parse(rule).is_err(), "{}", rule);
+        }
+    }
+
+    #[test]
+    fn expands_daily_with_interval() {
+        assert_eq!(
+            expand("FREQ=DAILY;INTERVAL=3", at(2026, 1, 30), 3),
+            dates(&[(2026, 1, 30), (2026, 2, 2), (2026, 2, 5)])
+        );
+    }
+
+    #[test]
+    fn expands_weekly_byday_with_interval() {
+        // 2026-01-05 is a Monday.
+        assert_eq!(
+            expand("FREQ=WEEKLY;INTERVAL=2;BYDAY=TH,MO", at(2026, 1, 5), 5),
+            dates(&[
+                (2026, 1, 5),
+                (2026, 1, 8),
+                (2026, 1, 19),
+                (2026, 1, 22),
+                (2026, 2, 2)
+            ])
+        );
+    }
+
+    #[test]
+    fn expands_monthly_ordinal_weekdays() {
+        assert_eq!(
+            expand("FREQ=MONTHLY;BYDAY=-1FR", at(2026, 1, 30), 3),
+            dates(&[(2026, 1, 30), (2026, 2, 27), (2026, 3, 27)])
+        );
+        assert_eq!(
+            expand("FREQ=MONTHLY;BYDAY=2TU", at(2026, 1, 13), 3),
+            dates(&[(2026, 1, 13), (2026, 2, 10), (2026, 3, 10)])
+        );
+    }
+
+    #[test]
+    fn monthly_skips_months_without_the_anchor_day() {
+        assert_eq!(
+            expand("FREQ=MONTHLY", at(2026, 1, 31), 3),
+            dates(&[(2026, 1, 31), (2026, 3, 31), (2026, 5, 31)])
+        );
+        assert_eq!(
+            expand("FREQ=MONTHLY;BYMONTHDAY=-1", at(2026, 1, 31), 3),
+            dates(&[(2026, 1, 31), (2026, 2, 28), (2026, 3, 31)])
+        );
+    }
+
+    #[test]
+    fn count_limits_the_series() {
+        assert_eq!(
+            expand("FREQ=WEEKLY;COUNT=3", at(2026, 1, 5), 10),
+            dates(&[(2026, 1, 5), (2026, 1, 12), (2026, 1, 19)])
+        );
+    }
+
+    #[test]
+    fn until_includes_a_date_only_last_day() {
+        assert_eq!(
+            expand("FREQ=DAILY;UNTIL=20260107", at(2026, 1, 5), 10),
+            dates(&[(2026, 1, 5), (2026, 1, 6), (2026, 1, 7)])
+        );
+    }
+
+    #[tokio::test]
+    async fn purged_successor_is_not_spawned_again() {
+        let pool = crate::db::memory_pool().await;
+        let mut todo = Todo::new("Water plants".to_string(), String::new());
+        todo.recurrence = Some("FREQ=DAILY".to_string());
+        prepare_series(&mut todo).unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+        crate::import::write_todo(&mut conn, &todo).await.unwrap();
+        drop(conn);
+
+        let next = spawn_next(&pool, &todo).await.unwrap().unwrap();
+        sqlx::query("DELETE FROM todos WHERE id = ?")
+            .bind(&next)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        // Un-completing and completing again hands the stale copy back in.
+        assert_eq!(spawn_next(&pool, &todo).await.unwrap(), None);
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM todos")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+}
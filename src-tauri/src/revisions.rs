@@ -0,0 +1,121 @@
+use crate::models::{DiffLine, DiffOp, Note};
+use chrono::{DateTime, Duration, Utc};
+use sqlx::SqlitePool;
+
+/// Saves closer together than this collapse into the latest revision...
+const MERGE_WINDOW_SECS: i64 = 120;
+/// ...as long as that revision was started less than this long ago.
+const MAX_REVISION_SPAN_SECS: i64 = 15 * 60;
+/// Oldest revisions beyond this count are dropped.
+pub const MAX_REVISIONS_PER_NOTE: i64 = 50;
+
+/// id, title, content, created_at and saved_at of a revision.
+type RevisionRow = (i64, String, String, DateTime<Utc>, DateTime<Utc>);
+
+/// Records `note`'s current state in its history, folding it into the latest
+/// revision when that one belongs to the same editing burst (unless `force_new`).
+pub async fn record_revision(
+    pool: &SqlitePool,
+    note: &Note,
+    force_new: bool,
+) -> Result<(), String> {
+    let now = Utc::now();
+    let latest: Option<RevisionRow> = sqlx::query_as(
+        "SELECT id, title, content, created_at, saved_at FROM note_revisions
+         WHERE note_id = ? ORDER BY id DESC LIMIT 1",
+    )
+    .bind(&note.id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch latest revision: {}", e))?;
+
+    if let Some((id, title, content, created_at, saved_at)) = latest {
+        if title == note.title && content == note.content {
+            return Ok(());
+        }
+
+        if !force_new
+            && now - saved_at < Duration::seconds(MERGE_WINDOW_SECS)
+            && now - created_at < Duration::seconds(MAX_REVISION_SPAN_SECS)
+        {
+            sqlx::query(
+                "UPDATE note_revisions SET title = ?, content = ?, saved_at = ? WHERE id = ?",
+            )
+            .bind(&note.title)
+            .bind(&note.content)
+            .bind(now)
+            .bind(id)
+            .execute(pool)
+            .await
+            .map_err(|e| format!("Failed to update revision: {}", e))?;
+            return Ok(());
+        }
+    }
+
+    sqlx::query(
+        "INSERT INTO note_revisions (note_id, title, content, created_at, saved_at) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(&note.id)
+    .bind(&note.title)
+    .bind(&note.content)
+    .bind(now)
+    .bind(now)
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Failed to record revision: {}", e))?;
+
+    sqlx::query(
+        "DELETE FROM note_revisions WHERE note_id = ? AND id NOT IN (
+            SELECT id FROM note_revisions WHERE note_id = ? ORDER BY id DESC LIMIT ?
+        )",
+    )
+    .bind(&note.id)
+    .bind(&note.id)
+    .bind(MAX_REVISIONS_PER_NOTE)
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Failed to prune revisions: {}", e))?;
+
+    Ok(())
+}
+
+/// Line diff of two texts based on their longest common subsequence.
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+
+    // lcs[i][j] = LCS length of a[i..] and b[j..]
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let line = |op: DiffOp, text: &str| DiffLine {
+        op,
+        text: text.to_string(),
+    };
+    let mut out = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            out.push(line(DiffOp::Equal, a[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            out.push(line(DiffOp::Delete, a[i]));
+            i += 1;
+        } else {
+            out.push(line(DiffOp::Insert, b[j]));
+            j += 1;
+        }
+    }
+    out.extend(a[i..].iter().map(|t| line(DiffOp::Delete, t)));
+    out.extend(b[j..].iter().map(|t| line(DiffOp::Insert, t)));
+    out
+}
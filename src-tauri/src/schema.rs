@@ -0,0 +1,354 @@
+use sqlx::{Executor, SqliteConnection, SqlitePool};
+use std::path::Path;
+
+/// A single, numbered schema change.
+///
+/// Migrations are applied in ascending `version` order and each one runs in
+/// its own transaction together with the `schema_version` bookkeeping, so a
+/// failure leaves the database at the previous version.
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+// Never edit or reorder a migration that has shipped; append a new one instead.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial todos and notes tables",
+        // Databases created before versioning already have these tables (including
+        // the `notified` column the old init_db added), so this is a no-op for them.
+        sql: "CREATE TABLE IF NOT EXISTS todos (
+            id TEXT PRIMARY KEY,
+            title TEXT NOT NULL,
+            content TEXT NOT NULL,
+            remind_time TEXT,
+            completed BOOLEAN NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL,
+            notified BOOLEAN NOT NULL DEFAULT 0
+        );
+        CREATE TABLE IF NOT EXISTS notes (
+            id TEXT PRIMARY KEY,
+            title TEXT NOT NULL,
+            content TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );",
+    },
+    Migration {
+        version: 2,
+        description: "soft deletion for todos and notes",
+        sql: "ALTER TABLE todos ADD COLUMN deleted_at TEXT;
+        ALTER TABLE notes ADD COLUMN deleted_at TEXT;",
+    },
+    Migration {
+        version: 3,
+        description: "full-text search over todos and notes",
+        // `search_text` holds the plain text extracted from the HTML content;
+        // it is NULL until search::backfill_search_text fills it in.
+        sql: "ALTER TABLE todos ADD COLUMN search_text TEXT;
+        ALTER TABLE notes ADD COLUMN search_text TEXT;
+        CREATE VIRTUAL TABLE todos_fts USING fts5(
+            title, search_text, content='todos', content_rowid='rowid', tokenize='trigram'
+        );
+        CREATE VIRTUAL TABLE notes_fts USING fts5(
+            title, search_text, content='notes', content_rowid='rowid', tokenize='trigram'
+        );
+        CREATE TRIGGER todos_fts_ai AFTER INSERT ON todos BEGIN
+            INSERT INTO todos_fts (rowid, title, search_text) VALUES (new.rowid, new.title, new.search_text);
+        END;
+        CREATE TRIGGER todos_fts_ad AFTER DELETE ON todos BEGIN
+            INSERT INTO todos_fts (todos_fts, rowid, title, search_text) VALUES ('delete', old.rowid, old.title, old.search_text);
+        END;
+        CREATE TRIGGER todos_fts_au AFTER UPDATE OF title, search_text ON todos BEGIN
+            INSERT INTO todos_fts (todos_fts, rowid, title, search_text) VALUES ('delete', old.rowid, old.title, old.search_text);
+            INSERT INTO todos_fts (rowid, title, search_text) VALUES (new.rowid, new.title, new.search_text);
+        END;
+        CREATE TRIGGER notes_fts_ai AFTER INSERT ON notes BEGIN
+            INSERT INTO notes_fts (rowid, title, search_text) VALUES (new.rowid, new.title, new.search_text);
+        END;
+        CREATE TRIGGER notes_fts_ad AFTER DELETE ON notes BEGIN
+            INSERT INTO notes_fts (notes_fts, rowid, title, search_text) VALUES ('delete', old.rowid, old.title, old.search_text);
+        END;
+        CREATE TRIGGER notes_fts_au AFTER UPDATE OF title, search_text ON notes BEGIN
+            INSERT INTO notes_fts (notes_fts, rowid, title, search_text) VALUES ('delete', old.rowid, old.title, old.search_text);
+            INSERT INTO notes_fts (rowid, title, search_text) VALUES (new.rowid, new.title, new.search_text);
+        END;
+        INSERT INTO todos_fts (todos_fts) VALUES ('rebuild');
+        INSERT INTO notes_fts (notes_fts) VALUES ('rebuild');",
+    },
+    Migration {
+        version: 4,
+        description: "tags for todos and notes",
+        sql: "CREATE TABLE tags (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            created_at TEXT NOT NULL
+        );
+        CREATE TABLE todo_tags (
+            todo_id TEXT NOT NULL REFERENCES todos(id) ON DELETE CASCADE,
+            tag_id TEXT NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+            PRIMARY KEY (todo_id, tag_id)
+        );
+        CREATE TABLE note_tags (
+            note_id TEXT NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+            tag_id TEXT NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+            PRIMARY KEY (note_id, tag_id)
+        );
+        CREATE INDEX idx_todo_tags_tag ON todo_tags(tag_id);
+        CREATE INDEX idx_note_tags_tag ON note_tags(tag_id);",
+    },
+    Migration {
+        version: 5,
+        description: "todo lists",
+        sql: "CREATE TABLE todo_lists (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            color TEXT,
+            sort_order INTEGER NOT NULL DEFAULT 0,
+            archived BOOLEAN NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL
+        );
+        INSERT INTO todo_lists (id, name, sort_order, created_at)
+            VALUES ('inbox', 'Inbox', 0, strftime('%Y-%m-%dT%H:%M:%S+00:00', 'now'));
+        ALTER TABLE todos ADD COLUMN list_id TEXT REFERENCES todo_lists(id) ON DELETE SET NULL;
+        CREATE INDEX idx_todos_list ON todos(list_id);",
+    },
+    Migration {
+        version: 6,
+        description: "subtasks",
+        sql: "ALTER TABLE todos ADD COLUMN parent_id TEXT REFERENCES todos(id) ON DELETE CASCADE;
+        CREATE INDEX idx_todos_parent ON todos(parent_id);",
+    },
+    Migration {
+        version: 7,
+        description: "due dates and priorities",
+        // `due_remind_at` is derived from `due_at` and `remind_before_due_minutes`
+        // by save_todo so the reminder loop can filter on it directly.
+        sql: "ALTER TABLE todos ADD COLUMN due_at TEXT;
+        ALTER TABLE todos ADD COLUMN due_all_day BOOLEAN NOT NULL DEFAULT 0;
+        ALTER TABLE todos ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE todos ADD COLUMN remind_before_due_minutes INTEGER;
+        ALTER TABLE todos ADD COLUMN due_remind_at TEXT;
+        ALTER TABLE todos ADD COLUMN due_notified BOOLEAN NOT NULL DEFAULT 0;
+        CREATE INDEX idx_todos_due ON todos(due_at);
+        CREATE INDEX idx_todos_priority ON todos(priority);",
+    },
+    Migration {
+        version: 8,
+        description: "recurring todos",
+        // Every occurrence is its own row sharing `series_id`; `occurrence_at` is
+        // the slot the rule scheduled it for and `next_occurrence_at` is set
+        // until the following occurrence has been created, which sets
+        // `handed_over` for good.
+        sql: "ALTER TABLE todos ADD COLUMN recurrence TEXT;
+        ALTER TABLE todos ADD COLUMN series_id TEXT;
+        ALTER TABLE todos ADD COLUMN recurrence_index INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE todos ADD COLUMN occurrence_at TEXT;
+        ALTER TABLE todos ADD COLUMN next_occurrence_at TEXT;
+        ALTER TABLE todos ADD COLUMN handed_over BOOLEAN NOT NULL DEFAULT 0;
+        CREATE UNIQUE INDEX idx_todos_series ON todos(series_id, recurrence_index) WHERE series_id IS NOT NULL;
+        CREATE INDEX idx_todos_next_occurrence ON todos(next_occurrence_at) WHERE next_occurrence_at IS NOT NULL;",
+    },
+    Migration {
+        version: 9,
+        description: "note revision history",
+        sql: "CREATE TABLE note_revisions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            note_id TEXT NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+            title TEXT NOT NULL,
+            content TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            saved_at TEXT NOT NULL
+        );
+        CREATE INDEX idx_note_revisions_note ON note_revisions(note_id, id);
+        INSERT INTO note_revisions (note_id, title, content, created_at, saved_at)
+            SELECT id, title, content, updated_at, updated_at FROM notes;",
+    },
+    Migration {
+        version: 10,
+        description: "attachment store",
+        // Existing inline images are moved out by attachments::migrate_inline_images
+        // and replaced by `attachment:<hash>` references.
+        sql: "CREATE TABLE attachments (
+            hash TEXT PRIMARY KEY,
+            mime TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            created_at TEXT NOT NULL
+        );
+        CREATE TABLE note_attachments (
+            note_id TEXT NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+            hash TEXT NOT NULL REFERENCES attachments(hash),
+            PRIMARY KEY (note_id, hash)
+        );
+        CREATE INDEX idx_note_attachments_hash ON note_attachments(hash);",
+    },
+    Migration {
+        version: 11,
+        description: "links between notes",
+        // Filled from existing content by links::backfill_links.
+        sql: "CREATE TABLE note_links (
+            source_id TEXT NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+            target_title TEXT NOT NULL COLLATE NOCASE,
+            PRIMARY KEY (source_id, target_title)
+        );
+        CREATE INDEX idx_note_links_target ON note_links(target_title);",
+    },
+    Migration {
+        version: 12,
+        description: "todo and note relations",
+        sql: "CREATE TABLE todo_notes (
+            todo_id TEXT NOT NULL REFERENCES todos(id) ON DELETE CASCADE,
+            note_id TEXT NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+            created_at TEXT NOT NULL,
+            PRIMARY KEY (todo_id, note_id)
+        );
+        CREATE INDEX idx_todo_notes_note ON todo_notes(note_id);",
+    },
+    Migration {
+        version: 13,
+        description: "completion timestamps and archived todos",
+        // Completion time was never recorded; those todos keep a NULL `completed_at`.
+        sql: "ALTER TABLE todos ADD COLUMN completed_at TEXT;
+        ALTER TABLE todos ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;
+        CREATE INDEX idx_todos_completed_at ON todos(completed_at) WHERE completed = 1 AND archived = 0;",
+    },
+    Migration {
+        version: 14,
+        description: "password-protected notes",
+        sql: "ALTER TABLE notes ADD COLUMN locked INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE notes ADD COLUMN lock_params TEXT;
+        ALTER TABLE notes ADD COLUMN locked_content TEXT;",
+    },
+    Migration {
+        version: 15,
+        description: "indexes for filtered, paginated listings",
+        // Partial indexes cover the live rows every listing is restricted to,
+        // in the default order; the rest back the other sort and range fields.
+        sql: "CREATE INDEX idx_todos_live_created ON todos(created_at DESC, id) WHERE deleted_at IS NULL;
+        CREATE INDEX idx_todos_live_completed ON todos(completed, created_at DESC) WHERE deleted_at IS NULL;
+        CREATE INDEX idx_todos_title ON todos(title COLLATE NOCASE);
+        CREATE INDEX idx_todos_remind_time ON todos(remind_time);
+        CREATE INDEX idx_notes_live_updated ON notes(updated_at DESC, id) WHERE deleted_at IS NULL;
+        CREATE INDEX idx_notes_created_at ON notes(created_at);
+        CREATE INDEX idx_notes_title ON notes(title COLLATE NOCASE);",
+    },
+    Migration {
+        version: 16,
+        description: "last-modified time for todos",
+        sql: "ALTER TABLE todos ADD COLUMN updated_at TEXT;
+        UPDATE todos SET updated_at = COALESCE(completed_at, created_at);",
+    },
+    Migration {
+        version: 17,
+        description: "iCalendar UIDs of imported todos",
+        sql: "ALTER TABLE todos ADD COLUMN ical_uid TEXT;
+        CREATE UNIQUE INDEX idx_todos_ical_uid ON todos(ical_uid) WHERE ical_uid IS NOT NULL;",
+    },
+];
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+pub async fn current_version(pool: &SqlitePool) -> Result<i64, String> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at TEXT NOT NULL
+        )",
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Failed to create schema_version table: {}", e))?;
+
+    let version: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM schema_version")
+        .fetch_one(pool)
+        .await
+        .map_err(|e| format!("Failed to read schema version: {}", e))?;
+
+    Ok(version.unwrap_or(0))
+}
+
+/// Data rewrites that need Rust code, run inside the migration's transaction
+/// right after its SQL.
+async fn run_data_migration(
+    version: i64,
+    conn: &mut SqliteConnection,
+    app_dir: &Path,
+) -> Result<(), String> {
+    match version {
+        10 => crate::attachments::migrate_inline_images(conn, app_dir).await,
+        11 => crate::links::backfill_links(conn).await,
+        _ => Ok(()),
+    }
+}
+
+async fn has_user_tables(pool: &SqlitePool) -> Result<bool, String> {
+    sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master
+         WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name != 'schema_version')",
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| format!("Failed to inspect database: {}", e))
+}
+
+/// Brings the database up to `latest_version()`.
+///
+/// Refuses to touch a database written by a newer build, since applying an
+/// older schema on top of it could silently drop data.
+pub async fn run_migrations(pool: &SqlitePool, app_dir: &Path) -> Result<(), String> {
+    let current = current_version(pool).await?;
+    let latest = latest_version();
+
+    if current > latest {
+        return Err(format!(
+            "Database schema version {} is newer than this app supports ({}). Please update Silto.",
+            current, latest
+        ));
+    }
+
+    // A new database has nothing worth saving yet; one from before versioning
+    // (version 0 but with tables) does.
+    if current < latest && has_user_tables(pool).await? {
+        crate::backup::snapshot(pool, app_dir, crate::models::BackupKind::PreMigration).await?;
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        println!(
+            "Applying schema migration {}: {}",
+            migration.version, migration.description
+        );
+
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to begin migration {}: {}", migration.version, e))?;
+
+        // Through `Executor` the future is boxed as `Send` for any lifetime, which
+        // the commands that reopen the database need; `RawSql::execute` is not.
+        tx.execute(sqlx::raw_sql(migration.sql))
+            .await
+            .map_err(|e| format!("Failed to apply migration {}: {}", migration.version, e))?;
+        run_data_migration(migration.version, &mut tx, app_dir).await?;
+
+        sqlx::query(
+            "INSERT INTO schema_version (version, description, applied_at) VALUES (?, ?, ?)",
+        )
+        .bind(migration.version)
+        .bind(migration.description)
+        .bind(chrono::Utc::now())
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to record migration {}: {}", migration.version, e))?;
+
+        tx.commit()
+            .await
+            .map_err(|e| format!("Failed to commit migration {}: {}", migration.version, e))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,246 @@
+use crate::html;
+use crate::models::{ItemKind, SearchHit};
+use sqlx::{QueryBuilder, Sqlite, SqlitePool};
+
+// The trigram tokenizer cannot match terms shorter than this.
+const MIN_FTS_TERM_CHARS: usize = 3;
+const SNIPPET_CONTEXT_CHARS: usize = 24;
+// Marks highlights in raw snippets; swapped for `<mark>` tags once the text is escaped.
+const MARK_START: char = '\u{2}';
+const MARK_END: char = '\u{3}';
+
+/// Fills `search_text` for rows written without it (legacy rows, the JSON
+/// migration). The FTS triggers pick the update up.
+pub async fn backfill_search_text(pool: &SqlitePool) -> Result<(), String> {
+    for table in ["todos", "notes"] {
+        let rows: Vec<(String, String)> = sqlx::query_as(&format!(
+            "SELECT id, content FROM {} WHERE search_text IS NULL",
+            table
+        ))
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to load {} for indexing: {}", table, e))?;
+
+        for (id, content) in rows {
+            sqlx::query(&format!(
+                "UPDATE {} SET search_text = ? WHERE id = ?",
+                table
+            ))
+            .bind(html::to_plain_text(&content))
+            .bind(id)
+            .execute(pool)
+            .await
+            .map_err(|e| format!("Failed to index {}: {}", table, e))?;
+        }
+    }
+    Ok(())
+}
+
+fn terms(query: &str) -> Vec<String> {
+    query
+        .split_whitespace()
+        .map(|t| t.replace('"', ""))
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+/// Every term becomes a quoted phrase so user input can never be parsed as
+/// FTS5 query syntax; adjacent phrases are ANDed.
+fn fts_query(terms: &[String]) -> String {
+    terms
+        .iter()
+        .map(|t| format!("\"{}\"", t))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn like_pattern(term: &str) -> String {
+    let escaped = term
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// HTML-escapes a snippet and turns its sentinels into `<mark>` tags, so the
+/// indexed text can never inject markup into the results.
+fn highlight(snippet: &str) -> String {
+    html::escape_text(snippet)
+        .replace(MARK_START, "<mark>")
+        .replace(MARK_END, "</mark>")
+}
+
+/// Builds a `<mark>`-highlighted excerpt around the first occurrence of any term.
+fn excerpt(text: &str, terms: &[String]) -> String {
+    let text: String = text
+        .chars()
+        .filter(|&c| c != MARK_START && c != MARK_END)
+        .collect();
+    let lower = text.to_lowercase();
+    let chars: Vec<char> = text.chars().collect();
+    let lower_chars: Vec<char> = lower.chars().collect();
+    if chars.len() != lower_chars.len() {
+        // Case folding changed the length; fall back to the leading text.
+        let lead: String = chars.iter().take(SNIPPET_CONTEXT_CHARS * 2).collect();
+        return highlight(&lead);
+    }
+
+    let found = terms.iter().find_map(|term| {
+        let needle: Vec<char> = term.to_lowercase().chars().collect();
+        lower_chars
+            .windows(needle.len())
+            .position(|w| w == needle.as_slice())
+            .map(|pos| (pos, needle.len()))
+    });
+
+    let Some((pos, len)) = found else {
+        let lead: String = chars.iter().take(SNIPPET_CONTEXT_CHARS * 2).collect();
+        return highlight(&lead);
+    };
+
+    let from = pos.saturating_sub(SNIPPET_CONTEXT_CHARS);
+    let to = (pos + len + SNIPPET_CONTEXT_CHARS).min(chars.len());
+    let mut out = String::new();
+    if from > 0 {
+        out.push('…');
+    }
+    out.extend(&chars[from..pos]);
+    out.push(MARK_START);
+    out.extend(&chars[pos..pos + len]);
+    out.push(MARK_END);
+    out.extend(&chars[pos + len..to]);
+    if to < chars.len() {
+        out.push('…');
+    }
+    highlight(&out.replace('\n', " "))
+}
+
+/// Appends `AND ...` keeping rows of `table` (`todos` or `notes`) whose title
+/// or content contains every term of `text`.
+pub fn push_text_filter(builder: &mut QueryBuilder<'_, Sqlite>, table: &str, text: &str) {
+    let terms = terms(text);
+    if terms.is_empty() {
+        return;
+    }
+
+    if terms
+        .iter()
+        .all(|t| t.chars().count() >= MIN_FTS_TERM_CHARS)
+    {
+        builder.push(format!(
+            " AND rowid IN (SELECT rowid FROM {0}_fts WHERE {0}_fts MATCH ",
+            table
+        ));
+        builder.push_bind(fts_query(&terms));
+        builder.push(")");
+    } else {
+        for term in &terms {
+            let pattern = like_pattern(term);
+            builder.push(" AND (title LIKE ");
+            builder.push_bind(pattern.clone());
+            builder.push(" ESCAPE '\\' OR search_text LIKE ");
+            builder.push_bind(pattern);
+            builder.push(" ESCAPE '\\')");
+        }
+    }
+}
+
+async fn search_fts(
+    pool: &SqlitePool,
+    terms: &[String],
+    limit: i64,
+) -> Result<Vec<SearchHit>, String> {
+    let query = fts_query(terms);
+    let mut hits = sqlx::query_as::<_, SearchHit>(
+        "SELECT * FROM (
+            SELECT 'todo' AS kind, t.id AS id, t.title AS title,
+                   snippet(todos_fts, 1, char(2), char(3), '…', 16) AS snippet,
+                   bm25(todos_fts, 10.0, 1.0) AS rank
+            FROM todos_fts JOIN todos t ON t.rowid = todos_fts.rowid
+            WHERE todos_fts MATCH ? AND t.deleted_at IS NULL
+            UNION ALL
+            SELECT 'note' AS kind, n.id AS id, n.title AS title,
+                   snippet(notes_fts, 1, char(2), char(3), '…', 16) AS snippet,
+                   bm25(notes_fts, 10.0, 1.0) AS rank
+            FROM notes_fts JOIN notes n ON n.rowid = notes_fts.rowid
+            WHERE notes_fts MATCH ? AND n.deleted_at IS NULL
+        ) ORDER BY rank LIMIT ?",
+    )
+    .bind(&query)
+    .bind(&query)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to search: {}", e))?;
+    for hit in &mut hits {
+        hit.snippet = highlight(&hit.snippet);
+    }
+    Ok(hits)
+}
+
+/// Substring scan used when a term is too short for the trigram index.
+async fn search_like(
+    pool: &SqlitePool,
+    terms: &[String],
+    limit: i64,
+) -> Result<Vec<SearchHit>, String> {
+    let mut hits = Vec::new();
+
+    for (kind, table, order) in [
+        (ItemKind::Todo, "todos", "created_at"),
+        (ItemKind::Note, "notes", "updated_at"),
+    ] {
+        let conditions =
+            vec!["(title LIKE ? ESCAPE '\\' OR search_text LIKE ? ESCAPE '\\')"; terms.len()]
+                .join(" AND ");
+        let sql = format!(
+            "SELECT id, title, COALESCE(search_text, '') FROM {} WHERE deleted_at IS NULL AND {} ORDER BY {} DESC LIMIT ?",
+            table, conditions, order
+        );
+
+        let mut query = sqlx::query_as::<_, (String, String, String)>(&sql);
+        for term in terms {
+            let pattern = like_pattern(term);
+            query = query.bind(pattern.clone()).bind(pattern);
+        }
+        let rows = query
+            .bind(limit)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| format!("Failed to search {}: {}", table, e))?;
+
+        hits.extend(rows.into_iter().map(|(id, title, text)| {
+            // Title matches rank ahead of body-only matches.
+            let in_title = terms
+                .iter()
+                .any(|t| title.to_lowercase().contains(&t.to_lowercase()));
+            SearchHit {
+                kind: kind.clone(),
+                id,
+                snippet: excerpt(&text, terms),
+                title,
+                rank: if in_title { -1.0 } else { 0.0 },
+            }
+        }));
+    }
+
+    hits.sort_by(|a, b| a.rank.total_cmp(&b.rank));
+    hits.truncate(limit.max(0) as usize);
+    Ok(hits)
+}
+
+pub async fn search(pool: &SqlitePool, query: &str, limit: i64) -> Result<Vec<SearchHit>, String> {
+    let terms = terms(query);
+    if terms.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    if terms
+        .iter()
+        .all(|t| t.chars().count() >= MIN_FTS_TERM_CHARS)
+    {
+        search_fts(pool, &terms, limit).await
+    } else {
+        search_like(pool, &terms, limit).await
+    }
+}
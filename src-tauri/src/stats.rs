@@ -0,0 +1,68 @@
+use crate::models::{StatsBucket, StatsPeriod};
+use chrono::{DateTime, Utc};
+use sqlx::SqlitePool;
+
+/// SQL for the local date starting the period that `column` falls in. Uses
+/// the system timezone, like the reminders and recurrence; weeks start on Monday.
+fn bucket_expr(period: StatsPeriod, column: &str) -> String {
+    match period {
+        StatsPeriod::Day => format!("date({}, 'localtime')", column),
+        StatsPeriod::Week => format!("date({}, 'localtime', 'weekday 0', '-6 days')", column),
+        StatsPeriod::Month => format!("strftime('%Y-%m-01', {}, 'localtime')", column),
+    }
+}
+
+/// Todos created and completed per period between `from` and `to`, oldest
+/// first. Trashed todos are left out; archived ones count. Todos completed
+/// before completion times were recorded only count as created.
+pub async fn buckets(
+    pool: &SqlitePool,
+    period: StatsPeriod,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<StatsBucket>, String> {
+    // An all-day todo is late once its due day has ended.
+    let sql = format!(
+        "WITH events AS (
+            SELECT {} AS period_start, 1 AS created, 0 AS completed, NULL AS seconds, 0 AS late
+            FROM todos WHERE deleted_at IS NULL AND created_at >= ? AND created_at < ?
+            UNION ALL
+            SELECT {}, 0, 1, (julianday(completed_at) - julianday(created_at)) * 86400,
+                   due_at IS NOT NULL AND julianday(completed_at) > julianday(due_at) + due_all_day
+            FROM todos WHERE deleted_at IS NULL AND completed = 1 AND completed_at IS NOT NULL
+                AND completed_at >= ? AND completed_at < ?
+        )
+        SELECT period_start, SUM(created) AS created, SUM(completed) AS completed,
+               SUM(late) AS completed_late, AVG(seconds) AS avg_completion_seconds
+        FROM events GROUP BY period_start ORDER BY period_start",
+        bucket_expr(period, "created_at"),
+        bucket_expr(period, "completed_at"),
+    );
+    sqlx::query_as::<_, StatsBucket>(&sql)
+        .bind(from)
+        .bind(to)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to compute statistics: {}", e))
+}
+
+/// Mean time from creation to completion, in seconds, for todos completed
+/// between `from` and `to`.
+pub async fn avg_completion_seconds(
+    pool: &SqlitePool,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Option<f64>, String> {
+    sqlx::query_scalar(
+        "SELECT AVG((julianday(completed_at) - julianday(created_at)) * 86400) FROM todos
+         WHERE deleted_at IS NULL AND completed = 1 AND completed_at IS NOT NULL
+           AND completed_at >= ? AND completed_at < ?",
+    )
+    .bind(from)
+    .bind(to)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| format!("Failed to compute completion time: {}", e))
+}
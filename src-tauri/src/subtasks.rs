@@ -0,0 +1,155 @@
+use crate::models::Todo;
+use sqlx::{QueryBuilder, Sqlite, SqlitePool};
+use std::collections::HashMap;
+
+/// Recursive CTE yielding the id bound to it plus every descendant's id as `subtree(id)`.
+pub const SUBTREE_CTE: &str = "WITH RECURSIVE subtree(id) AS (
+    SELECT ? UNION SELECT t.id FROM todos t JOIN subtree s ON t.parent_id = s.id
+)";
+
+/// Like `SUBTREE_CTE` but as `trashed(id)`, and the walk stays inside the trash:
+/// a subtask restored on its own is left out together with its descendants.
+pub const TRASHED_SUBTREE_CTE: &str = "WITH RECURSIVE trashed(id) AS (
+    SELECT id FROM todos WHERE id = ? AND deleted_at IS NOT NULL
+    UNION SELECT t.id FROM todos t JOIN trashed s ON t.parent_id = s.id
+    WHERE t.deleted_at IS NOT NULL
+)";
+
+/// Rejects a `parent_id` that is missing or would make `id` its own ancestor.
+pub async fn ensure_valid_parent(
+    pool: &SqlitePool,
+    id: &str,
+    parent_id: &str,
+) -> Result<(), String> {
+    if id == parent_id {
+        return Err("A todo cannot be its own subtask".to_string());
+    }
+
+    let parent_exists: Option<String> = sqlx::query_scalar("SELECT id FROM todos WHERE id = ?")
+        .bind(parent_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| format!("Failed to fetch parent todo: {}", e))?;
+    if parent_exists.is_none() {
+        return Err("Parent todo not found".to_string());
+    }
+
+    // UNION (not UNION ALL) stops the walk even if the table already holds a cycle.
+    let is_ancestor: bool = sqlx::query_scalar(
+        "WITH RECURSIVE ancestors(id) AS (
+            SELECT ? UNION SELECT t.parent_id FROM todos t JOIN ancestors a ON t.id = a.id WHERE t.parent_id IS NOT NULL
+        )
+        SELECT EXISTS (SELECT 1 FROM ancestors WHERE id = ?)",
+    )
+    .bind(parent_id)
+    .bind(id)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| format!("Failed to check subtask hierarchy: {}", e))?;
+
+    if is_ancestor {
+        return Err("A todo cannot be moved under one of its own subtasks".to_string());
+    }
+    Ok(())
+}
+
+fn progress_of(
+    id: &str,
+    children: &HashMap<String, Vec<(String, bool)>>,
+    memo: &mut HashMap<String, f64>,
+) -> Option<f64> {
+    if let Some(progress) = memo.get(id) {
+        return Some(*progress);
+    }
+    let kids = children.get(id)?;
+
+    // Guard against cycles written before validation existed.
+    memo.insert(id.to_string(), 0.0);
+    let total: f64 = kids
+        .iter()
+        .map(|(child_id, completed)| {
+            if *completed {
+                1.0
+            } else {
+                progress_of(child_id, children, memo).unwrap_or(0.0)
+            }
+        })
+        .sum();
+    let progress = total / kids.len() as f64;
+    memo.insert(id.to_string(), progress);
+    Some(progress)
+}
+
+/// Completion ratio (0.0..=1.0) for each of `ids` that has live subtasks. A
+/// completed subtask counts fully; an open one counts by its own progress,
+/// so the live descendants of `ids` are read, and nothing else.
+pub async fn load_progress(
+    pool: &SqlitePool,
+    ids: &[String],
+) -> Result<HashMap<String, f64>, String> {
+    if ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let mut builder = QueryBuilder::<Sqlite>::new(
+        "WITH RECURSIVE descendants(id, parent_id, completed) AS (
+            SELECT id, parent_id, completed FROM todos WHERE deleted_at IS NULL AND parent_id IN (",
+    );
+    let mut separated = builder.separated(", ");
+    for id in ids {
+        separated.push_bind(id.clone());
+    }
+    builder.push(
+        ") UNION SELECT t.id, t.parent_id, t.completed FROM todos t
+            JOIN descendants d ON t.parent_id = d.id WHERE t.deleted_at IS NULL
+        )
+        SELECT id, parent_id, completed FROM descendants",
+    );
+    let rows: Vec<(String, String, bool)> = builder
+        .build_query_as()
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to fetch subtasks: {}", e))?;
+
+    let mut children: HashMap<String, Vec<(String, bool)>> = HashMap::new();
+    for (id, parent_id, completed) in rows {
+        children.entry(parent_id).or_default().push((id, completed));
+    }
+
+    let mut memo = HashMap::new();
+    for id in ids {
+        progress_of(id, &children, &mut memo);
+    }
+    Ok(memo)
+}
+
+/// Nests `todos` under their parents, keeping the incoming order at every
+/// level. Todos whose parent is not in the list become roots.
+pub fn build_tree(todos: Vec<Todo>) -> Vec<Todo> {
+    let ids: std::collections::HashSet<String> = todos.iter().map(|t| t.id.clone()).collect();
+    let mut children: HashMap<String, Vec<Todo>> = HashMap::new();
+    let mut roots = Vec::new();
+
+    for todo in todos {
+        match todo.parent_id.clone() {
+            Some(parent_id) if ids.contains(&parent_id) => {
+                children.entry(parent_id).or_default().push(todo)
+            }
+            _ => roots.push(todo),
+        }
+    }
+
+    fn attach(todo: &mut Todo, children: &mut HashMap<String, Vec<Todo>>) {
+        if let Some(mut kids) = children.remove(&todo.id) {
+            for kid in &mut kids {
+                attach(kid, children);
+            }
+            todo.children = kids;
+        }
+    }
+
+    for root in &mut roots {
+        attach(root, &mut children);
+    }
+    roots
+}
@@ -0,0 +1,72 @@
+use crate::models::TagMatch;
+use sqlx::{QueryBuilder, Sqlite, SqlitePool};
+use std::collections::HashMap;
+
+/// Join table and item column for each taggable table.
+pub const TODO_TAGS: (&str, &str) = ("todo_tags", "todo_id");
+pub const NOTE_TAGS: (&str, &str) = ("note_tags", "note_id");
+/// Ids bound per query, well below SQLite's limit on bound variables.
+const IDS_PER_QUERY: usize = 500;
+
+/// Appends `AND id IN (...)` restricting the outer query to items tagged with
+/// `tag_ids` under `mode`. Does nothing when `tag_ids` is empty.
+pub fn push_tag_filter(
+    builder: &mut QueryBuilder<'_, Sqlite>,
+    (join_table, item_column): (&str, &str),
+    tag_ids: &[String],
+    mode: TagMatch,
+) {
+    if tag_ids.is_empty() {
+        return;
+    }
+
+    builder.push(format!(
+        " AND id IN (SELECT {} FROM {} WHERE tag_id IN (",
+        item_column, join_table
+    ));
+    let mut separated = builder.separated(", ");
+    for tag_id in tag_ids {
+        separated.push_bind(tag_id.clone());
+    }
+    builder.push(")");
+
+    if mode == TagMatch::All {
+        builder.push(format!(
+            " GROUP BY {} HAVING COUNT(DISTINCT tag_id) = ",
+            item_column
+        ));
+        builder.push_bind(tag_ids.len() as i64);
+    }
+    builder.push(")");
+}
+
+/// Maps each of `item_ids` to the ids of its tags.
+pub async fn load_tag_ids(
+    pool: &SqlitePool,
+    (join_table, item_column): (&str, &str),
+    item_ids: &[String],
+) -> Result<HashMap<String, Vec<String>>, String> {
+    let mut map: HashMap<String, Vec<String>> = HashMap::new();
+    for chunk in item_ids.chunks(IDS_PER_QUERY) {
+        let mut builder = QueryBuilder::<Sqlite>::new(format!(
+            "SELECT {0}, tag_id FROM {1} WHERE {0} IN (",
+            item_column, join_table
+        ));
+        let mut separated = builder.separated(", ");
+        for id in chunk {
+            separated.push_bind(id.clone());
+        }
+        builder.push(")");
+
+        let rows: Vec<(String, String)> = builder
+            .build_query_as()
+            .fetch_all(pool)
+            .await
+            .map_err(|e| format!("Failed to fetch tags: {}", e))?;
+
+        for (item_id, tag_id) in rows {
+            map.entry(item_id).or_default().push(tag_id);
+        }
+    }
+    Ok(map)
+}
@@ -6,7 +6,7 @@ use tauri::{
     image::Image,
     menu::{Menu, MenuItem, PredefinedMenuItem},
     tray::{TrayIcon, TrayIconBuilder, TrayIconEvent},
-    AppHandle, Emitter, Manager, PhysicalPosition,
+    AppHandle, Emitter, Manager,
 };
 use tauri_plugin_store::StoreExt;
 
@@ -17,14 +17,13 @@ use cocoa::base::id;
 use cocoa::foundation::NSRect;
 #[cfg(target_os = "macos")]
 use objc::{class, msg_send, sel, sel_impl};
+#[cfg(target_os = "macos")]
+use tauri::PhysicalPosition;
 
 pub fn create_tray(app: &AppHandle) -> Result<TrayIcon, tauri::Error> {
     // 获取当前语言设置
     let store = app.store("store.json").map_err(|e| {
-        tauri::Error::Io(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            e.to_string(),
-        ))
+        tauri::Error::Io(std::io::Error::other(e.to_string()))
     })?;
     let settings_value = store.get("settings");
 
@@ -66,7 +65,7 @@ pub fn create_tray(app: &AppHandle) -> Result<TrayIcon, tauri::Error> {
     // 加载图标
     let icon_bytes = include_bytes!("../icons/icon-menubar.png");
     let icon_image = image::load_from_memory(icon_bytes)
-        .map_err(|e| tauri::Error::Io(std::io::Error::new(std::io::ErrorKind::Other, e)))?
+        .map_err(|e| tauri::Error::Io(std::io::Error::other(e)))?
         .to_rgba8();
     let (width, height) = icon_image.dimensions();
     let icon = Image::new(icon_image.as_raw(), width, height);
@@ -106,15 +105,12 @@ pub fn create_tray(app: &AppHandle) -> Result<TrayIcon, tauri::Error> {
                 ..
             } = event
             {
-                match button {
-                    tauri::tray::MouseButton::Left => {
-                        // 只在鼠标松开时处理，避免按下和松开都触发
-                        if button_state == tauri::tray::MouseButtonState::Up {
-                            let app = tray.app_handle();
-                            show_popup_window(app, position);
-                        }
-                    }
-                    _ => {}
+                // 只在左键松开时处理，避免按下和松开都触发
+                if button == tauri::tray::MouseButton::Left
+                    && button_state == tauri::tray::MouseButtonState::Up
+                {
+                    let app = tray.app_handle();
+                    show_popup_window(app, position);
                 }
             }
         })
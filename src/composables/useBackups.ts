@@ -0,0 +1,32 @@
+import { ref } from 'vue'
+import { invoke } from '@tauri-apps/api/core'
+import type { BackupInfo } from '../types'
+
+const backups = ref<BackupInfo[]>([])
+
+export function useBackups() {
+    const loadBackups = async () => {
+        try {
+            backups.value = await invoke<BackupInfo[]>('list_backups')
+        } catch (e) {
+            console.error('Failed to load backups:', e)
+        }
+    }
+
+    // The backend emits 'refresh-data' once the restored database is open
+    const restoreBackup = async (name: string, passphrase?: string) => {
+        try {
+            await invoke('restore_backup', { name, passphrase })
+            await loadBackups()
+        } catch (e) {
+            console.error('Failed to restore backup:', e)
+            throw e
+        }
+    }
+
+    return {
+        backups,
+        loadBackups,
+        restoreBackup,
+    }
+}
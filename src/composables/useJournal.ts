@@ -0,0 +1,48 @@
+import { ref } from 'vue'
+import { invoke } from '@tauri-apps/api/core'
+import type { JournalStatus } from '../types'
+
+const status = ref<JournalStatus>({ undo: null, redo: null })
+
+// The backend emits 'refresh-data' to every window once a change is undone or redone
+export function useJournal() {
+    const loadStatus = async () => {
+        try {
+            status.value = await invoke<JournalStatus>('get_journal_status')
+        } catch (e) {
+            console.error('Failed to load journal status:', e)
+        }
+    }
+
+    const undo = async () => {
+        try {
+            const label = await invoke<string | null>('undo')
+            await loadStatus()
+            return label
+        } catch (e) {
+            console.error('Failed to undo:', e)
+            // The failed change has been dropped from the history
+            await loadStatus()
+            throw e
+        }
+    }
+
+    const redo = async () => {
+        try {
+            const label = await invoke<string | null>('redo')
+            await loadStatus()
+            return label
+        } catch (e) {
+            console.error('Failed to redo:', e)
+            await loadStatus()
+            throw e
+        }
+    }
+
+    return {
+        status,
+        loadStatus,
+        undo,
+        redo,
+    }
+}
@@ -1,33 +1,89 @@
 import { ref, computed } from 'vue'
 import { invoke } from '@tauri-apps/api/core'
-import type { Note } from '../types'
+import type { Note, NoteSort, Page, SaveError } from '../types'
+import { resolveAttachments, serializeSaves } from '../lib/utils'
+
+const PAGE_SIZE = 100
+const SORT: NoteSort = { field: 'created_at', direction: 'desc' }
 
 const notes = ref<Note[]>([])
+// Cursor of the next page, null once every note is shown
+const cursor = ref<string | null>(null)
+const query = ref('')
 const loading = ref(false)
 const error = ref<string | null>(null)
+// Bumped by every reload so a page requested before it is dropped
+let generation = 0
+// Shared by every caller, so autosaves from the editor queue behind each other
+const persistNote = serializeSaves((note: Note, rewriteLinks: boolean) =>
+    invoke<Note>('save_note', { note, rewriteLinks }))
+
+const fetchNotes = async (after: string | null, limit = PAGE_SIZE) => {
+    const page = await invoke<Page<Note>>('get_notes', {
+        filter: { sort: SORT, text: query.value.trim() || null, cursor: after, limit },
+    })
+    return { ...page, items: page.items.map(note => ({ ...note, content: resolveAttachments(note.content) })) }
+}
 
 export function useNotes() {
+    // Reloads as many notes as are already shown, so a refresh keeps the scroll position
     const loadNotes = async () => {
+        const current = ++generation
         loading.value = true
         error.value = null
         try {
-            const result = await invoke<Note[]>('get_notes')
-            notes.value = result
+            const page = await fetchNotes(null, Math.max(PAGE_SIZE, notes.value.length))
+            if (current !== generation) return
+            notes.value = page.items
+            cursor.value = page.next_cursor
         } catch (e) {
             error.value = e as string
             console.error('Failed to load notes:', e)
         } finally {
-            loading.value = false
+            if (current === generation) loading.value = false
         }
     }
 
-    const saveNote = async (note: Note) => {
+    const hasMore = computed(() => !!cursor.value)
+
+    const loadMore = async () => {
+        if (loading.value || !cursor.value) return
+        const current = generation
+        loading.value = true
+        try {
+            const page = await fetchNotes(cursor.value)
+            if (current !== generation) return
+            notes.value = [...notes.value, ...page.items]
+            cursor.value = page.next_cursor
+        } catch (e) {
+            console.error('Failed to load more notes:', e)
+        } finally {
+            if (current === generation) loading.value = false
+        }
+    }
+
+    // Filters on the backend; starts again from the first page
+    const search = async (text: string) => {
+        query.value = text
+        notes.value = []
+        await loadNotes()
+    }
+
+    const saveNote = async (note: Note, rewriteLinks = false) => {
         error.value = null
         try {
-            await invoke('save_note', { note })
+            const saved = await persistNote(note, rewriteLinks)
             await loadNotes()
+            return { ...saved, content: resolveAttachments(saved.content) }
         } catch (e) {
-            error.value = e as string
+            const err = e as SaveError<Note>
+            if (err.kind === 'conflict') {
+                // Changed in another window; show the stored copy
+                await loadNotes()
+                error.value = 'conflict'
+            } else {
+                error.value = err.message
+            }
             console.error('Failed to save note:', e)
             throw e
         }
@@ -45,18 +101,14 @@ export function useNotes() {
         }
     }
 
-    const sortedNotes = computed(() =>
-        [...notes.value].sort((a, b) =>
-            new Date(b.created_at).getTime() - new Date(a.created_at).getTime()
-        )
-    )
-
     return {
         notes,
         loading,
         error,
-        sortedNotes,
+        hasMore,
         loadNotes,
+        loadMore,
+        search,
         saveNote,
         deleteNote,
     }
@@ -7,6 +7,10 @@ const settings = ref<Settings>({
     left_click_action: 'todo',
     theme: 'system',
     language: 'zh-CN',
+    trash_retention_days: 30,
+    auto_archive_days: 0,
+    backup_daily_keep: 7,
+    backup_weekly_keep: 4,
 })
 
 export function useSettings() {
@@ -1,33 +1,115 @@
 import { ref, computed } from 'vue'
 import { invoke } from '@tauri-apps/api/core'
-import type { Todo } from '../types'
+import { serializeSaves } from '../lib/utils'
+import type { Todo, TodoSort, Page, SaveError, BatchOp, BatchResult, Statistics, StatsPeriod } from '../types'
 
-const todos = ref<Todo[]>([])
+const PAGE_SIZE = 100
+// The order the list shows: reminders first (nearest first), then newest first
+const ACTIVE_SORT: TodoSort = { field: 'remind_time', direction: 'asc' }
+const COMPLETED_SORT: TodoSort = { field: 'created_at', direction: 'desc' }
+
+const activeTodos = ref<Todo[]>([])
+const completedTodos = ref<Todo[]>([])
+// Cursors of the next pages; completed todos are only paged in once every open one is shown
+const activeCursor = ref<string | null>(null)
+const completedCursor = ref<string | null>(null)
+const query = ref('')
 const loading = ref(false)
 const error = ref<string | null>(null)
+// Bumped by every reload so a page requested before it is dropped
+let generation = 0
+// Shared by every caller, so autosaves from the editor queue behind each other
+const persistTodo = serializeSaves((todo: Todo) => invoke<Todo>('save_todo', { todo }))
+
+const fetchTodos = (completed: boolean, cursor: string | null, limit = PAGE_SIZE) =>
+    invoke<Page<Todo>>('get_todos', {
+        filter: {
+            completed,
+            sort: completed ? COMPLETED_SORT : ACTIVE_SORT,
+            text: query.value.trim() || null,
+            cursor,
+            limit,
+        },
+    })
 
 export function useTodos() {
+    // Reloads as many rows as are already shown, so a refresh keeps the scroll position
     const loadTodos = async () => {
+        const current = ++generation
         loading.value = true
         error.value = null
         try {
-            const result = await invoke<Todo[]>('get_todos')
-            todos.value = result
+            const active = await fetchTodos(false, null, Math.max(PAGE_SIZE, activeTodos.value.length))
+            const completed = active.next_cursor
+                ? null
+                : await fetchTodos(true, null, Math.max(PAGE_SIZE, completedTodos.value.length))
+            if (current !== generation) return
+            activeTodos.value = active.items
+            activeCursor.value = active.next_cursor
+            completedTodos.value = completed?.items ?? []
+            completedCursor.value = completed?.next_cursor ?? null
         } catch (e) {
             error.value = e as string
             console.error('Failed to load todos:', e)
         } finally {
-            loading.value = false
+            if (current === generation) loading.value = false
         }
     }
 
+    const hasMore = computed(() => !!(activeCursor.value || completedCursor.value))
+
+    // Appends the next page: open todos first, then the completed ones
+    const loadMore = async () => {
+        if (loading.value || !hasMore.value) return
+        const current = generation
+        loading.value = true
+        try {
+            if (activeCursor.value) {
+                const active = await fetchTodos(false, activeCursor.value)
+                const completed = active.next_cursor ? null : await fetchTodos(true, null)
+                if (current !== generation) return
+                activeTodos.value = [...activeTodos.value, ...active.items]
+                activeCursor.value = active.next_cursor
+                if (completed) {
+                    completedTodos.value = completed.items
+                    completedCursor.value = completed.next_cursor
+                }
+            } else if (completedCursor.value) {
+                const completed = await fetchTodos(true, completedCursor.value)
+                if (current !== generation) return
+                completedTodos.value = [...completedTodos.value, ...completed.items]
+                completedCursor.value = completed.next_cursor
+            }
+        } catch (e) {
+            console.error('Failed to load more todos:', e)
+        } finally {
+            if (current === generation) loading.value = false
+        }
+    }
+
+    // Filters on the backend; starts again from the first page
+    const search = async (text: string) => {
+        query.value = text
+        activeTodos.value = []
+        completedTodos.value = []
+        await loadTodos()
+    }
+
     const saveTodo = async (todo: Todo) => {
         error.value = null
         try {
-            await invoke('save_todo', { todo })
+            const saved = await persistTodo(todo)
             await loadTodos()
+            return saved
         } catch (e) {
-            error.value = e as string
+            const err = e as SaveError<Todo>
+            if (err.kind === 'conflict') {
+                // Changed in another window; show the stored copy
+                await loadTodos()
+                error.value = 'conflict'
+            } else {
+                error.value = err.message
+            }
             console.error('Failed to save todo:', e)
             throw e
         }
@@ -45,40 +127,46 @@ export function useTodos() {
         }
     }
 
+    // All or nothing: if one operation fails, none are applied
+    const applyBatch = async (ops: BatchOp[]) => {
+        error.value = null
+        try {
+            const result = await invoke<BatchResult>('apply_todo_batch', { ops })
+            await loadTodos()
+            return result
+        } catch (e) {
+            error.value = e as string
+            console.error('Failed to apply batch:', e)
+            throw e
+        }
+    }
+
+    const getStatistics = async (period: StatsPeriod, from?: string, to?: string) => {
+        try {
+            return await invoke<Statistics>('get_statistics', { period, from, to })
+        } catch (e) {
+            console.error('Failed to load statistics:', e)
+            throw e
+        }
+    }
+
     const toggleTodo = async (todo: Todo) => {
         const updated = { ...todo, completed: !todo.completed }
         await saveTodo(updated)
     }
 
-    const activeTodos = computed(() =>
-        todos.value.filter(t => !t.completed).sort((a, b) => {
-            // Check if items have reminders
-            if (a.remind_time && b.remind_time) {
-                // Both have reminders: sort by reminder time ascending (nearer first)
-                return new Date(a.remind_time).getTime() - new Date(b.remind_time).getTime()
-            }
-            if (a.remind_time) return -1 // a has reminder, comes first
-            if (b.remind_time) return 1  // b has reminder, comes first
-            
-            // Neither has reminder: sort by creation time descending (newer first)
-            return new Date(b.created_at).getTime() - new Date(a.created_at).getTime()
-        })
-    )
-
-    const completedTodos = computed(() =>
-        todos.value.filter(t => t.completed).sort((a, b) =>
-            new Date(b.created_at).getTime() - new Date(a.created_at).getTime()
-        )
-    )
-
     return {
-        todos,
         loading,
         error,
         activeTodos,
         completedTodos,
+        hasMore,
         loadTodos,
+        loadMore,
+        search,
         saveTodo,
+        applyBatch,
+        getStatistics,
         deleteTodo,
         toggleTodo,
     }
@@ -0,0 +1,51 @@
+import { invoke } from '@tauri-apps/api/core'
+import type { ExportFormat, ExportReport, ImportReport, ImportStrategy } from '../types'
+
+export function useTransfer() {
+    // Resolves to null when the user cancels the folder picker
+    const exportData = async (formats?: ExportFormat[]) => {
+        try {
+            return await invoke<ExportReport | null>('export_data', { formats })
+        } catch (e) {
+            console.error('Failed to export data:', e)
+            throw e
+        }
+    }
+
+    // The backend emits 'refresh-data' after a successful import
+    const importData = async (strategy?: ImportStrategy) => {
+        try {
+            return await invoke<ImportReport | null>('import_data', { strategy })
+        } catch (e) {
+            console.error('Failed to import data:', e)
+            throw e
+        }
+    }
+
+    // Exports every live todo when no ids are given; resolves to the file path,
+    // or null when the user cancels the save dialog
+    const exportIcs = async (ids?: string[]) => {
+        try {
+            return await invoke<string | null>('export_ics', { ids })
+        } catch (e) {
+            console.error('Failed to export calendar:', e)
+            throw e
+        }
+    }
+
+    const importIcs = async () => {
+        try {
+            return await invoke<ImportReport | null>('import_ics')
+        } catch (e) {
+            console.error('Failed to import calendar:', e)
+            throw e
+        }
+    }
+
+    return {
+        exportData,
+        importData,
+        exportIcs,
+        importIcs,
+    }
+}
@@ -1,5 +1,6 @@
 import { type ClassValue, clsx } from 'clsx'
 import { twMerge } from 'tailwind-merge'
+import { convertFileSrc } from '@tauri-apps/api/core'
 
 export function cn(...inputs: ClassValue[]) {
     return twMerge(clsx(inputs))
@@ -11,3 +12,41 @@ export function stripHtml(html: string): string {
     tmp.innerHTML = html
     return tmp.textContent || tmp.innerText || ''
 }
+
+// Stored notes point at attachments as `attachment:<sha256>`; the backend turns
+// the URLs back into references when the note is saved.
+const ATTACHMENT_REFERENCE = /attachment:([0-9a-f]{64})/g
+
+export function resolveAttachments(html: string): string {
+    if (!html) return html
+    return html.replace(ATTACHMENT_REFERENCE, (_, hash: string) => convertFileSrc(hash, 'silto-attachment'))
+}
+
+// Runs saves one at a time. A save still based on a version that an earlier
+// save from this window replaced is moved onto the version that save produced,
+// so back-to-back autosaves never conflict with each other.
+export function serializeSaves<T extends { id: string; updated_at?: string }, A extends unknown[]>(
+    save: (item: T, ...args: A) => Promise<T>,
+): (item: T, ...args: A) => Promise<T> {
+    let tail: Promise<unknown> = Promise.resolve()
+    const versions = new Map<string, { replaced: Set<string>; latest: string }>()
+
+    const run = async (item: T, args: A) => {
+        const known = versions.get(item.id)
+        const base = item.updated_at ?? ''
+        const rebased = known?.replaced.has(base) ? { ...item, updated_at: known.latest } : item
+        const saved = await save(rebased, ...args)
+        const entry = known ?? { replaced: new Set<string>(), latest: '' }
+        entry.replaced.add(base)
+        entry.replaced.add(rebased.updated_at ?? '')
+        entry.latest = saved.updated_at ?? ''
+        versions.set(item.id, entry)
+        return saved
+    }
+
+    return (item: T, ...args: A) => {
+        const result = tail.then(() => run(item, args))
+        tail = result.catch(() => undefined)
+        return result
+    }
+}
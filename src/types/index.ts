@@ -1,3 +1,5 @@
+export type Priority = 'none' | 'low' | 'medium' | 'high'
+
 export interface Todo {
     id: string
     title: string
@@ -5,6 +7,25 @@ export interface Todo {
     remind_time: string | null
     completed: boolean
     created_at: string
+    updated_at?: string
+    list_id?: string | null
+    parent_id?: string | null
+    due_at?: string | null
+    due_all_day?: boolean
+    priority?: Priority
+    remind_before_due_minutes?: number | null
+    recurrence?: string | null
+    series_id?: string | null
+    recurrence_index?: number
+    occurrence_at?: string | null
+    handed_over?: boolean
+    completed_at?: string | null
+    archived?: boolean
+    deleted_at?: string | null
+    ical_uid?: string | null
+    tag_ids?: string[]
+    progress?: number | null
+    children?: Todo[]
 }
 
 export interface Note {
@@ -13,6 +34,210 @@ export interface Note {
     content: string
     created_at: string
     updated_at: string
+    locked?: boolean
+    deleted_at?: string | null
+    tag_ids?: string[]
+}
+
+export const INBOX_LIST_ID = 'inbox'
+
+export interface TodoList {
+    id: string
+    name: string
+    color: string | null
+    sort_order: number
+    archived: boolean
+    created_at: string
+}
+
+export type ListDeleteMode = 'move_to_inbox' | 'trash'
+
+export interface Tag {
+    id: string
+    name: string
+    created_at: string
+}
+
+export type TagMatch = 'all' | 'any'
+
+export interface TodoFilter {
+    tag_ids?: string[]
+    tag_match?: TagMatch
+    list_id?: string | null
+    tree?: boolean
+    overdue?: boolean
+    min_priority?: Priority | null
+    sort?: TodoSort | null
+    include_archived?: boolean
+    completed?: boolean | null
+    text?: string | null
+    date_field?: TodoDateField
+    from?: string | null
+    to?: string | null
+    cursor?: string | null
+    limit?: number | null
+}
+
+export type TodoDateField = 'created_at' | 'due_at' | 'remind_time' | 'completed_at'
+
+export type TodoSortField = 'created_at' | 'due_at' | 'priority' | 'remind_time' | 'title'
+export type SortDirection = 'asc' | 'desc'
+
+export interface TodoSort {
+    field: TodoSortField
+    direction?: SortDirection
+}
+
+export interface NoteFilter {
+    tag_ids?: string[]
+    tag_match?: TagMatch
+    sort?: NoteSort | null
+    text?: string | null
+    date_field?: NoteDateField
+    from?: string | null
+    to?: string | null
+    cursor?: string | null
+    limit?: number | null
+}
+
+export type NoteSortField = 'created_at' | 'updated_at' | 'title'
+export type NoteDateField = 'created_at' | 'updated_at'
+
+export interface NoteSort {
+    field: NoteSortField
+    direction?: SortDirection
+}
+
+export interface Page<T> {
+    items: T[]
+    total: number
+    next_cursor: string | null
+}
+
+// Error from save_todo / save_note; a conflict carries the stored copy.
+export type SaveError<T> =
+    | { kind: 'conflict'; current: T }
+    | { kind: 'failed'; message: string }
+
+export type BatchOp =
+    | { op: 'complete'; id: string }
+    | { op: 'uncomplete'; id: string }
+    | { op: 'delete'; id: string }
+    | { op: 'move'; id: string; list_id: string | null }
+    | { op: 'tag'; id: string; tag_id: string }
+    | { op: 'untag'; id: string; tag_id: string }
+
+export type BatchStatus = 'applied' | 'failed' | 'rolled_back'
+
+export interface BatchItemResult {
+    id: string
+    status: BatchStatus
+    error: string | null
+}
+
+export interface BatchResult {
+    committed: boolean
+    items: BatchItemResult[]
+}
+
+export type StatsPeriod = 'day' | 'week' | 'month'
+
+export interface StatsBucket {
+    period_start: string
+    created: number
+    completed: number
+    completed_late: number
+    avg_completion_seconds: number | null
+}
+
+export interface Statistics {
+    buckets: StatsBucket[]
+    avg_completion_seconds: number | null
+    overdue: number
+}
+
+export interface Trash {
+    todos: Todo[]
+    notes: Note[]
+}
+
+export type ExportFormat = 'json' | 'markdown' | 'csv'
+
+export interface TodoNoteLink {
+    todo_id: string
+    note_id: string
+    created_at: string
+}
+
+export interface NoteLock {
+    note_id: string
+    lock_params: string
+    locked_content: string
+}
+
+export interface ExportedAttachment {
+    hash: string
+    mime: string
+    data_base64: string
+}
+
+export interface ExportData {
+    format_version: number
+    exported_at: string
+    lists: TodoList[]
+    tags: Tag[]
+    todos: Todo[]
+    notes: Note[]
+    todo_notes: TodoNoteLink[]
+    note_locks: NoteLock[]
+    note_revisions: NoteRevision[]
+    attachments: ExportedAttachment[]
+}
+
+export type ImportStrategy = 'skip' | 'overwrite_if_newer' | 'duplicate'
+
+export interface ImportReport {
+    inserted: number
+    updated: number
+    skipped: number
+}
+
+export interface ExportReport {
+    dir: string
+    files: string[]
+    todos: number
+    notes: number
+}
+
+export type BackupKind = 'daily' | 'weekly' | 'pre_migration' | 'pre_restore'
+
+export interface BackupInfo {
+    name: string
+    kind: BackupKind
+    created_at: string
+    size: number
+}
+
+export interface RecoveredTable {
+    table: string
+    rows: number
+    complete: boolean
+}
+
+export interface RecoveryReport {
+    damaged_path: string
+    tables: RecoveredTable[]
+    problems: string[]
+}
+
+export interface JournalStatus {
+    undo: string | null
+    redo: string | null
+}
+
+export interface EncryptionStatus {
+    encrypted: boolean
+    locked: boolean
 }
 
 export type LeftClickAction = 'todo' | 'note'
@@ -22,4 +247,53 @@ export interface Settings {
     left_click_action: LeftClickAction
     theme: Theme
     language: string
+    trash_retention_days: number
+    auto_archive_days: number
+    backup_daily_keep: number
+    backup_weekly_keep: number
+}
+
+export type ItemKind = 'todo' | 'note'
+
+export interface SearchHit {
+    kind: ItemKind
+    id: string
+    title: string
+    snippet: string
+    rank: number
+}
+
+export interface NoteRevision {
+    id: number
+    note_id: string
+    title: string
+    content: string
+    created_at: string
+    saved_at: string
+}
+
+export type DiffOp = 'equal' | 'insert' | 'delete'
+
+export interface DiffLine {
+    op: DiffOp
+    text: string
+}
+
+export interface UnresolvedLink {
+    source_id: string
+    source_title: string
+    target_title: string
+}
+
+export interface Attachment {
+    hash: string
+    mime: string
+    size: number
+    created_at: string
+    url: string
+}
+
+export interface AttachmentData {
+    mime: string
+    data_base64: string
 }